@@ -0,0 +1,96 @@
+#ifndef BPF_LINKER_H
+#define BPF_LINKER_H
+
+/* Generated with cbindgen from capi/src/lib.rs, don't edit it by hand. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// Output an ELF object file.
+#define BPF_LINKER_OUTPUT_OBJECT 0
+
+// Output assembly.
+#define BPF_LINKER_OUTPUT_ASSEMBLY 1
+
+// Output LLVM bitcode.
+#define BPF_LINKER_OUTPUT_BITCODE 2
+
+// Output LLVM IR.
+#define BPF_LINKER_OUTPUT_LLVM_IR 3
+
+// A linker and the inputs added to it.
+struct bpf_linker;
+
+// Creates a linker.
+//
+// `target` is the LLVM target to generate code for, or NULL to use the one of the inputs. `cpu`
+// is one of the cpus of the `--cpu` option of bpf-linker, or NULL for `generic`. `opt_level` is
+// `"0"` to `"3"`, `"s"` or `"z"`, or NULL for `"2"`. BTF is emitted if `btf` is true.
+//
+// Returns NULL if an argument is invalid. The linker must be freed with [`bpf_linker_free`].
+//
+// # Safety
+//
+// The strings must be NULL or NUL-terminated.
+struct bpf_linker *bpf_linker_new(const char *target,
+                                  const char *cpu,
+                                  const char *opt_level,
+                                  bool btf);
+
+// Adds an input to link: the `len` bytes at `data`, named `name` in messages, or, if `data` is
+// NULL, the file at the path `name`. The bytes are copied, so they can be freed once this
+// returns.
+//
+// # Safety
+//
+// `linker` must have been returned by [`bpf_linker_new`], `name` must be NUL-terminated and
+// `data` must be NULL or point to `len` bytes.
+int bpf_linker_add_input(struct bpf_linker *linker,
+                         const char *name,
+                         const uint8_t *data,
+                         size_t len);
+
+// Links the inputs added since the last link, keeping the `export_symbols_len` symbols of
+// `export_symbols`, and generates an output of `output_type`, one of the `BPF_LINKER_OUTPUT_*`
+// constants.
+//
+// On success, `*out` and `*out_len` are set to the output, which must be freed with
+// [`bpf_linker_free_buffer`].
+//
+// # Safety
+//
+// `linker` must have been returned by [`bpf_linker_new`], `export_symbols` must point to
+// `export_symbols_len` NUL-terminated strings, or be NULL if it's 0, and `out` and `out_len` must
+// be valid for writes.
+int bpf_linker_link_to_buffer(struct bpf_linker *linker,
+                              int output_type,
+                              const char *const *export_symbols,
+                              size_t export_symbols_len,
+                              uint8_t **out,
+                              size_t *out_len);
+
+// Returns the message of the last error of `linker`, or NULL if there was none. The message is
+// valid until the next call taking `linker`.
+//
+// # Safety
+//
+// `linker` must have been returned by [`bpf_linker_new`].
+const char *bpf_linker_last_error(const struct bpf_linker *linker);
+
+// Frees an output returned by [`bpf_linker_link_to_buffer`].
+//
+// # Safety
+//
+// `buf` and `len` must have been returned by [`bpf_linker_link_to_buffer`], or `buf` must be
+// NULL.
+void bpf_linker_free_buffer(uint8_t *buf, size_t len);
+
+// Frees `linker` and the inputs added to it.
+//
+// # Safety
+//
+// `linker` must have been returned by [`bpf_linker_new`], or be NULL.
+void bpf_linker_free(struct bpf_linker *linker);
+
+#endif  /* BPF_LINKER_H */
@@ -0,0 +1,283 @@
+//! C API of bpf-linker, declared in `include/bpf_linker.h`, to embed the linker in build tools
+//! without running it as a process.
+//!
+//! Functions returning `int` return 0 on success and -1 on error, whose message is then returned
+//! by [`bpf_linker_last_error`].
+
+use std::{
+    ffi::{c_char, c_int, CStr, CString},
+    fmt,
+    path::PathBuf,
+    ptr, slice,
+    str::FromStr as _,
+};
+
+use bpf_linker::{
+    Cpu, DiagnosticCollector, Linker, LinkerInput, LinkerOptions, OptLevel, OutputType, Severity,
+};
+
+/// Output an ELF object file.
+pub const BPF_LINKER_OUTPUT_OBJECT: c_int = 0;
+/// Output assembly.
+pub const BPF_LINKER_OUTPUT_ASSEMBLY: c_int = 1;
+/// Output LLVM bitcode.
+pub const BPF_LINKER_OUTPUT_BITCODE: c_int = 2;
+/// Output LLVM IR.
+pub const BPF_LINKER_OUTPUT_LLVM_IR: c_int = 3;
+
+enum Input {
+    File(PathBuf),
+    Buffer(String, Vec<u8>),
+}
+
+/// A linker and the inputs added to it.
+pub struct BpfLinker {
+    linker: Linker,
+    diagnostics: DiagnosticCollector,
+    inputs: Vec<Input>,
+    last_error: Option<CString>,
+}
+
+impl BpfLinker {
+    /// Returns the messages of the errors reported since the last call, and drops the warnings.
+    fn take_errors(&self) -> Vec<String> {
+        self.diagnostics
+            .take()
+            .into_iter()
+            .filter(|diagnostic| matches!(diagnostic.severity, Severity::Error))
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    /// Sets the last error to `error`, followed by the errors reported while linking, and returns
+    /// -1.
+    fn fail(&mut self, error: impl fmt::Display) -> c_int {
+        let mut message = error.to_string();
+        for error in self.take_errors() {
+            message.push('\n');
+            message.push_str(&error);
+        }
+        message.retain(|c| c != '\0');
+        self.last_error = CString::new(message).ok();
+        -1
+    }
+}
+
+/// Returns the string `s` points to, `Some(None)` if it's NULL, or `None` if it isn't UTF-8.
+///
+/// # Safety
+///
+/// `s` must be NULL or point to a NUL-terminated string.
+unsafe fn opt_str<'a>(s: *const c_char) -> Option<Option<&'a str>> {
+    if s.is_null() {
+        return Some(None);
+    }
+    unsafe { CStr::from_ptr(s) }.to_str().ok().map(Some)
+}
+
+/// Creates a linker.
+///
+/// `target` is the LLVM target to generate code for, or NULL to use the one of the inputs. `cpu`
+/// is one of the cpus of the `--cpu` option of bpf-linker, or NULL for `generic`. `opt_level` is
+/// `"0"` to `"3"`, `"s"` or `"z"`, or NULL for `"2"`. BTF is emitted if `btf` is true.
+///
+/// Returns NULL if an argument is invalid. The linker must be freed with [`bpf_linker_free`].
+///
+/// # Safety
+///
+/// The strings must be NULL or NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn bpf_linker_new(
+    target: *const c_char,
+    cpu: *const c_char,
+    opt_level: *const c_char,
+    btf: bool,
+) -> *mut BpfLinker {
+    let options = || -> Option<LinkerOptions> {
+        let target = (!target.is_null()).then(|| unsafe { CStr::from_ptr(target) }.to_owned());
+        let cpu = match unsafe { opt_str(cpu) }? {
+            Some(cpu) => Cpu::from_str(cpu).ok()?,
+            None => Cpu::Generic,
+        };
+        let optimize = match unsafe { opt_str(opt_level) }? {
+            Some("0") => OptLevel::No,
+            Some("1") => OptLevel::Less,
+            None | Some("2") => OptLevel::Default,
+            Some("3") => OptLevel::Aggressive,
+            Some("s") => OptLevel::Size,
+            Some("z") => OptLevel::SizeMin,
+            Some(_) => return None,
+        };
+        Some(LinkerOptions {
+            target,
+            cpu,
+            cpu_features: CString::default(),
+            optimize,
+            unroll_loops: false,
+            ignore_inline_never: false,
+            llvm_args: vec![],
+            disable_expand_memcpy_in_order: false,
+            disable_memory_builtins: false,
+            allow_bpf_trap: false,
+            btf,
+        })
+    };
+    // There's no linker to report the error with yet.
+    let Some(options) = options() else {
+        return ptr::null_mut();
+    };
+
+    let mut linker = Linker::new(options);
+    let diagnostics = DiagnosticCollector::new();
+    linker.set_diagnostic_sink(diagnostics.clone());
+    Box::into_raw(Box::new(BpfLinker {
+        linker,
+        diagnostics,
+        inputs: Vec::new(),
+        last_error: None,
+    }))
+}
+
+/// Adds an input to link: the `len` bytes at `data`, named `name` in messages, or, if `data` is
+/// NULL, the file at the path `name`. The bytes are copied, so they can be freed once this
+/// returns.
+///
+/// # Safety
+///
+/// `linker` must have been returned by [`bpf_linker_new`], `name` must be NUL-terminated and
+/// `data` must be NULL or point to `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn bpf_linker_add_input(
+    linker: *mut BpfLinker,
+    name: *const c_char,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    let linker = unsafe { &mut *linker };
+    let name = match unsafe { CStr::from_ptr(name) }.to_str() {
+        Ok(name) => name,
+        Err(e) => return linker.fail(format_args!("invalid input name: {e}")),
+    };
+    let input = if data.is_null() {
+        Input::File(PathBuf::from(name))
+    } else {
+        let bytes = unsafe { slice::from_raw_parts(data, len) };
+        Input::Buffer(name.to_owned(), bytes.to_vec())
+    };
+    linker.inputs.push(input);
+    0
+}
+
+/// Links the inputs added since the last link, keeping the `export_symbols_len` symbols of
+/// `export_symbols`, and generates an output of `output_type`, one of the `BPF_LINKER_OUTPUT_*`
+/// constants.
+///
+/// On success, `*out` and `*out_len` are set to the output, which must be freed with
+/// [`bpf_linker_free_buffer`].
+///
+/// # Safety
+///
+/// `linker` must have been returned by [`bpf_linker_new`], `export_symbols` must point to
+/// `export_symbols_len` NUL-terminated strings, or be NULL if it's 0, and `out` and `out_len` must
+/// be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn bpf_linker_link_to_buffer(
+    linker: *mut BpfLinker,
+    output_type: c_int,
+    export_symbols: *const *const c_char,
+    export_symbols_len: usize,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    let linker = unsafe { &mut *linker };
+    let output_type = match output_type {
+        BPF_LINKER_OUTPUT_OBJECT => OutputType::Object,
+        BPF_LINKER_OUTPUT_ASSEMBLY => OutputType::Assembly,
+        BPF_LINKER_OUTPUT_BITCODE => OutputType::Bitcode,
+        BPF_LINKER_OUTPUT_LLVM_IR => OutputType::LlvmAssembly,
+        _ => return linker.fail(format_args!("invalid output type {output_type}")),
+    };
+    let export_symbols = if export_symbols_len == 0 {
+        &[]
+    } else {
+        unsafe { slice::from_raw_parts(export_symbols, export_symbols_len) }
+    };
+    let export_symbols = match export_symbols
+        .iter()
+        .map(|&symbol| unsafe { CStr::from_ptr(symbol) }.to_str())
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(export_symbols) => export_symbols,
+        Err(e) => return linker.fail(format_args!("invalid exported symbol: {e}")),
+    };
+
+    let inputs = std::mem::take(&mut linker.inputs);
+    let inputs = inputs.iter().map(|input| match input {
+        Input::File(path) => LinkerInput::new_from_file(path),
+        Input::Buffer(name, bytes) => LinkerInput::new_from_buffer(name, bytes),
+    });
+    let output = match linker
+        .linker
+        .link_to_buffer(inputs, output_type, export_symbols)
+    {
+        Ok(output) => output,
+        Err(e) => return linker.fail(e),
+    };
+    // Like bpf-linker, whose --fatal-errors is set by default. Unlike `Linker::has_errors`, the
+    // collected errors are only those of this link.
+    let errors = linker.take_errors();
+    if !errors.is_empty() {
+        return linker.fail(format_args!(
+            "LLVM issued diagnostic with error severity\n{}",
+            errors.join("\n")
+        ));
+    }
+
+    // The output is freed by the caller, so it's copied out of the buffer LLVM emitted it into.
+    let output = Box::<[u8]>::from(output.as_slice());
+    unsafe {
+        *out_len = output.len();
+        *out = Box::into_raw(output).cast();
+    }
+    0
+}
+
+/// Returns the message of the last error of `linker`, or NULL if there was none. The message is
+/// valid until the next call taking `linker`.
+///
+/// # Safety
+///
+/// `linker` must have been returned by [`bpf_linker_new`].
+#[no_mangle]
+pub unsafe extern "C" fn bpf_linker_last_error(linker: *const BpfLinker) -> *const c_char {
+    let linker = unsafe { &*linker };
+    linker
+        .last_error
+        .as_deref()
+        .map_or(ptr::null(), CStr::as_ptr)
+}
+
+/// Frees an output returned by [`bpf_linker_link_to_buffer`].
+///
+/// # Safety
+///
+/// `buf` and `len` must have been returned by [`bpf_linker_link_to_buffer`], or `buf` must be
+/// NULL.
+#[no_mangle]
+pub unsafe extern "C" fn bpf_linker_free_buffer(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(buf, len)) });
+    }
+}
+
+/// Frees `linker` and the inputs added to it.
+///
+/// # Safety
+///
+/// `linker` must have been returned by [`bpf_linker_new`], or be NULL.
+#[no_mangle]
+pub unsafe extern "C" fn bpf_linker_free(linker: *mut BpfLinker) {
+    if !linker.is_null() {
+        drop(unsafe { Box::from_raw(linker) });
+    }
+}
@@ -3,8 +3,10 @@
 use std::{
     env,
     ffi::CString,
-    fs, io,
+    fs, io, iter,
+    num::NonZeroUsize,
     path::{Component, Path, PathBuf},
+    process,
     str::FromStr,
 };
 
@@ -14,14 +16,18 @@ use std::{
     feature = "rust-llvm-21"
 ))]
 use aya_rustc_llvm_proxy as _;
-use bpf_linker::{Cpu, Linker, LinkerInput, LinkerOptions, OptLevel, OutputType};
+use bpf_linker::{
+    Cpu, DataSizeLimits, Diagnostic, DiagnosticSink, DuplicateSymbols, LineInfo, Linker,
+    LinkerInput, LinkerOptions, MapSectionRule, OptLevel, OutputType, Severity,
+    TargetMachineOptions, UndefinedSymbols, WarningKind,
+};
 use clap::{
     builder::{PathBufValueParser, TypedValueParser as _},
     error::ErrorKind,
-    Parser,
+    Arg, CommandFactory as _, Parser,
 };
 use thiserror::Error;
-use tracing::{info, Level};
+use tracing::{error, info, warn, Level};
 use tracing_subscriber::{fmt::MakeWriter, prelude::*, EnvFilter};
 use tracing_tree::HierarchicalLayer;
 
@@ -29,8 +35,14 @@ use tracing_tree::HierarchicalLayer;
 enum CliError {
     #[error("optimization level needs to be between 0-3, s or z (instead was `{0}`)")]
     InvalidOptimization(String),
-    #[error("unknown emission type: `{0}` - expected one of: `llvm-bc`, `asm`, `llvm-ir`, `obj`")]
+    #[error("unknown emission type: `{0}` - expected one of: `llvm-bc`, `asm`, `llvm-ir`, `obj`, `btf`, `btf-ext`")]
     InvalidOutputType(String),
+    #[error("unknown compression: `{0}` - expected `zstd` or `zstd:<level>`")]
+    InvalidCompression(String),
+    #[error("unknown format: `{0}` - expected `text` or `json`")]
+    InvalidFormat(String),
+    #[error("unknown remarks format: `{0}` - expected `yaml` or `json`")]
+    InvalidRemarksFormat(String),
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -64,11 +76,124 @@ impl FromStr for CliOutputType {
             "asm" => OutputType::Assembly,
             "llvm-ir" => OutputType::LlvmAssembly,
             "obj" => OutputType::Object,
+            "btf" => OutputType::Btf,
+            "btf-ext" => OutputType::BtfExt,
             _ => return Err(CliError::InvalidOutputType(s.to_string())),
         }))
     }
 }
 
+/// Extension of the files written for the output types of `--emit` after the first one.
+fn output_extension(output_type: OutputType) -> &'static str {
+    match output_type {
+        OutputType::Bitcode => "bc",
+        OutputType::Assembly => "s",
+        OutputType::LlvmAssembly => "ll",
+        OutputType::Object => "o",
+        OutputType::Btf => "btf",
+        OutputType::BtfExt => "btf.ext",
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum CliCompression {
+    Zstd(i32),
+}
+
+impl FromStr for CliCompression {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (algorithm, level) = match s.split_once(':') {
+            Some((algorithm, level)) => (algorithm, Some(level)),
+            None => (s, None),
+        };
+        match algorithm {
+            "zstd" => {
+                let level = match level {
+                    // 0 selects zstd's default level.
+                    None => 0,
+                    Some(level) => level
+                        .parse()
+                        .map_err(|_| CliError::InvalidCompression(s.to_string()))?,
+                };
+                Ok(Self::Zstd(level))
+            }
+            _ => Err(CliError::InvalidCompression(s.to_string())),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum CliFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for CliFormat {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(CliError::InvalidFormat(s.to_string())),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum CliRemarksFormat {
+    Yaml,
+    Json,
+}
+
+impl FromStr for CliRemarksFormat {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "yaml" => Ok(Self::Yaml),
+            "json" => Ok(Self::Json),
+            _ => Err(CliError::InvalidRemarksFormat(s.to_string())),
+        }
+    }
+}
+
+/// Reports the diagnostics of the linker to stderr as JSON objects, one per line.
+struct JsonDiagnostics;
+
+impl DiagnosticSink for JsonDiagnostics {
+    fn report(&self, diagnostic: Diagnostic) {
+        eprintln!("{}", diagnostic.to_json());
+    }
+}
+
+/// Logs the diagnostics of the checks of the output, like the linker logs its own.
+struct LogDiagnostics;
+
+impl DiagnosticSink for LogDiagnostics {
+    fn report(&self, diagnostic: Diagnostic) {
+        let Diagnostic {
+            severity, message, ..
+        } = diagnostic;
+        match severity {
+            Severity::Error => error!("{message}"),
+            Severity::Warning => warn!("{message}"),
+        }
+    }
+}
+
+fn error_diagnostic(message: String, symbol: Option<String>) -> Diagnostic {
+    Diagnostic {
+        severity: Severity::Error,
+        kind: None,
+        message,
+        symbol,
+        location: None,
+    }
+}
+
 fn parent_and_file_name(p: PathBuf) -> anyhow::Result<(PathBuf, PathBuf)> {
     let mut comps = p.components();
     let file_name = comps
@@ -84,13 +209,22 @@ fn parent_and_file_name(p: PathBuf) -> anyhow::Result<(PathBuf, PathBuf)> {
 }
 
 #[derive(Debug, Parser)]
-#[command(version)]
+#[command(version, args_override_self = true)]
 struct CommandLine {
-    /// LLVM target triple. When not provided, the target is inferred from the inputs
+    /// Read settings from the TOML file at `path`. Its keys are the names of the long options,
+    /// like `cpu = "v3"`, `btf = true` or `export = ["xdp_*"]`, with `optimize` for `-O` and
+    /// `inputs` for the inputs. Options given on the command line override the settings of the
+    /// file, and lists like `export` are extended by them
+    #[clap(long, value_name = "path")]
+    config: Option<PathBuf>,
+
+    /// LLVM target triple: bpf, bpfel or bpfeb, optionally followed by the rest of the triple.
+    /// When not provided, the target is inferred from the inputs
     #[clap(long)]
     target: Option<CString>,
 
-    /// Target BPF processor. Can be one of `generic`, `probe`, `v1`, `v2`, `v3`
+    /// Target BPF processor. Can be one of `generic`, `probe`, `v1`, `v2`, `v3`, `v4`, or
+    /// `native` for the most recent version the running kernel supports, including v4
     #[clap(long, default_value = "generic")]
     cpu: Cpu,
 
@@ -104,19 +238,180 @@ struct CommandLine {
     #[clap(short, long)]
     output: PathBuf,
 
-    /// Output type. Can be one of `llvm-bc`, `asm`, `llvm-ir`, `obj`
-    #[clap(long, default_value = "obj")]
+    /// Comma separated list of output types. Can be `llvm-bc`, `asm`, `llvm-ir`, `obj`, or `btf`
+    /// and `btf-ext` for the `.BTF` and `.BTF.ext` sections of the object as standalone files,
+    /// which require `--btf`. The first output is written to <output>, and the others next to it
+    /// with the extension of their type (`.bc`, `.s`, `.ll`, `.o`, `.btf` or `.btf.ext`), from the
+    /// same link
+    #[clap(long, default_value = "obj", use_value_delimiter = true, action = clap::ArgAction::Append)]
     emit: Vec<CliOutputType>,
 
+    /// Compress the output. Can be `zstd` or `zstd:<level>`. Object files can only be compressed
+    /// when `--raw-sidecar` is also given, since compressed objects can't be loaded
+    #[clap(long, value_name = "algorithm")]
+    compress_output: Option<CliCompression>,
+
+    /// Also write the uncompressed output to `path` when using `--compress-output`
+    #[clap(long, value_name = "path", requires = "compress_output")]
+    raw_sidecar: Option<PathBuf>,
+
+    /// Also write an object file for each program section in `dir`, with the programs of the
+    /// section and the functions and maps they use. Requires `--emit=obj`
+    #[clap(long, value_name = "dir", conflicts_with = "compress_output")]
+    split_output_dir: Option<PathBuf>,
+
+    /// Also write a libbpf subskeleton header for the linked object to `path`, like `bpftool gen
+    /// subskeleton` does. Requires `--emit=obj`
+    #[clap(long, value_name = "path")]
+    subskeleton: Option<PathBuf>,
+
+    /// Check the worst-case stack usage of the programs across BPF to BPF calls, and fail when a
+    /// call chain uses more than 512 bytes of stack or nests more than 8 calls. Requires
+    /// `--emit=obj`
+    #[clap(long)]
+    check_stack: bool,
+
+    /// Report the number of instructions of each function of the linked object, largest first.
+    /// Requires `--emit=obj`
+    #[clap(long)]
+    insn_counts: bool,
+
+    /// Fail when a function of the linked object has more than `count` instructions. Requires
+    /// `--emit=obj`
+    #[clap(long, value_name = "count")]
+    max_insns: Option<u64>,
+
+    /// Write a summary of the relocations of the linked object to `path`, by section and kind,
+    /// including CO-RE relocations. Requires `--emit=obj`
+    #[clap(long, value_name = "path")]
+    relocation_report: Option<PathBuf>,
+
+    /// Write the CO-RE relocations of the linked object to `path`, with the type and access
+    /// string of each, and whether they resolve against `--vmlinux-btf`. Requires `--emit=obj`
+    #[clap(long, value_name = "path")]
+    core_relocs: Option<PathBuf>,
+
+    /// Format of `--core-relocs`: `text` or `json`
+    #[clap(long, value_name = "format", default_value = "text")]
+    core_relocs_format: CliFormat,
+
+    /// Resolve the CO-RE relocations of the linked object against the BTF at `path`, eg
+    /// `/sys/kernel/btf/vmlinux`, and fail when a type, field or enumerator they access doesn't
+    /// exist in it. Requires `--emit=obj`
+    #[clap(long, value_name = "path")]
+    vmlinux_btf: Option<PathBuf>,
+
+    /// Also resolve the CO-RE relocations against the split BTF of a kernel module at `path`, eg
+    /// `/sys/kernel/btf/nf_conntrack`, on top of `--vmlinux-btf`. Can be repeated
+    #[clap(long, value_name = "path", requires = "vmlinux_btf")]
+    module_btf: Vec<PathBuf>,
+
+    /// Write the raw instructions of each program of the linked object to `<dir>/<program>.bin`,
+    /// and an index of the programs and of their map references to `<dir>/index`, for loaders
+    /// that don't handle ELF objects. Requires `--emit=obj`
+    #[clap(long, value_name = "dir")]
+    raw_programs: Option<PathBuf>,
+
+    /// Only check that the inputs link, for a quick validation in CI: link and verify the module,
+    /// without writing any output. Code is only generated with `--btf`, in memory, to check that
+    /// BTF can be generated. <output> is still required, since rustc always passes it
+    #[clap(
+        long,
+        conflicts_with_all = [
+            "compress_output",
+            "split_output_dir",
+            "subskeleton",
+            "check_stack",
+            "insn_counts",
+            "max_insns",
+            "relocation_report",
+            "core_relocs",
+            "vmlinux_btf",
+            "raw_programs",
+            "btf_strip_func_info",
+            "btf_strip_line_info",
+            "strip_debug",
+        ]
+    )]
+    check: bool,
+
+    /// Whether to emit comments in the generated assembly
+    #[clap(long, value_name = "bool", action = clap::ArgAction::Set)]
+    asm_verbose: Option<bool>,
+
+    /// Warn when a `.bss`, `.data` or `.rodata` section is larger than `bytes`. 0 disables the
+    /// warning
+    #[clap(long, value_name = "bytes")]
+    warn_section_size: Option<u64>,
+
+    /// Warn when a global variable in a `.bss`, `.data` or `.rodata` section is larger than
+    /// `bytes`. 0 disables the warning
+    #[clap(long, value_name = "bytes")]
+    warn_global_size: Option<u64>,
+
+    /// Fail when the inputs, including the members of archives, are larger than `bytes` in total
+    #[clap(long, value_name = "bytes")]
+    max_input_size: Option<u64>,
+
+    /// Number of threads parsing and linking the inputs. Defaults to the number of CPUs
+    #[clap(short = 'j', long, value_name = "jobs")]
+    jobs: Option<NonZeroUsize>,
+
+    /// Warn about code that the verifier is likely to reject: loops, large memory copies, calls
+    /// through function pointers and calls with more than 5 arguments
+    #[clap(long)]
+    lint_verifier: bool,
+
     /// Emit BTF information
     #[clap(long)]
     btf: bool,
 
+    /// Don't check that the DATASECs of the BTF match the layout of the object, which loaders
+    /// need to create the maps of data sections
+    #[clap(long)]
+    disable_datasec_check: bool,
+
+    /// How much line info to emit with `--btf`. Can be `full` for line info whenever the source
+    /// line changes, `statements` for the first instruction of each line in a basic block, or
+    /// `entry` for the entry of functions only. Less line info makes smaller objects that load
+    /// faster, at the cost of less helpful verifier logs
+    #[clap(long, value_name = "level", default_value = "full")]
+    btf_line_info: LineInfo,
+
+    /// With `--btf`, rename the global variables that aren't exported after their demangled Rust
+    /// name, so that their BTF shows eg `CONFIG` rather than their mangled name
+    #[clap(long)]
+    btf_demangle: bool,
+
+    /// With `--btf`, drop the func info of `.BTF.ext`, keeping the types. Requires `--emit=obj`
+    #[clap(long, requires = "btf", conflicts_with = "compress_output")]
+    btf_strip_func_info: bool,
+
+    /// With `--btf`, drop the line info of `.BTF.ext`, keeping the types. Requires `--emit=obj`
+    #[clap(long, requires = "btf", conflicts_with = "compress_output")]
+    btf_strip_line_info: bool,
+
+    /// Remove the DWARF debug info from the output, keeping `.BTF` and `.BTF.ext`, which are
+    /// generated from it. Requires `--emit=obj`
+    #[clap(long, conflicts_with = "compress_output")]
+    strip_debug: bool,
+
+    /// Keep the full DWARF debug info in the output, for source-level debugging with tools like
+    /// `llvm-objdump -S`. LLVM generates BTF from the debug info, so this implies `--btf`
+    #[clap(long, conflicts_with_all = ["strip_debug", "btf_line_info"])]
+    keep_dwarf: bool,
+
     /// Permit automatic insertion of __bpf_trap calls.
     /// See: https://github.com/llvm/llvm-project/commit/ab391beb11f733b526b86f9df23734a34657d876
     #[clap(long)]
     allow_bpf_trap: bool,
 
+    /// Disable machine outlining, hot/cold splitting and partial inlining, which move code into
+    /// new functions that some loaders reject. With `--emit=obj`, also fail if the output has such
+    /// functions
+    #[clap(long)]
+    disable_outlining: bool,
+
     /// UNUSED: it only exists for compatibility with rustc
     #[clap(short = 'L', number_of_values = 1)]
     _libs: Vec<PathBuf>,
@@ -125,7 +420,22 @@ struct CommandLine {
     #[clap(short = 'O', default_value = "2")]
     optimize: Vec<CliOptLevel>,
 
-    /// Export the symbols specified in the file `path`. The symbols must be separated by new lines
+    /// Run the given pipeline of LLVM passes, eg `default<O2>,loop-unroll<O3>`, instead of the
+    /// default pipeline of the optimization level
+    #[clap(long, value_name = "pipeline")]
+    passes: Option<CString>,
+
+    /// Threshold of the inliner, overriding the one of the optimization level. Lower values
+    /// inline less. Also applies to the default pipelines included in `--passes`
+    #[clap(long, value_name = "threshold", allow_negative_numbers = true)]
+    inline_threshold: Option<i32>,
+
+    /// Largest number of times a loop is unrolled
+    #[clap(long, value_name = "count")]
+    unroll_max_count: Option<u32>,
+
+    /// Export the symbols specified in the file `path`. The symbols must be separated by new lines,
+    /// and can be patterns like `tracepoint_*`. Empty lines and lines starting with `#` are ignored
     #[clap(long, value_name = "path")]
     export_symbols: Option<PathBuf>,
 
@@ -137,6 +447,10 @@ struct CommandLine {
     )]
     log_file: Option<(PathBuf, PathBuf)>,
 
+    /// Show the mangled name of Rust symbols after their demangled name in diagnostics and reports
+    #[clap(long)]
+    show_mangled: bool,
+
     /// Set the log level. If not specified, no logging is used. Can be one of
     /// `error`, `warn`, `info`, `debug`, `trace`.
     #[clap(long, value_name = "level")]
@@ -158,6 +472,21 @@ struct CommandLine {
     #[clap(long, value_name = "args", use_value_delimiter = true, action = clap::ArgAction::Append)]
     llvm_args: Vec<CString>,
 
+    /// Collect the optimization remarks of the LLVM passes matching the regular expression
+    /// `passes`, eg `inline|loop-unroll`, including missed optimizations. Remarks are logged at
+    /// the info level unless `--remarks-output` is given
+    #[clap(long, value_name = "passes")]
+    remarks: Option<CString>,
+
+    /// Write the optimization remarks to the given `path`
+    #[clap(long, value_name = "path", requires = "remarks")]
+    remarks_output: Option<PathBuf>,
+
+    /// Format of the remarks written with `--remarks-output`: `yaml`, like the remarks files of
+    /// LLVM, or `json` for an array of objects with the file, line, column and message of each
+    #[clap(long, value_name = "format", default_value = "yaml")]
+    remarks_format: CliRemarksFormat,
+
     /// Disable passing --bpf-expand-memcpy-in-order to LLVM.
     #[clap(long)]
     disable_expand_memcpy_in_order: bool,
@@ -168,11 +497,28 @@ struct CommandLine {
     #[clap(long)]
     disable_memory_builtins: bool,
 
-    /// Input files. Can be object files or static libraries
+    /// Disable folding the values of global variables into the code using them. Useful when the
+    /// loader patches global variables, like configuration constants, before loading the programs
+    #[clap(long)]
+    disable_const_propagation: bool,
+
+    /// Merge identical functions after optimization, like the instances of generic functions that
+    /// compile to the same code
+    #[clap(long)]
+    icf: bool,
+
+    /// Remove the maps that no program uses, like the maps of utility crates. Unused functions
+    /// are always removed
+    #[clap(long)]
+    gc_maps: bool,
+
+    /// Input files. Can be object files, static libraries or BPF assembly files (.s). `-` reads an
+    /// input from stdin
     #[clap(required = true)]
     inputs: Vec<PathBuf>,
 
-    /// Comma separated list of symbols to export. See also `--export-symbols`
+    /// Comma separated list of symbols to export, which can be patterns like `tracepoint_*`. See
+    /// also `--export-symbols`
     #[clap(long, value_name = "symbols", use_value_delimiter = true, action = clap::ArgAction::Append)]
     export: Vec<String>,
 
@@ -180,6 +526,48 @@ struct CommandLine {
     #[clap(long, action = clap::ArgAction::Set, default_value_t = true)]
     fatal_errors: bool,
 
+    /// Turn warnings into errors. Takes an optional comma separated list of the kinds of warnings
+    /// to turn into errors, among `llvm`, `missing-bitcode`, `data-size`, `map`, `text-function`,
+    /// `debug-info`, `btf-map` and `verifier`. All warnings are turned into errors when no kind is
+    /// given
+    #[clap(
+        long,
+        value_name = "kinds",
+        num_args = 0..,
+        require_equals = true,
+        value_delimiter = ','
+    )]
+    fatal_warnings: Option<Vec<WarningKind>>,
+
+    /// Format of the errors and warnings of the linker: `text`, or `json` for a JSON object per
+    /// line on stderr, with the severity, kind, message, symbol, file and line of each
+    #[clap(long, value_name = "format", default_value = "text")]
+    diagnostics_format: CliFormat,
+
+    /// How to handle symbols that more than one input defines: `error`, `first-wins` or
+    /// `last-wins` to keep the definition of the first or last input, or `rename-second` to rename
+    /// the definitions after the first so that each input keeps using its own
+    #[clap(long, value_name = "policy", default_value = "error")]
+    duplicate_symbols: DuplicateSymbols,
+
+    /// Allow symbols that are referenced but defined in none of the inputs, leaving them for the
+    /// loader to resolve. Takes an optional comma separated list of the symbols to allow. All
+    /// undefined symbols are allowed when no symbol is given
+    #[clap(
+        long,
+        value_name = "symbols",
+        num_args = 0..,
+        require_equals = true,
+        value_delimiter = ','
+    )]
+    allow_undefined: Option<Vec<String>>,
+
+    /// Comma separated list of `<pattern>=maps` or `<pattern>=.maps` rules, placing the maps whose
+    /// name matches `pattern` in the legacy `maps` or the BTF `.maps` section. `*` in patterns
+    /// matches any sequence of characters. The first matching rule applies
+    #[clap(long, value_name = "rules", use_value_delimiter = true, action = clap::ArgAction::Append)]
+    map_section: Vec<MapSectionRule>,
+
     // The options below are for wasm-ld compatibility
     #[clap(long = "debug", hide = true)]
     _debug: bool,
@@ -196,47 +584,181 @@ where
         .with_indent_lines(true)
         .with_writer(writer)
 }
-fn main() -> anyhow::Result<()> {
-    let args = env::args().map(|arg| {
-        if arg == "-flavor" {
-            "--flavor".to_string()
-        } else {
-            arg
+/// Inserts the settings of the `--config` file of `args`, if there's one, before the arguments,
+/// so that the arguments given on the command line override them.
+fn with_config(args: Vec<String>) -> anyhow::Result<Vec<String>> {
+    let path = args.iter().enumerate().skip(1).find_map(|(index, arg)| {
+        match arg.strip_prefix("--config")? {
+            "" => args.get(index + 1).cloned(),
+            path => path.strip_prefix('=').map(ToOwned::to_owned),
         }
     });
+    let Some(path) = path else {
+        return Ok(args);
+    };
+    let config = fs::read_to_string(&path)
+        .map_err(|err| anyhow::anyhow!("failed to read config {path}: {err}"))?;
+    let config =
+        config_args(&config).map_err(|err| anyhow::anyhow!("invalid config {path}: {err}"))?;
+    let mut args = args.into_iter();
+    Ok(args.next().into_iter().chain(config).chain(args).collect())
+}
+
+/// Returns the command line arguments equivalent to the settings of a TOML config file.
+fn config_args(config: &str) -> anyhow::Result<Vec<String>> {
+    let config = config.parse::<toml::Table>()?;
+    let mut command = CommandLine::command();
+    command.build();
+    let mut args = Vec::new();
+    for (key, value) in config {
+        let id = key.replace('-', "_");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == id.as_str() && !arg.is_hide_set() && id != "config")
+            .ok_or_else(|| anyhow::anyhow!("unknown setting `{key}`"))?;
+        let values = match value {
+            toml::Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                toml::Value::String(value) => value,
+                toml::Value::Integer(value) => value.to_string(),
+                // Flags, and options whose value is optional, are set by giving them alone.
+                toml::Value::Boolean(value)
+                    if !arg.get_action().takes_values()
+                        || arg
+                            .get_num_args()
+                            .is_some_and(|num_args| num_args.min_values() == 0) =>
+                {
+                    if value {
+                        args.push(config_arg(arg, None));
+                    }
+                    continue;
+                }
+                toml::Value::Boolean(value) => value.to_string(),
+                _ => return Err(anyhow::anyhow!("invalid value for `{key}`")),
+            };
+            args.push(config_arg(arg, Some(&value)));
+        }
+    }
+    Ok(args)
+}
+
+fn config_arg(arg: &Arg, value: Option<&str>) -> String {
+    match (arg.get_long(), arg.get_short(), value) {
+        (Some(long), _, None) => format!("--{long}"),
+        (Some(long), _, Some(value)) => format!("--{long}={value}"),
+        (None, Some(short), value) => format!("-{short}{}", value.unwrap_or_default()),
+        // Positional arguments.
+        (None, None, value) => value.unwrap_or_default().to_owned(),
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = env::args()
+        .map(|arg| {
+            if arg == "-flavor" {
+                "--flavor".to_string()
+            } else {
+                arg
+            }
+        })
+        .collect::<Vec<_>>();
+    let args = with_config(args)?;
+    let command_line: CommandLine = match Parser::try_parse_from(args) {
+        Ok(command_line) => command_line,
+        Err(err) => match err.kind() {
+            ErrorKind::DisplayHelp | ErrorKind::DisplayVersion => {
+                print!("{err}");
+                return Ok(());
+            }
+            _ => return Err(err.into()),
+        },
+    };
+    let diagnostics_format = command_line.diagnostics_format;
+    match link(command_line) {
+        // The error ending the link is a diagnostic like the others, for the tools reading them.
+        Err(err) if matches!(diagnostics_format, CliFormat::Json) => {
+            JsonDiagnostics.report(error_diagnostic(format!("{err:#}"), None));
+            process::exit(1)
+        }
+        result => result,
+    }
+}
+
+/// Links the inputs, and checks the outputs, as set by `command_line`.
+fn link(command_line: CommandLine) -> anyhow::Result<()> {
     let CommandLine {
+        config: _,
         target,
         cpu,
         cpu_features,
         output,
         emit,
+        compress_output,
+        raw_sidecar,
+        split_output_dir,
+        subskeleton,
+        check_stack,
+        insn_counts,
+        max_insns,
+        relocation_report,
+        core_relocs,
+        core_relocs_format,
+        vmlinux_btf,
+        module_btf,
+        raw_programs,
+        check,
+        asm_verbose,
+        warn_section_size,
+        warn_global_size,
+        max_input_size,
+        jobs,
+        lint_verifier,
         btf,
+        disable_datasec_check,
+        btf_line_info,
+        btf_demangle,
+        btf_strip_func_info,
+        btf_strip_line_info,
+        strip_debug,
+        keep_dwarf,
         allow_bpf_trap,
+        disable_outlining,
         optimize,
+        passes,
+        inline_threshold,
+        unroll_max_count,
         export_symbols,
         log_file,
         log_level,
+        show_mangled,
         unroll_loops,
         ignore_inline_never,
         dump_module,
         llvm_args,
+        remarks,
+        remarks_output,
+        remarks_format,
         disable_expand_memcpy_in_order,
         disable_memory_builtins,
         inputs,
         export,
         fatal_errors,
+        fatal_warnings,
+        diagnostics_format,
+        allow_undefined,
+        duplicate_symbols,
+        map_section,
+        disable_const_propagation,
+        icf,
+        gc_maps,
         _debug,
         _libs,
-    } = match Parser::try_parse_from(args) {
-        Ok(command_line) => command_line,
-        Err(err) => match err.kind() {
-            ErrorKind::DisplayHelp | ErrorKind::DisplayVersion => {
-                print!("{err}");
-                return Ok(());
-            }
-            _ => return Err(err.into()),
-        },
-    };
+    } = command_line;
+    // The debug info is only kept to generate BTF, which LLVM emits whenever there is debug info.
+    let btf = btf || keep_dwarf;
 
     // Configure tracing.
     let _guard = {
@@ -269,23 +791,100 @@ fn main() -> anyhow::Result<()> {
         env::args().collect::<Vec<_>>().join(" ")
     );
 
+    bpf_linker::set_show_mangled_names(show_mangled);
+
     let export_symbols = export_symbols.map(fs::read_to_string).transpose()?;
 
     let export_symbols = export_symbols
         .as_deref()
         .into_iter()
         .flat_map(str::lines)
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
         .chain(export.iter().map(String::as_str));
 
-    let output_type = match *emit.as_slice() {
+    let (output_type, extra_outputs) = match emit.as_slice() {
         [] => unreachable!("emit has a default value"),
-        [CliOutputType(output_type), ..] => output_type,
+        [CliOutputType(output_type), extra_outputs @ ..] => (*output_type, extra_outputs),
     };
+    let extra_outputs = extra_outputs
+        .iter()
+        .map(|&CliOutputType(output_type)| {
+            (
+                output_type,
+                output.with_extension(output_extension(output_type)),
+            )
+        })
+        .collect::<Vec<_>>();
+    for (index, (_, path)) in extra_outputs.iter().enumerate() {
+        if *path == output
+            || extra_outputs[..index]
+                .iter()
+                .any(|(_, other)| other == path)
+        {
+            return Err(anyhow::anyhow!(
+                "--emit would write {path:?} more than once"
+            ));
+        }
+    }
+    if !btf
+        && iter::once(&output_type)
+            .chain(extra_outputs.iter().map(|(output_type, _)| output_type))
+            .any(|output_type| matches!(output_type, OutputType::Btf | OutputType::BtfExt))
+    {
+        return Err(anyhow::anyhow!(
+            "--emit=btf and --emit=btf-ext require --btf"
+        ));
+    }
+    if split_output_dir.is_some()
+        && (!matches!(output_type, OutputType::Object) || !extra_outputs.is_empty())
+    {
+        return Err(anyhow::anyhow!("--split-output-dir requires --emit=obj"));
+    }
+    if (btf_strip_func_info || btf_strip_line_info)
+        && (!matches!(output_type, OutputType::Object) || !extra_outputs.is_empty())
+    {
+        return Err(anyhow::anyhow!(
+            "--btf-strip-func-info and --btf-strip-line-info require --emit=obj"
+        ));
+    }
+    if strip_debug && (!matches!(output_type, OutputType::Object) || !extra_outputs.is_empty()) {
+        return Err(anyhow::anyhow!("--strip-debug requires --emit=obj"));
+    }
+    if !extra_outputs.is_empty() && compress_output.is_some() {
+        return Err(anyhow::anyhow!(
+            "--compress-output can't be used with several --emit types"
+        ));
+    }
     let optimize = match *optimize.as_slice() {
         [] => unreachable!("emit has a default value"),
         [.., CliOptLevel(optimize)] => optimize,
     };
 
+    if subskeleton.is_some() && !matches!(output_type, OutputType::Object) {
+        return Err(anyhow::anyhow!("--subskeleton requires --emit=obj"));
+    }
+    if check_stack && !matches!(output_type, OutputType::Object) {
+        return Err(anyhow::anyhow!("--check-stack requires --emit=obj"));
+    }
+    if (insn_counts || max_insns.is_some()) && !matches!(output_type, OutputType::Object) {
+        return Err(anyhow::anyhow!(
+            "--insn-counts and --max-insns require --emit=obj"
+        ));
+    }
+    if relocation_report.is_some() && !matches!(output_type, OutputType::Object) {
+        return Err(anyhow::anyhow!("--relocation-report requires --emit=obj"));
+    }
+    if core_relocs.is_some() && !matches!(output_type, OutputType::Object) {
+        return Err(anyhow::anyhow!("--core-relocs requires --emit=obj"));
+    }
+    if vmlinux_btf.is_some() && !matches!(output_type, OutputType::Object) {
+        return Err(anyhow::anyhow!("--vmlinux-btf requires --emit=obj"));
+    }
+    if raw_programs.is_some() && !matches!(output_type, OutputType::Object) {
+        return Err(anyhow::anyhow!("--raw-programs requires --emit=obj"));
+    }
+
     let mut linker = Linker::new(LinkerOptions {
         target,
         cpu,
@@ -304,12 +903,323 @@ fn main() -> anyhow::Result<()> {
         linker.set_dump_module_path(path);
     }
 
-    let inputs = inputs
-        .iter()
-        .map(|p| LinkerInput::new_from_file(p.as_path()));
+    let mut target_machine_options = TargetMachineOptions::new();
+    if let Some(verbose) = asm_verbose {
+        target_machine_options = target_machine_options.asm_verbose(verbose);
+    }
+    linker.set_target_machine_options(target_machine_options);
+
+    let DataSizeLimits { section, global } = DataSizeLimits::default();
+    let limit =
+        |size: Option<u64>, default| size.map_or(default, |size| (size != 0).then_some(size));
+    linker.set_data_size_limits(DataSizeLimits {
+        section: limit(warn_section_size, section),
+        global: limit(warn_global_size, global),
+    });
+
+    if let Some(kinds) = fatal_warnings {
+        match kinds.as_slice() {
+            [] => linker.set_fatal_warnings(WarningKind::ALL.iter().copied()),
+            kinds => linker.set_fatal_warnings(kinds.iter().copied()),
+        }
+    }
+
+    linker.set_undefined_symbols(match allow_undefined {
+        None => UndefinedSymbols::Deny,
+        Some(symbols) if symbols.is_empty() => UndefinedSymbols::Allow,
+        Some(symbols) => UndefinedSymbols::AllowOnly(symbols.into_iter().collect()),
+    });
+    linker.set_duplicate_symbols(duplicate_symbols);
+    linker.set_map_sections(map_section);
+    linker.set_const_propagation(!disable_const_propagation);
+    linker.set_identical_code_folding(icf);
+    linker.set_gc_maps(gc_maps);
+    linker.set_line_info(btf_line_info);
+    linker.set_demangle_btf_names(btf_demangle);
+    linker.set_input_size_limit(max_input_size);
+    linker.set_jobs(jobs);
+    linker.set_verifier_lints(lint_verifier);
+    linker.set_outlining(!disable_outlining);
+    linker.set_datasec_check(!disable_datasec_check);
+    linker.set_remarks(remarks);
+    linker.set_passes(passes);
+    linker.set_inline_threshold(inline_threshold);
+    linker.set_unroll_max_count(unroll_max_count);
+    if let CliFormat::Json = diagnostics_format {
+        linker.set_diagnostic_sink(JsonDiagnostics);
+    }
+
+    let is_stdin = |path: &Path| path.as_os_str() == "-";
+    if inputs.iter().filter(|path| is_stdin(path)).count() > 1 {
+        return Err(anyhow::anyhow!("`-` can only be given once"));
+    }
+    let inputs = inputs.iter().map(|p| {
+        if is_stdin(p) {
+            LinkerInput::new_from_reader("stdin", io::stdin().lock())
+        } else {
+            LinkerInput::new_from_file(p.as_path())
+        }
+    });
+
+    let mut split_objects = Vec::new();
+    match (compress_output, &split_output_dir) {
+        _ if check => linker.check(inputs, export_symbols)?,
+        (None, Some(split_output_dir)) => {
+            split_objects =
+                linker.link_to_split_files(inputs, &output, split_output_dir, export_symbols)?;
+            for path in &split_objects {
+                info!("wrote {:?}", path);
+            }
+        }
+        (None, None) if extra_outputs.is_empty() => {
+            linker.link_to_file(inputs, &output, output_type, export_symbols)?
+        }
+        (None, None) => linker.link_to_files(
+            inputs,
+            iter::once((output_type, output.as_path())).chain(
+                extra_outputs
+                    .iter()
+                    .map(|(output_type, path)| (*output_type, path.as_path())),
+            ),
+            export_symbols,
+        )?,
+        (Some(CliCompression::Zstd(level)), _) => {
+            if matches!(output_type, OutputType::Object) && raw_sidecar.is_none() {
+                return Err(anyhow::anyhow!(
+                    "refusing to compress an object file without --raw-sidecar"
+                ));
+            }
+            let linked = linker.link_to_buffer(inputs, output_type, export_symbols)?;
+            if let Some(raw_sidecar) = &raw_sidecar {
+                fs::write(raw_sidecar, linked.as_slice())?;
+            }
+            let file = fs::File::create(&output)?;
+            zstd::stream::copy_encode(linked.as_slice(), file, level)?;
+        }
+    }
+
+    let remarks = linker.remarks();
+    match remarks_output {
+        Some(path) => {
+            let remarks = match remarks_format {
+                CliRemarksFormat::Yaml => remarks
+                    .iter()
+                    .map(bpf_linker::Remark::to_yaml)
+                    .collect::<String>(),
+                CliRemarksFormat::Json => format!(
+                    "[{}]\n",
+                    remarks
+                        .iter()
+                        .map(bpf_linker::Remark::to_json)
+                        .collect::<Vec<_>>()
+                        .join(",")
+                ),
+            };
+            fs::write(path, remarks)?;
+        }
+        None => {
+            for remark in &remarks {
+                info!("remark: {remark}");
+            }
+        }
+    }
+
+    // The checks of the output have nothing to read.
+    if check {
+        return check_errors(&linker, fatal_errors);
+    }
 
-    linker.link_to_file(inputs, &output, output_type, export_symbols)?;
+    if btf_strip_func_info || btf_strip_line_info {
+        for path in iter::once(&output).chain(&split_objects) {
+            let mut object = fs::read(path)?;
+            bpf_linker::strip_btf_ext(&mut object, btf_strip_func_info, btf_strip_line_info)?;
+            fs::write(path, object)?;
+        }
+    }
+    if strip_debug {
+        for path in iter::once(&output).chain(&split_objects) {
+            let object = bpf_linker::strip_debug(&fs::read(path)?)?;
+            fs::write(path, object)?;
+        }
+    }
 
+    // The object is read and parsed once for all the analyses of it. Compressed objects always
+    // come with an uncompressed sidecar.
+    let analyze_object = matches!(output_type, OutputType::Object)
+        && (disable_outlining
+            || subskeleton.is_some()
+            || check_stack
+            || insn_counts
+            || max_insns.is_some()
+            || relocation_report.is_some()
+            || core_relocs.is_some()
+            || vmlinux_btf.is_some()
+            || raw_programs.is_some());
+    let object_data = analyze_object
+        .then(|| fs::read(raw_sidecar.as_deref().unwrap_or(&output)))
+        .transpose()?;
+    // The problems found in the output are reported like those found by the linker.
+    let diagnostics: &dyn DiagnosticSink = match diagnostics_format {
+        CliFormat::Text => &LogDiagnostics,
+        CliFormat::Json => &JsonDiagnostics,
+    };
+    if let Some(object_data) = &object_data {
+        let object = &object::File::parse(object_data.as_slice())?;
+        if disable_outlining {
+            let functions = bpf_linker::artificial_functions(object)?;
+            for function in &functions {
+                let function = bpf_linker::demangle(function);
+                diagnostics.report(error_diagnostic(
+                    format!("function {function} was outlined despite --disable-outlining"),
+                    Some(function),
+                ));
+            }
+            if !functions.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "code was outlined despite --disable-outlining"
+                ));
+            }
+        }
+        if let Some(path) = subskeleton {
+            let name = output
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .ok_or_else(|| anyhow::anyhow!("can't name the subskeleton after {output:?}"))?;
+            fs::write(path, bpf_linker::subskeleton(name, object)?)?;
+        }
+        if check_stack {
+            let mut exceeded = false;
+            for usage in bpf_linker::stack_usage(object)? {
+                let call_chain = usage
+                    .call_chain
+                    .iter()
+                    .map(|frame| format!("{} ({} bytes)", frame.function, frame.size))
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                if usage.exceeds_limits() {
+                    exceeded = true;
+                    let program = bpf_linker::demangle(&usage.program);
+                    diagnostics.report(error_diagnostic(
+                        format!(
+                            "program {} uses {} bytes of stack and {} call frames, more than {} \
+                             bytes or {} frames: {}",
+                            program,
+                            usage.stack_size,
+                            usage.call_frames,
+                            bpf_linker::MAX_STACK_SIZE,
+                            bpf_linker::MAX_CALL_FRAMES,
+                            call_chain
+                        ),
+                        Some(program),
+                    ));
+                } else {
+                    info!(
+                        "program {} uses {} bytes of stack and {} call frames: {}",
+                        bpf_linker::demangle(&usage.program),
+                        usage.stack_size,
+                        usage.call_frames,
+                        call_chain
+                    );
+                }
+            }
+            if exceeded {
+                return Err(anyhow::anyhow!("stack limits exceeded"));
+            }
+        }
+        if insn_counts || max_insns.is_some() {
+            let mut exceeded = false;
+            for function in bpf_linker::instruction_counts(object)? {
+                match max_insns {
+                    Some(max_insns) if function.insns > max_insns => {
+                        exceeded = true;
+                        diagnostics.report(error_diagnostic(
+                            format!("{function}, more than {max_insns}"),
+                            Some(bpf_linker::demangle(&function.function)),
+                        ));
+                    }
+                    _ if insn_counts => info!("{function}"),
+                    _ => {}
+                }
+            }
+            if exceeded {
+                return Err(anyhow::anyhow!("instruction limit exceeded"));
+            }
+        }
+        if let Some(path) = relocation_report {
+            let report = bpf_linker::relocation_report(object)?
+                .iter()
+                .map(ToString::to_string)
+                .collect::<String>();
+            fs::write(path, report)?;
+        }
+        if core_relocs.is_some() || vmlinux_btf.is_some() {
+            let target = vmlinux_btf.as_ref().map(fs::read).transpose()?;
+            let modules = module_btf
+                .iter()
+                .map(fs::read)
+                .collect::<Result<Vec<_>, _>>()?;
+            let modules = modules.iter().map(Vec::as_slice).collect::<Vec<_>>();
+            let relocations =
+                bpf_linker::core_relocation_report(object, target.as_deref(), &modules)?;
+            if let Some(path) = core_relocs {
+                let report = match core_relocs_format {
+                    CliFormat::Text => relocations
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<String>(),
+                    CliFormat::Json => format!(
+                        "[{}]\n",
+                        relocations
+                            .iter()
+                            .map(bpf_linker::CoreRelocation::to_json)
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    ),
+                };
+                fs::write(path, report)?;
+            }
+            // Existence checks are expected not to resolve on some kernels.
+            let unresolved = relocations
+                .iter()
+                .filter(|relocation| {
+                    relocation.resolved == Some(false) && !relocation.checks_existence()
+                })
+                .collect::<Vec<_>>();
+            for relocation in &unresolved {
+                diagnostics.report(error_diagnostic(
+                    format!(
+                        "CO-RE relocation doesn't resolve: {}",
+                        relocation.to_string().trim_end()
+                    ),
+                    None,
+                ));
+            }
+            if !unresolved.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "{} CO-RE relocations don't resolve against {}",
+                    unresolved.len(),
+                    vmlinux_btf.unwrap_or_default().display()
+                ));
+            }
+        }
+        if let Some(dir) = raw_programs {
+            let programs = bpf_linker::raw_programs(object)?;
+            fs::create_dir_all(&dir)?;
+            for program in &programs {
+                fs::write(
+                    dir.join(format!("{}.bin", program.name)),
+                    &program.instructions,
+                )?;
+            }
+            let index = programs.iter().map(ToString::to_string).collect::<String>();
+            fs::write(dir.join("index"), index)?;
+        }
+    }
+
+    check_errors(&linker, fatal_errors)
+}
+
+fn check_errors(linker: &Linker, fatal_errors: bool) -> anyhow::Result<()> {
     if fatal_errors && linker.has_errors() {
         return Err(anyhow::anyhow!(
             "LLVM issued diagnostic with error severity"
@@ -388,4 +1298,135 @@ mod test {
             [PathBuf::from("symbols.o"), PathBuf::from("rcgu.o")]
         );
     }
+
+    #[test]
+    fn test_fatal_warnings() {
+        let args = [
+            "bpf-linker",
+            "-o",
+            "/tmp/bin.o",
+            "--fatal-warnings",
+            "symbols.o",
+        ];
+        let CommandLine {
+            inputs,
+            fatal_warnings,
+            ..
+        } = Parser::parse_from(args);
+        assert_eq!(fatal_warnings, Some(vec![]));
+        assert_eq!(inputs, [PathBuf::from("symbols.o")]);
+
+        let args = [
+            "bpf-linker",
+            "-o",
+            "/tmp/bin.o",
+            "--fatal-warnings=map,data-size",
+            "symbols.o",
+        ];
+        let CommandLine { fatal_warnings, .. } = Parser::parse_from(args);
+        assert_eq!(
+            fatal_warnings,
+            Some(vec![WarningKind::Map, WarningKind::DataSize])
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let args = ["bpf-linker", "-o", "/tmp/bin.o", "--check", "symbols.o"];
+        let CommandLine { check, .. } = Parser::parse_from(args);
+        assert!(check);
+
+        // The output isn't written for the post-processing to read.
+        let args = [
+            "bpf-linker",
+            "-o",
+            "/tmp/bin.o",
+            "--check",
+            "--strip-debug",
+            "symbols.o",
+        ];
+        let err = CommandLine::try_parse_from(args).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn test_config() {
+        let config = r#"
+            cpu = "v3"
+            btf = true
+            fatal-errors = false
+            fatal_warnings = true
+            optimize = "s"
+            export = ["xdp_*", "tc_*"]
+            inputs = ["symbols.o"]
+        "#;
+        let args = iter::once("bpf-linker".to_owned())
+            .chain(config_args(config).unwrap())
+            .chain(
+                [
+                    "-o",
+                    "/tmp/bin.o",
+                    "--cpu=v2",
+                    "--export=kprobe_*",
+                    "rcgu.o",
+                ]
+                .map(Into::into),
+            );
+        let CommandLine {
+            cpu,
+            btf,
+            fatal_errors,
+            fatal_warnings,
+            optimize,
+            export,
+            inputs,
+            ..
+        } = Parser::parse_from(args);
+        assert!(matches!(cpu, Cpu::V2));
+        assert!(btf);
+        assert!(!fatal_errors);
+        assert_eq!(fatal_warnings, Some(vec![]));
+        assert!(matches!(optimize.as_slice(), [CliOptLevel(OptLevel::Size)]));
+        assert_eq!(export, ["xdp_*", "tc_*", "kprobe_*"]);
+        assert_eq!(
+            inputs,
+            [PathBuf::from("symbols.o"), PathBuf::from("rcgu.o")]
+        );
+
+        assert!(config_args("opt = 3").is_err());
+        assert!(config_args("cpu = { name = \"v3\" }").is_err());
+    }
+
+    #[test]
+    fn test_compress_output() {
+        assert!(matches!(
+            "zstd".parse::<CliCompression>(),
+            Ok(CliCompression::Zstd(0))
+        ));
+        assert!(matches!(
+            "zstd:19".parse::<CliCompression>(),
+            Ok(CliCompression::Zstd(19))
+        ));
+        assert!("zstd:max".parse::<CliCompression>().is_err());
+        assert!("gzip".parse::<CliCompression>().is_err());
+    }
+
+    #[test]
+    fn test_emit() {
+        let args = [
+            "bpf-linker",
+            "-o",
+            "/tmp/bin.o",
+            "--emit=obj,llvm-ir",
+            "--emit",
+            "asm,btf-ext",
+            "symbols.o",
+        ];
+        let CommandLine { emit, .. } = Parser::parse_from(args);
+        let extensions = emit
+            .into_iter()
+            .map(|CliOutputType(output_type)| output_extension(output_type))
+            .collect::<Vec<_>>();
+        assert_eq!(extensions, ["o", "ll", "s", "btf.ext"]);
+    }
 }
@@ -0,0 +1,303 @@
+//! Reading of the identification block of LLVM bitcode, which names the compiler that produced
+//! it, so that bitcode LLVM fails to parse can be told apart from bitcode of another LLVM version.
+
+/// Magic of the wrapper some producers put around bitcode.
+const WRAPPER_MAGIC: u32 = 0x0b17_c0de;
+/// Magic of raw bitcode.
+const BITCODE_MAGIC: &[u8] = b"BC\xc0\xde";
+
+const END_BLOCK: u64 = 0;
+const ENTER_SUBBLOCK: u64 = 1;
+const DEFINE_ABBREV: u64 = 2;
+const UNABBREV_RECORD: u64 = 3;
+
+const IDENTIFICATION_BLOCK_ID: u64 = 13;
+const IDENTIFICATION_CODE_STRING: u64 = 1;
+
+/// An operand of an abbreviation.
+#[derive(Clone, Copy)]
+enum Op {
+    Literal(u64),
+    Fixed(u32),
+    Vbr(u32),
+    Array,
+    Char6,
+    Blob,
+}
+
+/// Reads the bits of a bitstream, least significant first.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, width: u32) -> Option<u64> {
+        let mut value = 0;
+        for i in 0..width.min(64) {
+            let byte = *self.data.get(self.pos / 8)?;
+            value |= u64::from((byte >> (self.pos % 8)) & 1) << i;
+            self.pos += 1;
+        }
+        Some(value)
+    }
+
+    fn vbr(&mut self, width: u32) -> Option<u64> {
+        if width < 2 {
+            return self.read(width);
+        }
+        let high = 1 << (width - 1);
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let piece = self.read(width)?;
+            value |= (piece & (high - 1)).checked_shl(shift)?;
+            if piece & high == 0 {
+                return Some(value);
+            }
+            shift += width - 1;
+        }
+    }
+
+    fn align32(&mut self) {
+        self.pos = self.pos.next_multiple_of(32);
+    }
+
+    fn char6(&mut self) -> Option<u64> {
+        let value = self.read(6)? as u8;
+        Some(u64::from(match value {
+            0..=25 => b'a' + value,
+            26..=51 => b'A' + value - 26,
+            52..=61 => b'0' + value - 52,
+            62 => b'.',
+            _ => b'_',
+        }))
+    }
+
+    fn scalar(&mut self, op: Op) -> Option<u64> {
+        match op {
+            Op::Literal(value) => Some(value),
+            Op::Fixed(width) => self.read(width),
+            Op::Vbr(width) => self.vbr(width),
+            Op::Char6 => self.char6(),
+            Op::Array | Op::Blob => None,
+        }
+    }
+}
+
+/// Returns the producer of `bitcode`, like `LLVM19.1.7-rust-1.86.0-stable`, as recorded in its
+/// identification block, or `None` if it has none.
+pub(crate) fn bitcode_producer(bitcode: &[u8]) -> Option<String> {
+    let bitcode = match bitcode.get(..4)?.try_into().map(u32::from_le_bytes) {
+        // The wrapper header is made of the magic, the version, and the offset and the size of
+        // the bitcode.
+        Ok(WRAPPER_MAGIC) => {
+            let field = |index: usize| {
+                let bytes = bitcode.get(index * 4..index * 4 + 4)?;
+                Some(u32::from_le_bytes(bytes.try_into().ok()?) as usize)
+            };
+            let offset = field(2)?;
+            bitcode.get(offset..offset.checked_add(field(3)?)?)?
+        }
+        _ => bitcode,
+    };
+    if !bitcode.starts_with(BITCODE_MAGIC) {
+        return None;
+    }
+
+    // The identification block is the first block of the stream.
+    let mut bits = Bits {
+        data: bitcode,
+        pos: BITCODE_MAGIC.len() * 8,
+    };
+    if bits.read(2)? != ENTER_SUBBLOCK || bits.vbr(8)? != IDENTIFICATION_BLOCK_ID {
+        return None;
+    }
+    let width = bits.vbr(4)? as u32;
+    bits.align32();
+    let _block_words = bits.read(32)?;
+
+    let mut abbrevs = Vec::new();
+    loop {
+        let record = match bits.read(width)? {
+            END_BLOCK | ENTER_SUBBLOCK => return None,
+            DEFINE_ABBREV => {
+                let count = bits.vbr(5)?;
+                let mut ops = Vec::new();
+                for _ in 0..count {
+                    let op = if bits.read(1)? == 1 {
+                        Op::Literal(bits.vbr(8)?)
+                    } else {
+                        match bits.read(3)? {
+                            1 => Op::Fixed(bits.vbr(5)? as u32),
+                            2 => Op::Vbr(bits.vbr(5)? as u32),
+                            3 => Op::Array,
+                            4 => Op::Char6,
+                            5 => Op::Blob,
+                            _ => return None,
+                        }
+                    };
+                    ops.push(op);
+                }
+                abbrevs.push(ops);
+                continue;
+            }
+            UNABBREV_RECORD => {
+                let code = bits.vbr(6)?;
+                let count = bits.vbr(6)?;
+                let mut record = vec![code];
+                for _ in 0..count {
+                    record.push(bits.vbr(6)?);
+                }
+                record
+            }
+            id => {
+                let ops = abbrevs.get(usize::try_from(id - 4).ok()?)?;
+                let mut record = Vec::new();
+                let mut ops = ops.iter().copied();
+                while let Some(op) = ops.next() {
+                    match op {
+                        Op::Array => {
+                            let element = ops.next()?;
+                            for _ in 0..bits.vbr(6)? {
+                                record.push(bits.scalar(element)?);
+                            }
+                        }
+                        Op::Blob => {
+                            let len = bits.vbr(6)?;
+                            bits.align32();
+                            for _ in 0..len {
+                                record.push(bits.read(8)?);
+                            }
+                            bits.align32();
+                        }
+                        op => record.push(bits.scalar(op)?),
+                    }
+                }
+                record
+            }
+        };
+        if let [IDENTIFICATION_CODE_STRING, producer @ ..] = record.as_slice() {
+            return producer
+                .iter()
+                .map(|&c| u8::try_from(c).ok().map(char::from))
+                .collect();
+        }
+    }
+}
+
+/// Returns the major version of the LLVM that produced bitcode, from its `producer`.
+pub(crate) fn producer_llvm_major(producer: &str) -> Option<u32> {
+    let version = producer.strip_prefix("LLVM")?;
+    let end = version
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(version.len());
+    version[..end].parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Writes the bits of a bitstream, least significant first.
+    #[derive(Default)]
+    struct BitWriter {
+        data: Vec<u8>,
+        pos: usize,
+    }
+
+    impl BitWriter {
+        fn write(&mut self, value: u64, width: u32) {
+            for i in 0..width {
+                if self.pos % 8 == 0 {
+                    self.data.push(0);
+                }
+                self.data[self.pos / 8] |= (((value >> i) & 1) as u8) << (self.pos % 8);
+                self.pos += 1;
+            }
+        }
+
+        fn vbr(&mut self, mut value: u64, width: u32) {
+            let high = 1 << (width - 1);
+            while value >= high {
+                self.write((value & (high - 1)) | high, width);
+                value >>= width - 1;
+            }
+            self.write(value, width);
+        }
+
+        fn align32(&mut self) {
+            while self.pos % 32 != 0 {
+                self.write(0, 1);
+            }
+        }
+    }
+
+    fn identification_block(write_string: impl FnOnce(&mut BitWriter)) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        for &byte in BITCODE_MAGIC {
+            w.write(byte.into(), 8);
+        }
+        w.write(ENTER_SUBBLOCK, 2);
+        w.vbr(IDENTIFICATION_BLOCK_ID, 8);
+        w.vbr(5, 4);
+        w.align32();
+        w.write(0, 32);
+        write_string(&mut w);
+        w.data
+    }
+
+    #[test]
+    fn test_bitcode_producer() {
+        // As written by LLVM when the producer is made of char6 characters.
+        let bitcode = identification_block(|w| {
+            w.write(DEFINE_ABBREV, 5);
+            w.vbr(3, 5);
+            w.write(1, 1);
+            w.vbr(IDENTIFICATION_CODE_STRING, 8);
+            w.write(0, 1);
+            w.write(3, 3);
+            w.write(0, 1);
+            w.write(4, 3);
+            w.write(4, 5);
+            let producer = [37, 37, 47, 38, 53, 61, 62, 53, 62, 59];
+            w.vbr(producer.len() as u64, 6);
+            for c in producer {
+                w.write(c, 6);
+            }
+        });
+        assert_eq!(bitcode_producer(&bitcode).as_deref(), Some("LLVM19.1.7"));
+
+        // Otherwise, the producer is in an unabbreviated record.
+        let producer = "LLVM20.1.1-rust-1.87.0-stable";
+        let bitcode = identification_block(|w| {
+            w.write(UNABBREV_RECORD, 5);
+            w.vbr(IDENTIFICATION_CODE_STRING, 6);
+            w.vbr(producer.len() as u64, 6);
+            for c in producer.bytes() {
+                w.vbr(c.into(), 6);
+            }
+        });
+        assert_eq!(bitcode_producer(&bitcode).as_deref(), Some(producer));
+
+        let mut wrapped = [WRAPPER_MAGIC, 0, 20, bitcode.len() as u32, 0]
+            .iter()
+            .flat_map(|field| field.to_le_bytes())
+            .collect::<Vec<_>>();
+        wrapped.extend(&bitcode);
+        assert_eq!(bitcode_producer(&wrapped).as_deref(), Some(producer));
+
+        assert_eq!(bitcode_producer(b"BC\xc0\xde"), None);
+        assert_eq!(bitcode_producer(b"\x7fELF"), None);
+    }
+
+    #[test]
+    fn test_producer_llvm_major() {
+        assert_eq!(producer_llvm_major("LLVM19.1.7"), Some(19));
+        assert_eq!(
+            producer_llvm_major("LLVM20.1.1-rust-1.87.0-stable"),
+            Some(20)
+        );
+        assert_eq!(producer_llvm_major("APPLE_1_1500.3.9.4_0"), None);
+    }
+}
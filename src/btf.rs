@@ -0,0 +1,327 @@
+use thiserror::Error;
+
+/// Magic number of the `.BTF` and `.BTF.ext` headers.
+pub(crate) const BTF_MAGIC: u16 = 0xeb9f;
+
+/// Kind of pointer types.
+pub(crate) const BTF_KIND_PTR: usize = 2;
+/// Kind of array types.
+pub(crate) const BTF_KIND_ARRAY: usize = 3;
+/// Kind of struct types.
+pub(crate) const BTF_KIND_STRUCT: usize = 4;
+/// Kind of union types.
+pub(crate) const BTF_KIND_UNION: usize = 5;
+/// Kind of enum types with 32-bit values.
+pub(crate) const BTF_KIND_ENUM: usize = 6;
+/// Kind of enum types with 64-bit values.
+pub(crate) const BTF_KIND_ENUM64: usize = 19;
+/// Kind of the type of a global variable.
+pub(crate) const BTF_KIND_VAR: usize = 14;
+/// Kind of the type of a data section, listing its variables.
+pub(crate) const BTF_KIND_DATASEC: usize = 15;
+
+/// The `.BTF` or `.BTF.ext` section is malformed.
+#[derive(Debug)]
+pub(crate) struct MalformedBtf(pub(crate) &'static str);
+
+/// Error analyzing an object file.
+#[derive(Debug, Error)]
+pub enum ObjectError {
+    /// The object file couldn't be parsed.
+    #[error("failed to parse object: {0}")]
+    ParseError(#[from] object::Error),
+
+    /// The `.BTF` or `.BTF.ext` section is malformed.
+    #[error("malformed {0} section")]
+    InvalidBtf(&'static str),
+}
+
+impl From<MalformedBtf> for ObjectError {
+    fn from(MalformedBtf(section): MalformedBtf) -> Self {
+        Self::InvalidBtf(section)
+    }
+}
+
+pub(crate) struct Reader<'data> {
+    pub(crate) data: &'data [u8],
+    pub(crate) little_endian: bool,
+    pub(crate) section: &'static str,
+}
+
+impl Reader<'_> {
+    pub(crate) fn u16(&self, offset: usize) -> Result<u16, MalformedBtf> {
+        let bytes = self
+            .data
+            .get(offset..offset + 2)
+            .ok_or(MalformedBtf(self.section))?;
+        let bytes = bytes.try_into().unwrap();
+        Ok(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    pub(crate) fn u32(&self, offset: usize) -> Result<usize, MalformedBtf> {
+        let bytes = self
+            .data
+            .get(offset..offset + 4)
+            .ok_or(MalformedBtf(self.section))?;
+        let bytes = bytes.try_into().unwrap();
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        } as usize)
+    }
+}
+
+/// A type of `.BTF`, laid out as `struct btf_type`.
+pub(crate) struct BtfType {
+    pub(crate) name_off: usize,
+    pub(crate) kind: usize,
+    pub(crate) vlen: usize,
+    /// The size of the type, or the type it refers to, depending on its kind.
+    pub(crate) size_or_type: usize,
+    /// Offset in `.BTF` of the data following the type, whose size depends on its kind.
+    pub(crate) data: usize,
+}
+
+/// The types and strings of a `.BTF` section.
+pub(crate) struct Btf<'data> {
+    pub(crate) reader: Reader<'data>,
+    strings: &'data [u8],
+    /// The types, by type id minus one.
+    pub(crate) types: Vec<BtfType>,
+}
+
+impl<'data> Btf<'data> {
+    pub(crate) fn parse(data: &'data [u8], little_endian: bool) -> Result<Self, MalformedBtf> {
+        let reader = Reader {
+            data,
+            little_endian,
+            section: ".BTF",
+        };
+        if reader.u16(0)? != BTF_MAGIC {
+            return Err(MalformedBtf(".BTF"));
+        }
+        let hdr_len = reader.u32(4)?;
+        let (type_off, type_len) = (reader.u32(8)?, reader.u32(12)?);
+        let (str_off, str_len) = (reader.u32(16)?, reader.u32(20)?);
+        let strings = data
+            .get(hdr_len + str_off..hdr_len + str_off + str_len)
+            .ok_or(MalformedBtf(".BTF"))?;
+
+        let mut types = Vec::new();
+        let mut offset = hdr_len + type_off;
+        let end = offset + type_len;
+        while offset < end {
+            let name_off = reader.u32(offset)?;
+            let info = reader.u32(offset + 4)?;
+            let kind = (info >> 24) & 0x1f;
+            let vlen = info & 0xffff;
+            // Every type is followed by data whose size depends on its kind.
+            let extra = match kind {
+                // INT, VAR and DECL_TAG
+                1 | BTF_KIND_VAR | 17 => 4,
+                // ARRAY
+                3 => 12,
+                // STRUCT, UNION, DATASEC and ENUM64
+                4 | 5 | BTF_KIND_DATASEC | 19 => vlen * 12,
+                // ENUM and FUNC_PROTO
+                6 | 13 => vlen * 8,
+                _ => 0,
+            };
+            types.push(BtfType {
+                name_off,
+                kind,
+                vlen,
+                size_or_type: reader.u32(offset + 8)?,
+                data: offset + 12,
+            });
+            offset += 12 + extra;
+        }
+        Ok(Self {
+            reader,
+            strings,
+            types,
+        })
+    }
+
+    pub(crate) fn string(&self, offset: usize) -> String {
+        let string = self.strings.get(offset..).unwrap_or_default();
+        let len = string.iter().position(|&b| b == 0).unwrap_or(string.len());
+        String::from_utf8_lossy(&string[..len]).into_owned()
+    }
+
+    pub(crate) fn type_by_id(&self, type_id: usize) -> Option<&BtfType> {
+        type_id
+            .checked_sub(1)
+            .and_then(|index| self.types.get(index))
+    }
+
+    pub(crate) fn type_name(&self, type_id: usize) -> String {
+        match self.type_by_id(type_id) {
+            Some(ty) if ty.name_off != 0 => self.string(ty.name_off),
+            Some(_) => "<anon>".into(),
+            None => format!("<type {type_id}>"),
+        }
+    }
+
+    /// Follows the typedefs and the const, volatile, restrict and type tag qualifiers of
+    /// `type_id` to the type they refer to.
+    pub(crate) fn skip_modifiers(&self, mut type_id: usize) -> usize {
+        // Bounded by the number of types, in case of reference cycles.
+        for _ in 0..self.types.len() {
+            match self.type_by_id(type_id) {
+                // TYPEDEF, VOLATILE, CONST, RESTRICT and TYPE_TAG
+                Some(ty) if matches!(ty.kind, 8..=11 | 18) => type_id = ty.size_or_type,
+                _ => break,
+            }
+        }
+        type_id
+    }
+
+    /// The size of the values of `type_id`, or `None` if it has no size, like functions and void.
+    pub(crate) fn type_size(&self, mut type_id: usize) -> Option<usize> {
+        let mut len = 1usize;
+        // Bounded by the number of types, in case of reference cycles.
+        for _ in 0..self.types.len() {
+            let ty = self.type_by_id(self.skip_modifiers(type_id))?;
+            match ty.kind {
+                BTF_KIND_ARRAY => {
+                    let (element, elements) = self.array(ty).ok()?;
+                    len = len.checked_mul(elements)?;
+                    type_id = element;
+                }
+                BTF_KIND_PTR => return len.checked_mul(8),
+                // INT, STRUCT, UNION, ENUM, FLOAT and ENUM64
+                1 | BTF_KIND_STRUCT | BTF_KIND_UNION | BTF_KIND_ENUM | 16 | BTF_KIND_ENUM64 => {
+                    return len.checked_mul(ty.size_or_type)
+                }
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// The element type and the number of elements of an array type.
+    pub(crate) fn array(&self, ty: &BtfType) -> Result<(usize, usize), MalformedBtf> {
+        // Arrays are described by `struct btf_array`: type, index_type and nelems.
+        Ok((self.reader.u32(ty.data)?, self.reader.u32(ty.data + 8)?))
+    }
+
+    /// The names and types of the members of a struct or union, or the names and values of the
+    /// enumerators of an enum, truncated to 32 bits.
+    pub(crate) fn members(&self, ty: &BtfType) -> Result<Vec<(String, usize)>, MalformedBtf> {
+        let size = match ty.kind {
+            BTF_KIND_STRUCT | BTF_KIND_UNION | BTF_KIND_ENUM64 => 12,
+            BTF_KIND_ENUM => 8,
+            _ => return Ok(Vec::new()),
+        };
+        (0..ty.vlen)
+            .map(|index| {
+                let offset = ty.data + index * size;
+                Ok((
+                    self.string(self.reader.u32(offset)?),
+                    self.reader.u32(offset + 4)?,
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Merges the split BTF `split`, like the BTF of a kernel module, into its `base`, like the BTF of
+/// the kernel, returning raw BTF with the types and strings of `split` after those of `base`.
+///
+/// The type ids and string offsets of split BTF start after those of its base, so the sections
+/// are concatenated as they are.
+pub(crate) fn merge_split_btf(
+    base: &[u8],
+    split: &[u8],
+    little_endian: bool,
+) -> Result<Vec<u8>, MalformedBtf> {
+    fn sections(data: &[u8], little_endian: bool) -> Result<(&[u8], &[u8]), MalformedBtf> {
+        let reader = Reader {
+            data,
+            little_endian,
+            section: ".BTF",
+        };
+        if reader.u16(0)? != BTF_MAGIC {
+            return Err(MalformedBtf(".BTF"));
+        }
+        let hdr_len = reader.u32(4)?;
+        let (type_off, type_len) = (reader.u32(8)?, reader.u32(12)?);
+        let (str_off, str_len) = (reader.u32(16)?, reader.u32(20)?);
+        let section = |offset: usize, len: usize| {
+            data.get(hdr_len + offset..hdr_len + offset + len)
+                .ok_or(MalformedBtf(".BTF"))
+        };
+        Ok((section(type_off, type_len)?, section(str_off, str_len)?))
+    }
+    let (base_types, base_strings) = sections(base, little_endian)?;
+    let (split_types, split_strings) = sections(split, little_endian)?;
+
+    let u32 = |value: usize| {
+        let value = value as u32;
+        if little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        }
+    };
+    let types_len = base_types.len() + split_types.len();
+    let mut btf = if little_endian {
+        BTF_MAGIC.to_le_bytes()
+    } else {
+        BTF_MAGIC.to_be_bytes()
+    }
+    .to_vec();
+    // Version 1, no flags.
+    btf.extend([1, 0]);
+    for value in [
+        24,
+        0,
+        types_len,
+        types_len,
+        base_strings.len() + split_strings.len(),
+    ] {
+        btf.extend(u32(value));
+    }
+    for section in [base_types, split_types, base_strings, split_strings] {
+        btf.extend(section);
+    }
+    Ok(btf)
+}
+
+/// Builders of raw BTF, for the tests of the analyses reading it.
+#[cfg(test)]
+pub(crate) mod test {
+    use super::BTF_MAGIC;
+
+    pub(crate) fn u16s(values: &[u16]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    pub(crate) fn u32s(values: &[u32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    /// Returns a little-endian `.BTF` section made of `types`, laid out as `struct btf_type`
+    /// followed by their data, and of `strings`.
+    pub(crate) fn raw_btf(types: &[u8], strings: &[u8]) -> Vec<u8> {
+        let mut btf = u16s(&[BTF_MAGIC]);
+        btf.extend([1, 0]);
+        btf.extend(u32s(&[24, 0, types.len() as u32, types.len() as u32]));
+        btf.extend(u32s(&[strings.len() as u32]));
+        btf.extend(types);
+        btf.extend(strings);
+        btf
+    }
+}
@@ -0,0 +1,88 @@
+use object::{Object as _, ObjectSection as _};
+
+use crate::btf::{ObjectError, Reader, BTF_MAGIC};
+
+/// Offset of `func_info_len` in the `.BTF.ext` header.
+const FUNC_INFO_LEN_OFFSET: usize = 12;
+/// Offset of `line_info_len` in the `.BTF.ext` header.
+const LINE_INFO_LEN_OFFSET: usize = 20;
+
+/// Drops the func info and/or the line info of the `.BTF.ext` section of `object`, keeping the
+/// types of `.BTF` and the CO-RE relocations. Objects without `.BTF.ext` are left untouched.
+///
+/// The records are dropped from the header of the section, so loaders ignore them, but they stay
+/// in the section.
+pub fn strip_btf_ext(
+    object: &mut [u8],
+    func_info: bool,
+    line_info: bool,
+) -> Result<(), ObjectError> {
+    let (range, little_endian) = {
+        let file = object::File::parse(&*object)?;
+        let Some(section) = file.section_by_name(".BTF.ext") else {
+            return Ok(());
+        };
+        let Some((offset, size)) = section.file_range() else {
+            return Ok(());
+        };
+        (
+            offset as usize..(offset + size) as usize,
+            file.is_little_endian(),
+        )
+    };
+    let btf_ext = object
+        .get_mut(range)
+        .ok_or(ObjectError::InvalidBtf(".BTF.ext"))?;
+
+    let reader = Reader {
+        data: btf_ext,
+        little_endian,
+        section: ".BTF.ext",
+    };
+    if reader.u16(0)? != BTF_MAGIC || reader.u32(4)? < LINE_INFO_LEN_OFFSET + 4 {
+        return Err(ObjectError::InvalidBtf(".BTF.ext"));
+    }
+    for (strip, offset) in [
+        (func_info, FUNC_INFO_LEN_OFFSET),
+        (line_info, LINE_INFO_LEN_OFFSET),
+    ] {
+        if strip {
+            btf_ext[offset..offset + 4].fill(0);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use object::{write, Architecture, BinaryFormat, Endianness, SectionKind};
+
+    use super::*;
+    use crate::btf::test::{u16s, u32s};
+
+    #[test]
+    fn test_strip_btf_ext() {
+        // magic, version, flags, hdr_len, func_info_off, func_info_len, line_info_off,
+        // line_info_len, followed by the records, which aren't read.
+        let header = [
+            u16s(&[BTF_MAGIC]),
+            vec![1, 0],
+            u32s(&[24, 0, 8, 8, 16]),
+            vec![0; 24],
+        ]
+        .concat();
+        let mut object =
+            write::Object::new(BinaryFormat::Elf, Architecture::Bpf, Endianness::Little);
+        let section = object.add_section(vec![], b".BTF.ext".to_vec(), SectionKind::Debug);
+        let _: u64 = object.append_section_data(section, &header, 4);
+        let mut object = object.write().unwrap();
+
+        strip_btf_ext(&mut object, false, true).unwrap();
+
+        let file = object::File::parse(object.as_slice()).unwrap();
+        let data = file.section_by_name(".BTF.ext").unwrap().data().unwrap();
+        let len = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        assert_eq!(len(FUNC_INFO_LEN_OFFSET), 8);
+        assert_eq!(len(LINE_INFO_LEN_OFFSET), 0);
+    }
+}
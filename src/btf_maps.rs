@@ -0,0 +1,221 @@
+use std::fmt;
+
+use object::{Object as _, ObjectSection as _};
+
+use crate::{
+    btf::{
+        Btf, MalformedBtf, ObjectError, BTF_KIND_ARRAY, BTF_KIND_DATASEC, BTF_KIND_PTR,
+        BTF_KIND_STRUCT,
+    },
+    maps::{self, MapDef},
+};
+
+/// A problem found in a map defined in the `.maps` section.
+#[derive(Debug, PartialEq)]
+pub struct BtfMapProblem {
+    /// Name of the map.
+    pub map: String,
+    /// Whether the loader or the kernel rejects the map. Other problems are likely mistakes, but
+    /// the map can still be loaded.
+    pub error: bool,
+    pub message: String,
+}
+
+impl fmt::Display for BtfMapProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { map, message, .. } = self;
+        write!(f, "map `{map}`: {message}")
+    }
+}
+
+/// Checks the maps defined in the `.maps` section of `object`, from their BTF definition, like
+/// libbpf and aya read them: their type, their `max_entries`, and their key and value sizes,
+/// given by `key_size` and `value_size` or by the types of `key` and `value`.
+///
+/// Objects without BTF or without `.maps` have no such maps, and are left to the checks of legacy
+/// map definitions done at link time.
+pub fn check_btf_maps(object: &object::File<'_>) -> Result<Vec<BtfMapProblem>, ObjectError> {
+    let Some(btf) = object.section_by_name(".BTF") else {
+        return Ok(Vec::new());
+    };
+    let btf = Btf::parse(btf.data()?, object.is_little_endian())?;
+
+    let mut problems = Vec::new();
+    let mut pinned = Vec::new();
+    let datasecs = btf
+        .types
+        .iter()
+        .filter(|ty| ty.kind == BTF_KIND_DATASEC && btf.string(ty.name_off) == ".maps");
+    for datasec in datasecs {
+        for index in 0..datasec.vlen {
+            // Variables are described by `struct btf_var_secinfo`.
+            let var = btf.reader.u32(datasec.data + index * 12)?;
+            let map = btf.type_name(var);
+            let mut problem = |error, message: String| {
+                problems.push(BtfMapProblem {
+                    map: map.clone(),
+                    error,
+                    message,
+                })
+            };
+            let Some(definition) = btf
+                .type_by_id(var)
+                .and_then(|var| btf.type_by_id(btf.skip_modifiers(var.size_or_type)))
+                .filter(|ty| ty.kind == BTF_KIND_STRUCT)
+            else {
+                problem(true, "the definition isn't a struct".into());
+                continue;
+            };
+
+            let mut def = MapDef {
+                map_type: 0,
+                key_size: 0,
+                value_size: 0,
+                max_entries: 0,
+                pinning: 0,
+            };
+            let (mut key, mut value) = (None, None);
+            for (name, type_id) in btf.members(definition)? {
+                match name.as_str() {
+                    "type" => def.map_type = integer(&btf, type_id)?,
+                    "key_size" => def.key_size = integer(&btf, type_id)?,
+                    "value_size" => def.value_size = integer(&btf, type_id)?,
+                    "max_entries" => def.max_entries = integer(&btf, type_id)?,
+                    "pinning" => def.pinning = integer(&btf, type_id)?,
+                    "key" => key = pointee_size(&btf, type_id),
+                    "value" => value = pointee_size(&btf, type_id),
+                    _ => {}
+                }
+            }
+            for (field, size, btf_size) in [
+                ("key", &mut def.key_size, key),
+                ("value", &mut def.value_size, value),
+            ] {
+                let Some(btf_size) = btf_size else {
+                    continue;
+                };
+                if *size != 0 && *size != btf_size {
+                    problem(
+                        true,
+                        format!("{field}_size is {size}, but the {field} type is {btf_size} bytes"),
+                    );
+                }
+                *size = btf_size;
+            }
+            for map_problem in maps::check(&def) {
+                problem(map_problem.is_error(), map_problem.to_string());
+            }
+            if def.is_pinned() {
+                pinned.push(map);
+            }
+        }
+    }
+
+    // Maps of `.maps` are pinned under their name.
+    let pinned = pinned
+        .iter()
+        .map(|map| (map.as_str(), map.as_str()))
+        .collect::<Vec<_>>();
+    for (map, problem) in maps::check_pinning(&pinned) {
+        problems.push(BtfMapProblem {
+            map: map.to_owned(),
+            error: false,
+            message: problem.to_string(),
+        });
+    }
+    Ok(problems)
+}
+
+/// Reads an integer field of a map definition, declared with `__uint(name, value)` as a pointer
+/// to an array of `value` elements.
+fn integer(btf: &Btf<'_>, type_id: usize) -> Result<u32, MalformedBtf> {
+    let array = btf
+        .type_by_id(type_id)
+        .filter(|ty| ty.kind == BTF_KIND_PTR)
+        .and_then(|ptr| btf.type_by_id(ptr.size_or_type))
+        .filter(|ty| ty.kind == BTF_KIND_ARRAY);
+    match array {
+        Some(array) => Ok(btf.array(array)?.1 as u32),
+        None => Err(MalformedBtf(".BTF")),
+    }
+}
+
+/// The size of the type of a field of a map definition declared with `__type(name, type)`, as a
+/// pointer to the type.
+fn pointee_size(btf: &Btf<'_>, type_id: usize) -> Option<u32> {
+    let ptr = btf
+        .type_by_id(type_id)
+        .filter(|ty| ty.kind == BTF_KIND_PTR)?;
+    btf.type_size(ptr.size_or_type)
+        .and_then(|size| size.try_into().ok())
+}
+
+#[cfg(test)]
+mod test {
+    use object::{write, Architecture, BinaryFormat, Endianness, SectionKind};
+
+    use super::*;
+    use crate::btf::test::{raw_btf, u32s};
+
+    #[test]
+    fn test_check_btf_maps() {
+        // Strings: "", "int", "type", "key", "value", "max_entries", "key_size", "EVENTS",
+        // "COUNTS", ".maps".
+        let strings = b"\0int\0type\0key\0value\0max_entries\0key_size\0EVENTS\0COUNTS\0.maps\0";
+        let types = [
+            // 1: int
+            u32s(&[1, 1 << 24, 4, 32]),
+            // 2, 3: int[1], int (*)[1]
+            u32s(&[0, 3 << 24, 0, 1, 1, 1]),
+            u32s(&[0, 2 << 24, 2]),
+            // 4, 5: int[1024], int (*)[1024]
+            u32s(&[0, 3 << 24, 0, 1, 1, 1024]),
+            u32s(&[0, 2 << 24, 4]),
+            // 6, 7: int[8], int (*)[8]
+            u32s(&[0, 3 << 24, 0, 1, 1, 8]),
+            u32s(&[0, 2 << 24, 6]),
+            // 8: int *
+            u32s(&[0, 2 << 24, 1]),
+            // 9: a hash map of 1024 int keys and values, as `type`, `key`, `value` and
+            // `max_entries` members.
+            u32s(&[0, (4 << 24) | 4, 32]),
+            u32s(&[5, 3, 0, 10, 8, 64, 14, 8, 128, 20, 5, 192]),
+            // 10: a hash map with an 8 bytes `key_size`, an int `key`, and no `max_entries`.
+            u32s(&[0, (4 << 24) | 3, 24]),
+            u32s(&[5, 3, 0, 32, 7, 64, 10, 8, 128]),
+            // 11, 12: the EVENTS and COUNTS variables.
+            u32s(&[41, 14 << 24, 9, 1]),
+            u32s(&[48, 14 << 24, 10, 1]),
+            // 13: the .maps DATASEC.
+            u32s(&[55, (15 << 24) | 2, 56, 11, 0, 32, 12, 32, 24]),
+        ]
+        .concat();
+        let mut object =
+            write::Object::new(BinaryFormat::Elf, Architecture::Bpf, Endianness::Little);
+        let section = object.add_section(vec![], b".BTF".to_vec(), SectionKind::Other);
+        let _: u64 = object.append_section_data(section, &raw_btf(&types, strings), 4);
+        let object = object.write().unwrap();
+        let object = object::File::parse(object.as_slice()).unwrap();
+
+        let problems = check_btf_maps(&object).unwrap();
+        assert_eq!(
+            problems,
+            [
+                BtfMapProblem {
+                    map: "COUNTS".into(),
+                    error: true,
+                    message: "key_size is 8, but the key type is 4 bytes".into(),
+                },
+                BtfMapProblem {
+                    map: "COUNTS".into(),
+                    error: true,
+                    message: "max_entries is 0".into(),
+                },
+            ]
+        );
+        assert_eq!(
+            problems[0].to_string(),
+            "map `COUNTS`: key_size is 8, but the key type is 4 bytes"
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use std::fmt;
+
+use object::{Object as _, ObjectSection as _, ObjectSymbol as _};
+
+use crate::btf::{Btf, ObjectError, BTF_KIND_DATASEC, BTF_KIND_VAR};
+
+/// A value of a BTF DATASEC that doesn't match the layout of the ELF object.
+#[derive(Debug, PartialEq)]
+pub enum DatasecMismatch {
+    /// The size of the DATASEC isn't the size of its section.
+    SectionSize { section: String, btf: u64, elf: u64 },
+    /// The offset of a variable isn't the value of its symbol.
+    VariableOffset {
+        section: String,
+        variable: String,
+        btf: u64,
+        elf: u64,
+    },
+    /// The size of a variable isn't the size of its symbol.
+    VariableSize {
+        section: String,
+        variable: String,
+        btf: u64,
+        elf: u64,
+    },
+}
+
+impl fmt::Display for DatasecMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SectionSize { section, btf, elf } => write!(
+                f,
+                "DATASEC {section} has size {btf}, but the section is {elf} bytes"
+            ),
+            Self::VariableOffset {
+                section,
+                variable,
+                btf,
+                elf,
+            } => write!(
+                f,
+                "variable `{variable}` of DATASEC {section} has offset {btf}, but its symbol is at \
+                 offset {elf}"
+            ),
+            Self::VariableSize {
+                section,
+                variable,
+                btf,
+                elf,
+            } => write!(
+                f,
+                "variable `{variable}` of DATASEC {section} has size {btf}, but its symbol is \
+                 {elf} bytes"
+            ),
+        }
+    }
+}
+
+/// Checks that the DATASECs of the `.BTF` section of `object` match the layout of the sections
+/// they describe. Loaders create the maps of data sections from their DATASEC, so mismatches make
+/// them fail to create the maps.
+///
+/// DATASECs whose size is 0 are left for the loader to size. DATASECs of sections the object
+/// doesn't have, like `.kconfig` and `.ksyms`, describe externs and are skipped.
+pub fn check_datasecs(object: &object::File<'_>) -> Result<Vec<DatasecMismatch>, ObjectError> {
+    let Some(btf) = object.section_by_name(".BTF") else {
+        return Ok(Vec::new());
+    };
+    let btf = Btf::parse(btf.data()?, object.is_little_endian())?;
+
+    let mut mismatches = Vec::new();
+    for datasec in btf.types.iter().filter(|ty| ty.kind == BTF_KIND_DATASEC) {
+        let section_name = btf.string(datasec.name_off);
+        let Some(section) = object.section_by_name(&section_name) else {
+            continue;
+        };
+        let size = datasec.size_or_type as u64;
+        if size != 0 && size != section.size() {
+            mismatches.push(DatasecMismatch::SectionSize {
+                section: section_name.clone(),
+                btf: size,
+                elf: section.size(),
+            });
+        }
+
+        for index in 0..datasec.vlen {
+            // Variables are described by `struct btf_var_secinfo`.
+            let secinfo = datasec.data + index * 12;
+            let var = btf.reader.u32(secinfo)?;
+            let offset = btf.reader.u32(secinfo + 4)? as u64;
+            let size = btf.reader.u32(secinfo + 8)? as u64;
+            if btf.type_by_id(var).map(|ty| ty.kind) != Some(BTF_KIND_VAR) {
+                continue;
+            }
+            let variable = btf.type_name(var);
+            let Some(symbol) = object.symbols().find(|symbol| {
+                symbol.section_index() == Some(section.index())
+                    && symbol.name().is_ok_and(|name| name == variable)
+            }) else {
+                continue;
+            };
+            if offset != symbol.address() {
+                mismatches.push(DatasecMismatch::VariableOffset {
+                    section: section_name.clone(),
+                    variable: variable.clone(),
+                    btf: offset,
+                    elf: symbol.address(),
+                });
+            }
+            if symbol.size() != 0 && size != symbol.size() {
+                mismatches.push(DatasecMismatch::VariableSize {
+                    section: section_name.clone(),
+                    variable,
+                    btf: size,
+                    elf: symbol.size(),
+                });
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod test {
+    use object::{
+        write::{self, Symbol, SymbolSection},
+        Architecture, BinaryFormat, Endianness, SectionKind, SymbolFlags, SymbolKind, SymbolScope,
+    };
+
+    use super::*;
+    use crate::btf::test::{raw_btf, u32s};
+
+    #[test]
+    fn test_check_datasecs() {
+        let mut object =
+            write::Object::new(BinaryFormat::Elf, Architecture::Bpf, Endianness::Little);
+        let data = object.add_section(vec![], b".data".to_vec(), SectionKind::Data);
+        let _: u64 = object.append_section_data(data, &[0; 8], 4);
+        for (name, value) in [("COUNTER", 0), ("TOTAL", 4)] {
+            let _: write::SymbolId = object.add_symbol(Symbol {
+                name: name.as_bytes().to_vec(),
+                value,
+                size: 4,
+                kind: SymbolKind::Data,
+                scope: SymbolScope::Dynamic,
+                weak: false,
+                section: SymbolSection::Section(data),
+                flags: SymbolFlags::None,
+            });
+        }
+
+        // Strings: "", ".data", "COUNTER", "TOTAL".
+        let strings = b"\0.data\0COUNTER\0TOTAL\0";
+        // Two global VARs, and a DATASEC placing both at offset 0.
+        let types = [
+            u32s(&[7, 14 << 24, 0, 1]),
+            u32s(&[15, 14 << 24, 0, 1]),
+            u32s(&[1, (15 << 24) | 2, 8, 1, 0, 4, 2, 0, 4]),
+        ]
+        .concat();
+        let section = object.add_section(vec![], b".BTF".to_vec(), SectionKind::Other);
+        let _: u64 = object.append_section_data(section, &raw_btf(&types, strings), 4);
+
+        let object = object.write().unwrap();
+        let object = object::File::parse(object.as_slice()).unwrap();
+        let mismatches = check_datasecs(&object).unwrap();
+        assert_eq!(
+            mismatches,
+            [DatasecMismatch::VariableOffset {
+                section: ".data".into(),
+                variable: "TOTAL".into(),
+                btf: 0,
+                elf: 4,
+            }]
+        );
+        assert_eq!(
+            mismatches[0].to_string(),
+            "variable `TOTAL` of DATASEC .data has offset 0, but its symbol is at offset 4"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Names are formatted in LLVM callbacks and in the threads linking the inputs, far from the linker
+// options, so the setting is global like the LLVM command line options.
+static SHOW_MANGLED: AtomicBool = AtomicBool::new(false);
+
+/// Set whether diagnostics and reports show the mangled name of Rust symbols after their
+/// demangled name. Disabled by default.
+pub fn set_show_mangled_names(enable: bool) {
+    SHOW_MANGLED.store(enable, Ordering::Relaxed)
+}
+
+/// Returns the name of `symbol` as shown in diagnostics and reports: demangled without its hash
+/// if it's a Rust symbol, followed by the mangled name if enabled with
+/// [`set_show_mangled_names`].
+pub fn demangle(symbol: &str) -> String {
+    match rustc_demangle::try_demangle(symbol) {
+        Ok(demangled) if SHOW_MANGLED.load(Ordering::Relaxed) => {
+            format!("{demangled:#} ({symbol})")
+        }
+        Ok(demangled) => format!("{demangled:#}"),
+        Err(_) => symbol.to_owned(),
+    }
+}
+
+/// Demangles the Rust symbols in `text`, eg the message of an LLVM diagnostic.
+pub(crate) fn demangle_text(text: &str) -> String {
+    let is_symbol_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '.');
+    let mut demangled = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(is_symbol_char) {
+        let (before, symbol) = rest.split_at(start);
+        let end = symbol.find(|c| !is_symbol_char(c)).unwrap_or(symbol.len());
+        let (symbol, after) = symbol.split_at(end);
+        demangled.push_str(before);
+        demangled.push_str(&demangle(symbol));
+        rest = after;
+    }
+    demangled.push_str(rest);
+    demangled
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MANGLED: &str = "_ZN4core9panicking5panic17h0123456789abcdefE";
+
+    #[test]
+    fn test_demangle() {
+        assert_eq!(demangle(MANGLED), "core::panicking::panic");
+        assert_eq!(demangle("memcpy"), "memcpy");
+    }
+
+    #[test]
+    fn test_demangle_text() {
+        assert_eq!(
+            demangle_text(&format!("in function {MANGLED} void (): unsupported")),
+            "in function core::panicking::panic void (): unsupported"
+        );
+        assert_eq!(demangle_text("no symbols here."), "no symbols here.");
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use crate::WarningKind;
+
+/// Severity of a [`Diagnostic`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The output can't be loaded, or [`Linker::has_errors`](crate::Linker::has_errors) is set.
+    Error,
+    /// The output may not behave as intended.
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        })
+    }
+}
+
+/// An error or warning reported by the linker.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Kind of the warning, also set for warnings turned into errors with
+    /// [`Linker::set_fatal_warnings`](crate::Linker::set_fatal_warnings).
+    pub kind: Option<WarningKind>,
+    pub message: String,
+    /// Demangled name of the symbol the diagnostic is about, if any.
+    pub symbol: Option<String>,
+    /// Source file and line the diagnostic is about, if the module has debug info.
+    pub location: Option<(String, u32)>,
+}
+
+impl Diagnostic {
+    pub(crate) fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            kind: None,
+            message: message.into(),
+            symbol: None,
+            location: None,
+        }
+    }
+
+    pub(crate) fn warning(kind: WarningKind, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            kind: Some(kind),
+            message: message.into(),
+            symbol: None,
+            location: None,
+        }
+    }
+
+    pub(crate) fn with_symbol(self, symbol: impl Into<String>) -> Self {
+        Self {
+            symbol: Some(symbol.into()),
+            ..self
+        }
+    }
+
+    pub(crate) fn with_location(self, location: Option<(String, u32)>) -> Self {
+        Self { location, ..self }
+    }
+
+    /// Formats the diagnostic as a JSON object, with `null` for the fields that aren't set.
+    pub fn to_json(&self) -> String {
+        let Self {
+            severity,
+            kind,
+            message,
+            symbol,
+            location,
+        } = self;
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "null".into());
+        let (file, line) = match location {
+            Some((file, line)) => (json_string(file), line.to_string()),
+            None => ("null".into(), "null".into()),
+        };
+        format!(
+            "{{\"severity\":\"{severity}\",\"kind\":{},\"message\":{},\"symbol\":{},\"file\":{file},\"line\":{line}}}",
+            optional(kind.map(|kind| json_string(&kind.to_string()))),
+            json_string(message),
+            optional(symbol.as_deref().map(json_string)),
+        )
+    }
+}
+
+/// Receives the diagnostics of a [`Linker`](crate::Linker), set with
+/// [`Linker::set_diagnostic_sink`](crate::Linker::set_diagnostic_sink).
+///
+/// Diagnostics are reported from the threads linking the inputs as well.
+pub trait DiagnosticSink: Send + Sync {
+    fn report(&self, diagnostic: Diagnostic);
+}
+
+/// A [`DiagnosticSink`] that collects the diagnostics, to be taken once the link is done.
+///
+/// Clones share the collected diagnostics, so a clone can be set as the sink of the linker while
+/// the original is kept to take them.
+#[derive(Clone, Default)]
+pub struct DiagnosticCollector {
+    diagnostics: Arc<Mutex<Vec<Diagnostic>>>,
+}
+
+impl DiagnosticCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the diagnostics collected so far, in the order they were reported, and clears them.
+    pub fn take(&self) -> Vec<Diagnostic> {
+        std::mem::take(&mut *self.diagnostics.lock().unwrap())
+    }
+}
+
+impl DiagnosticSink for DiagnosticCollector {
+    fn report(&self, diagnostic: Diagnostic) {
+        self.diagnostics.lock().unwrap().push(diagnostic);
+    }
+}
+
+/// An optimization remark issued by LLVM, for the passes set with
+/// [`Linker::set_remarks`](crate::Linker::set_remarks).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Remark {
+    /// Source file, line and column the remark is about, if the module has debug info.
+    pub location: Option<(String, u32, u32)>,
+    pub message: String,
+}
+
+impl Remark {
+    /// Parses a remark as LLVM prints it: `<file>:<line>:<column>: <message>`.
+    pub(crate) fn parse(description: &str) -> Self {
+        let description = description.trim_end();
+        let parsed = description
+            .split_once(": ")
+            .and_then(|(location, message)| {
+                let mut parts = location.rsplitn(3, ':');
+                let column = parts.next()?.parse().ok()?;
+                let line = parts.next()?.parse().ok()?;
+                let file = parts.next()?;
+                // Remarks about code without debug info have an unknown location.
+                let location = (file != "<unknown>").then(|| (file.to_owned(), line, column));
+                Some((location, message))
+            });
+        let (location, message) = parsed.unwrap_or((None, description));
+        Self {
+            location,
+            message: message.to_owned(),
+        }
+    }
+
+    /// Formats the remark as a JSON object, with `null` for the location if it isn't known.
+    pub fn to_json(&self) -> String {
+        let Self { location, message } = self;
+        let (file, line, column) = match location {
+            Some((file, line, column)) => (json_string(file), line.to_string(), column.to_string()),
+            None => ("null".into(), "null".into(), "null".into()),
+        };
+        format!(
+            "{{\"file\":{file},\"line\":{line},\"column\":{column},\"message\":{}}}",
+            json_string(message)
+        )
+    }
+
+    /// Formats the remark as a YAML document, like the remarks files of LLVM.
+    pub fn to_yaml(&self) -> String {
+        let Self { location, message } = self;
+        let mut yaml = String::from("--- !Remark\n");
+        if let Some((file, line, column)) = location {
+            yaml.push_str(&format!(
+                "DebugLoc: {{ File: {}, Line: {line}, Column: {column} }}\n",
+                yaml_string(file)
+            ));
+        }
+        yaml.push_str(&format!("Message: {}\n...\n", yaml_string(message)));
+        yaml
+    }
+}
+
+impl fmt::Display for Remark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { location, message } = self;
+        if let Some((file, line, column)) = location {
+            write!(f, "{file}:{line}:{column}: ")?;
+        }
+        write!(f, "{message}")
+    }
+}
+
+/// Formats `s` as a JSON string.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut json = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Formats `s` as a single-quoted YAML string.
+fn yaml_string(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        let diagnostic =
+            Diagnostic::warning(WarningKind::Verifier, "calls \"f\"\nthrough a pointer")
+                .with_symbol("prog")
+                .with_location(Some(("src/main.rs".into(), 12)));
+        assert_eq!(
+            diagnostic.to_json(),
+            r#"{"severity":"warning","kind":"verifier","message":"calls \"f\"\nthrough a pointer","symbol":"prog","file":"src/main.rs","line":12}"#
+        );
+        assert_eq!(
+            Diagnostic::error("undefined symbol `f`").to_json(),
+            r#"{"severity":"error","kind":null,"message":"undefined symbol `f`","symbol":null,"file":null,"line":null}"#
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_collector() {
+        let collector = DiagnosticCollector::new();
+        let sink: Box<dyn DiagnosticSink> = Box::new(collector.clone());
+        sink.report(Diagnostic::error("undefined symbol `f`"));
+        sink.report(Diagnostic::warning(WarningKind::Llvm, "unsupported"));
+        assert_eq!(
+            collector.take(),
+            [
+                Diagnostic::error("undefined symbol `f`"),
+                Diagnostic::warning(WarningKind::Llvm, "unsupported"),
+            ]
+        );
+        assert_eq!(collector.take(), []);
+    }
+
+    #[test]
+    fn test_remark() {
+        let remark =
+            Remark::parse("src/lib.rs:12:5: 'parse' not inlined into 'prog' because too costly\n");
+        assert_eq!(
+            remark,
+            Remark {
+                location: Some(("src/lib.rs".into(), 12, 5)),
+                message: "'parse' not inlined into 'prog' because too costly".into(),
+            }
+        );
+        assert_eq!(
+            remark.to_yaml(),
+            "--- !Remark\n\
+             DebugLoc: { File: 'src/lib.rs', Line: 12, Column: 5 }\n\
+             Message: '''parse'' not inlined into ''prog'' because too costly'\n\
+             ...\n"
+        );
+        assert_eq!(
+            Remark::parse("<unknown>:0:0: loop not unrolled").to_json(),
+            r#"{"file":null,"line":null,"column":null,"message":"loop not unrolled"}"#
+        );
+    }
+}
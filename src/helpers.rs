@@ -0,0 +1,81 @@
+/// Helpers that can only be called by programs with a GPL compatible license, by `enum
+/// bpf_func_id` value in the kernel's `include/uapi/linux/bpf.h`. These are the helpers whose
+/// `struct bpf_func_proto` sets `gpl_only`.
+const GPL_ONLY_HELPERS: &[(u64, &str)] = &[
+    (4, "bpf_probe_read"),
+    (6, "bpf_trace_printk"),
+    (25, "bpf_perf_event_output"),
+    (27, "bpf_get_stackid"),
+    (35, "bpf_get_current_task"),
+    (36, "bpf_probe_write_user"),
+    (45, "bpf_probe_read_str"),
+    (55, "bpf_perf_event_read_value"),
+    (56, "bpf_perf_prog_read_value"),
+    (58, "bpf_override_return"),
+    (67, "bpf_get_stack"),
+    (111, "bpf_skb_output"),
+    (112, "bpf_probe_read_user"),
+    (113, "bpf_probe_read_kernel"),
+    (114, "bpf_probe_read_user_str"),
+    (115, "bpf_probe_read_kernel_str"),
+    (119, "bpf_read_branch_records"),
+    (121, "bpf_xdp_output"),
+    (126, "bpf_seq_printf"),
+    (127, "bpf_seq_write"),
+    (141, "bpf_get_task_stack"),
+    (148, "bpf_copy_from_user"),
+    (158, "bpf_get_current_task_btf"),
+    (165, "bpf_snprintf"),
+    (169, "bpf_timer_init"),
+    (170, "bpf_timer_set_callback"),
+    (171, "bpf_timer_start"),
+    (172, "bpf_timer_cancel"),
+    (175, "bpf_task_pt_regs"),
+    (176, "bpf_get_branch_snapshot"),
+    (177, "bpf_trace_vprintk"),
+    (191, "bpf_copy_from_user_task"),
+];
+
+/// Licenses the kernel considers GPL compatible, from `license_is_gpl_compatible` in
+/// `include/linux/license.h`.
+const GPL_COMPATIBLE_LICENSES: &[&str] = &[
+    "GPL",
+    "GPL v2",
+    "GPL and additional rights",
+    "Dual BSD/GPL",
+    "Dual MIT/GPL",
+    "Dual MPL/GPL",
+];
+
+/// Returns the name of helper `id` if it can only be called by GPL compatible programs.
+pub(crate) fn gpl_only_helper(id: u64) -> Option<&'static str> {
+    GPL_ONLY_HELPERS
+        .iter()
+        .find_map(|&(helper_id, name)| (helper_id == id).then_some(name))
+}
+
+/// Whether the kernel lets programs with `license` call GPL-only helpers.
+pub(crate) fn is_gpl_compatible(license: &str) -> bool {
+    GPL_COMPATIBLE_LICENSES.contains(&license)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_gpl_only_helper() {
+        assert_eq!(gpl_only_helper(1), None);
+        assert_eq!(gpl_only_helper(6), Some("bpf_trace_printk"));
+        assert_eq!(gpl_only_helper(113), Some("bpf_probe_read_kernel"));
+    }
+
+    #[test]
+    fn test_is_gpl_compatible() {
+        assert!(is_gpl_compatible("GPL"));
+        assert!(is_gpl_compatible("Dual MIT/GPL"));
+        assert!(!is_gpl_compatible("MIT"));
+        assert!(!is_gpl_compatible("gpl"));
+        assert!(!is_gpl_compatible(""));
+    }
+}
@@ -0,0 +1,34 @@
+use std::{path::Path, time::Duration};
+
+use crate::OutputType;
+
+/// Size of the module at the end of a phase of a link.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ModuleStats {
+    /// Number of functions defined in the module.
+    pub functions: usize,
+    /// Number of global variables defined in the module.
+    pub globals: usize,
+}
+
+/// Receives the progress of a [`Linker`](crate::Linker), set with
+/// [`Linker::set_hooks`](crate::Linker::set_hooks).
+///
+/// The input hooks are called from the threads linking the inputs, so they can be called
+/// concurrently. Methods do nothing by default.
+pub trait LinkerHooks: Send + Sync {
+    /// Called before `input` is parsed and linked. Archive members are named `archive(member)`.
+    fn parse_start(&self, _input: &Path) {}
+
+    /// Called once `input` is parsed and linked, or failed to.
+    fn parse_end(&self, _input: &Path, _elapsed: Duration) {}
+
+    /// Called once all the inputs are linked into a single module.
+    fn link_done(&self, _stats: ModuleStats, _elapsed: Duration) {}
+
+    /// Called once the linked module is optimized.
+    fn optimize_done(&self, _stats: ModuleStats, _elapsed: Duration) {}
+
+    /// Called once an output is generated.
+    fn codegen_done(&self, _output_type: OutputType, _elapsed: Duration) {}
+}
@@ -0,0 +1,124 @@
+use std::{cmp::Reverse, fmt};
+
+use object::{Object as _, ObjectSection as _, ObjectSymbol as _, SectionKind, SymbolKind};
+
+use crate::{btf::ObjectError, demangle::demangle};
+
+const BPF_INSN_SIZE: u64 = 8;
+
+/// The number of instructions of a function.
+#[derive(Debug, PartialEq)]
+pub struct FunctionInsns {
+    /// Name of the function.
+    pub function: String,
+    /// Section of the function.
+    pub section: String,
+    /// Whether the function is a program, rather than a BPF function called by programs.
+    pub program: bool,
+    /// Number of instructions, counting `ld_imm64` instructions as two like the verifier does.
+    pub insns: u64,
+}
+
+impl fmt::Display for FunctionInsns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            function,
+            section,
+            program,
+            insns,
+        } = self;
+        let kind = if *program { "program" } else { "function" };
+        write!(f, "{kind} {function} in {section}: {insns} instructions")
+    }
+}
+
+/// Counts the instructions of the functions of `object`, by function symbol, largest first.
+///
+/// The verifier limits the instructions of programs, including the BPF functions they call, and
+/// the instructions it goes through when exploring their paths, so large functions are the
+/// first to look at when a program is rejected for being too complex.
+pub fn instruction_counts(object: &object::File<'_>) -> Result<Vec<FunctionInsns>, ObjectError> {
+    let mut functions = Vec::new();
+    for symbol in object.symbols() {
+        if symbol.kind() != SymbolKind::Text || symbol.size() == 0 {
+            continue;
+        }
+        let Some(section_index) = symbol.section_index() else {
+            continue;
+        };
+        let section = object.section_by_index(section_index)?;
+        if section.kind() != SectionKind::Text {
+            continue;
+        }
+        let section = section.name()?;
+        functions.push(FunctionInsns {
+            function: demangle(symbol.name()?),
+            section: section.to_owned(),
+            // Global functions in .text are subprograms rather than programs.
+            program: symbol.is_global() && section != ".text",
+            insns: symbol.size() / BPF_INSN_SIZE,
+        });
+    }
+    functions.sort_by_key(|function| Reverse(function.insns));
+    Ok(functions)
+}
+
+#[cfg(test)]
+mod test {
+    use object::{
+        write::{self, Symbol, SymbolSection},
+        Architecture, BinaryFormat, Endianness, SymbolFlags, SymbolScope,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_instruction_counts() {
+        let mut object =
+            write::Object::new(BinaryFormat::Elf, Architecture::Bpf, Endianness::Little);
+        let xdp = object.add_section(vec![], b"xdp".to_vec(), SectionKind::Text);
+        let _: u64 = object.append_section_data(xdp, &[0; 40], 8);
+        let text = object.add_section(vec![], b".text".to_vec(), SectionKind::Text);
+        let _: u64 = object.append_section_data(text, &[0; 16], 8);
+        for (name, section, size, scope) in [
+            ("xdp_prog", xdp, 40, SymbolScope::Dynamic),
+            ("helper", text, 16, SymbolScope::Compilation),
+        ] {
+            let _: write::SymbolId = object.add_symbol(Symbol {
+                name: name.as_bytes().to_vec(),
+                value: 0,
+                size,
+                kind: SymbolKind::Text,
+                scope,
+                weak: false,
+                section: SymbolSection::Section(section),
+                flags: SymbolFlags::None,
+            });
+        }
+        let object = object.write().unwrap();
+        let object = object::File::parse(object.as_slice()).unwrap();
+
+        let counts = instruction_counts(&object).unwrap();
+        assert_eq!(
+            counts,
+            [
+                FunctionInsns {
+                    function: "xdp_prog".into(),
+                    section: "xdp".into(),
+                    program: true,
+                    insns: 5,
+                },
+                FunctionInsns {
+                    function: "helper".into(),
+                    section: ".text".into(),
+                    program: false,
+                    insns: 2,
+                },
+            ]
+        );
+        assert_eq!(
+            counts[0].to_string(),
+            "program xdp_prog in xdp: 5 instructions"
+        );
+    }
+}
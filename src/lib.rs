@@ -35,6 +35,16 @@ assert_unique_used_features! {
     "llvm-21"
 }
 
+#[cfg(all(
+    feature = "static-llvm",
+    any(
+        feature = "rust-llvm-19",
+        feature = "rust-llvm-20",
+        feature = "rust-llvm-21"
+    )
+))]
+compile_error!("feature \"static-llvm\" cannot be used with the LLVM provided by rustc");
+
 #[cfg(feature = "llvm-19")]
 pub extern crate llvm_sys_19 as llvm_sys;
 #[cfg(feature = "llvm-20")]
@@ -42,7 +52,42 @@ pub extern crate llvm_sys_20 as llvm_sys;
 #[cfg(feature = "llvm-21")]
 pub extern crate llvm_sys_21 as llvm_sys;
 
+mod bitcode;
+mod btf;
+mod btf_ext;
+mod btf_maps;
+mod datasec;
+mod demangle;
+mod diagnostic;
+mod helpers;
+mod hooks;
+mod insns;
 mod linker;
 mod llvm;
+mod maps;
+mod outlining;
+mod probe;
+mod raw;
+mod relocations;
+mod skeleton;
+mod stack;
+mod strip_debug;
 
+pub use btf::ObjectError;
+pub use btf_ext::strip_btf_ext;
+pub use btf_maps::{check_btf_maps, BtfMapProblem};
+pub use datasec::{check_datasecs, DatasecMismatch};
+pub use demangle::{demangle, set_show_mangled_names};
+pub use diagnostic::{Diagnostic, DiagnosticCollector, DiagnosticSink, Remark, Severity};
+pub use hooks::{LinkerHooks, ModuleStats};
+pub use insns::{instruction_counts, FunctionInsns};
 pub use linker::*;
+pub use outlining::artificial_functions;
+pub use raw::{raw_programs, MapReference, RawProgram, RawProgramsError};
+pub use relocations::{
+    core_relocation_report, relocation_report, CoreRelocation, RelocationKindSummary,
+    RelocationReportError, SectionRelocations,
+};
+pub use skeleton::subskeleton;
+pub use stack::{stack_usage, ProgramStackUsage, StackFrame, MAX_CALL_FRAMES, MAX_STACK_SIZE};
+pub use strip_debug::{strip_debug, StripDebugError};
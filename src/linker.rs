@@ -1,24 +1,53 @@
 use std::{
     borrow::Cow,
-    collections::HashSet,
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap, HashSet},
     ffi::{CStr, CString, OsStr},
     fs::File,
-    io::{self, Read, Seek},
-    ops::Deref,
-    os::unix::ffi::OsStrExt as _,
+    io::{self, Read, Seek, Write},
+    marker::PhantomData,
+    num::NonZeroUsize,
+    ops::{Deref, Range},
+    os::{fd::BorrowedFd, unix::ffi::OsStrExt as _},
     path::{Path, PathBuf},
     str::{self, FromStr},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
 use ar::Archive;
 use llvm_sys::{
     error_handling::{LLVMEnablePrettyStackTrace, LLVMInstallFatalErrorHandler},
-    target_machine::LLVMCodeGenFileType,
+    prelude::{LLVMContextRef, LLVMModuleRef, LLVMValueRef},
+    target_machine::{
+        LLVMCodeGenFileType, LLVMCodeGenOptLevel, LLVMCodeModel, LLVMRelocMode,
+        LLVMTargetMachineRef,
+    },
 };
+use object::{Object as _, ObjectSection as _};
 use thiserror::Error;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info, info_span, warn, Span};
 
-use crate::llvm::{self, LLVMContext, LLVMModule, LLVMTargetMachine, MemoryBuffer};
+use crate::{
+    bitcode::{bitcode_producer, producer_llvm_major},
+    btf::ObjectError,
+    btf_maps::check_btf_maps,
+    datasec::{check_datasecs, DatasecMismatch},
+    demangle::{demangle, demangle_text},
+    diagnostic::{Diagnostic, DiagnosticSink, Remark, Severity},
+    helpers,
+    hooks::{LinkerHooks, ModuleStats},
+    insns::instruction_counts,
+    llvm::{
+        self, LLVMContext, LLVMModule, LLVMTarget, LLVMTargetMachine, LLVMTargetMachineBuilder,
+        LinkBitcodeError, MemoryBuffer, Message,
+    },
+    maps, probe,
+};
 
 /// Linker error
 #[derive(Debug, Error)]
@@ -27,10 +56,44 @@ pub enum LinkerError {
     #[error("invalid CPU {0}")]
     InvalidCpu(String),
 
+    /// Invalid warning kind.
+    #[error("invalid warning kind {0}")]
+    InvalidWarningKind(String),
+
+    /// Invalid line info level.
+    #[error("invalid line info level {0}")]
+    InvalidLineInfo(String),
+
+    /// Invalid duplicate symbols policy.
+    #[error(
+        "invalid duplicate symbols policy {0}, expected error, first-wins, last-wins or rename-second"
+    )]
+    InvalidDuplicateSymbols(String),
+
+    /// Invalid map section rule.
+    #[error("invalid map section rule {0}, expected <pattern>=maps or <pattern>=.maps")]
+    InvalidMapSectionRule(String),
+
     /// Invalid LLVM target.
     #[error("invalid LLVM target {0}")]
     InvalidTarget(String),
 
+    /// The byte order of the target isn't the one of the inputs.
+    #[error("target {0} is {1}, but the inputs are {2}, pass --target={3}")]
+    TargetByteOrderMismatch(String, &'static str, &'static str, &'static str),
+
+    /// The byte order of a module isn't the one of the modules linked before it.
+    #[error("{0} is {1}, unlike the inputs linked before it")]
+    InputByteOrderMismatch(PathBuf, &'static str),
+
+    /// The data layout of a module isn't the one of the modules linked before it.
+    #[error("{0} has data layout `{2}`, but the inputs linked before it have `{1}`")]
+    DataLayoutMismatch(PathBuf, String, String),
+
+    /// The target triple of a module isn't the one of the modules linked before it.
+    #[error("{0} has target triple `{2}`, but the inputs linked before it have `{1}`")]
+    TargetTripleMismatch(PathBuf, String, String),
+
     /// An IO Error occurred while linking a module.
     #[error("`{0}`: {1}")]
     IoError(PathBuf, io::Error),
@@ -39,14 +102,37 @@ pub enum LinkerError {
     #[error("invalid input file `{0}`")]
     InvalidInputType(PathBuf),
 
+    /// The module is not valid LLVM bitcode.
+    #[error("invalid bitcode in {0}")]
+    InvalidBitcode(PathBuf),
+
+    /// The module is bitcode LLVM can't parse, produced by another version of LLVM than the one
+    /// of the linker. Bitcode of older versions is upgraded when it's parsed, so this usually
+    /// means the bitcode is newer.
+    #[error("invalid bitcode in {0}: it was produced by {1}, but the linker uses LLVM {2}")]
+    IncompatibleBitcode(PathBuf, String, String),
+
     /// Linking a module failed.
     #[error("failure linking module {0}")]
     LinkModuleError(PathBuf),
 
+    /// The linked module is malformed, in the listed functions or outside of functions if there
+    /// are none. Returned by [`Linker::check`].
+    #[error("linked module is malformed (functions: {0:?}): {1}")]
+    MalformedModule(Vec<String>, String),
+
     /// Linking a module included in an archive failed.
     #[error("failure linking module {1} from {0}")]
     LinkArchiveModuleError(PathBuf, PathBuf),
 
+    /// Reading an archive failed before its last member.
+    #[error("failure reading archive {0}: {1}")]
+    ReadArchiveError(PathBuf, io::Error),
+
+    /// The inputs are larger than the limit set with [`Linker::set_input_size_limit`].
+    #[error("reading {0} brings the inputs to {1} bytes, more than the limit of {2} bytes")]
+    InputSizeLimitExceeded(PathBuf, u64, u64),
+
     /// Optimizing the BPF code failed.
     #[error("LLVMRunPasses failed: {0}")]
     OptimizeError(String),
@@ -56,8 +142,17 @@ pub enum LinkerError {
     EmitCodeError(String),
 
     /// Writing the bitcode failed.
-    #[error("LLVMWriteBitcodeToFile failed: {0}")]
-    WriteBitcodeError(io::Error),
+    #[error("writing bitcode failed: {0}")]
+    WriteBitcodeError(#[source] io::Error),
+
+    /// Checking the generated object file failed.
+    #[error("checking the generated object failed: {0}")]
+    CheckObjectError(#[source] ObjectError),
+
+    /// Parsing the generated object file failed.
+    #[cfg(feature = "aya-obj")]
+    #[error("parsing the generated object failed: {0}")]
+    ParseObjectError(#[source] aya_obj::ParseError),
 
     /// Writing the LLVM IR failed.
     #[error("LLVMPrintModuleToFile failed: {0}")]
@@ -74,6 +169,18 @@ pub enum LinkerError {
     /// LLVM cannot create a module for linking.
     #[error("failed to create module")]
     CreateModuleError,
+
+    /// Writing the output to a file descriptor or a writer failed.
+    #[error("failed to write output: {0}")]
+    WriteOutputError(#[source] io::Error),
+
+    /// Reading the generated object file failed.
+    #[error("reading the generated object failed: {0}")]
+    ReadObjectError(#[source] object::Error),
+
+    /// The generated object file has no BTF section to output.
+    #[error("the generated object has no {0} section")]
+    MissingBtfSection(&'static str),
 }
 
 /// BPF Cpu type
@@ -84,6 +191,10 @@ pub enum Cpu {
     V1,
     V2,
     V3,
+    V4,
+    /// The most recent version the running kernel supports, probed by loading small programs.
+    /// Unlike [`Cpu::Probe`], which is probed by LLVM, this also detects v4.
+    Native,
 }
 
 impl Cpu {
@@ -94,6 +205,8 @@ impl Cpu {
             Self::V1 => c"v1",
             Self::V2 => c"v2",
             Self::V3 => c"v3",
+            Self::V4 => c"v4",
+            Self::Native => probe::native_cpu().as_c_str(),
         }
     }
 }
@@ -106,6 +219,8 @@ impl std::fmt::Display for Cpu {
             Self::V1 => "v1",
             Self::V2 => "v2",
             Self::V3 => "v3",
+            Self::V4 => "v4",
+            Self::Native => "native",
         })
     }
 }
@@ -120,11 +235,127 @@ impl FromStr for Cpu {
             "v1" => Self::V1,
             "v2" => Self::V2,
             "v3" => Self::V3,
+            "v4" => Self::V4,
+            "native" => Self::Native,
             _ => return Err(LinkerError::InvalidCpu(s.to_string())),
         })
     }
 }
 
+/// Kind of warning issued by the linker
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    /// Warnings issued by LLVM.
+    Llvm,
+    /// Inputs ignored because they contain no embedded bitcode.
+    MissingBitcode,
+    /// Data sections and globals above the [`DataSizeLimits`].
+    DataSize,
+    /// Legacy map definitions that are likely mistakes.
+    Map,
+    /// Exported functions placed in `.text`.
+    TextFunction,
+    /// Types for which no debug info was emitted.
+    DebugInfo,
+    /// Maps of `.maps` that are likely mistakes, checked from their BTF definition.
+    BtfMap,
+    /// Code patterns that the verifier is likely to reject, reported when
+    /// [`Linker::set_verifier_lints`] is enabled.
+    Verifier,
+}
+
+impl WarningKind {
+    /// All the kinds of warnings.
+    pub const ALL: &[Self] = &[
+        Self::Llvm,
+        Self::MissingBitcode,
+        Self::DataSize,
+        Self::Map,
+        Self::TextFunction,
+        Self::DebugInfo,
+        Self::BtfMap,
+        Self::Verifier,
+    ];
+}
+
+impl std::fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            Self::Llvm => "llvm",
+            Self::MissingBitcode => "missing-bitcode",
+            Self::DataSize => "data-size",
+            Self::Map => "map",
+            Self::TextFunction => "text-function",
+            Self::DebugInfo => "debug-info",
+            Self::BtfMap => "btf-map",
+            Self::Verifier => "verifier",
+        })
+    }
+}
+
+impl FromStr for WarningKind {
+    type Err = LinkerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "llvm" => Self::Llvm,
+            "missing-bitcode" => Self::MissingBitcode,
+            "data-size" => Self::DataSize,
+            "map" => Self::Map,
+            "text-function" => Self::TextFunction,
+            "debug-info" => Self::DebugInfo,
+            "btf-map" => Self::BtfMap,
+            "verifier" => Self::Verifier,
+            _ => return Err(LinkerError::InvalidWarningKind(s.to_string())),
+        })
+    }
+}
+
+/// Issues the warnings of the linker, turning those of the kinds set with
+/// [`Linker::set_fatal_warnings`] into errors, and the errors found by its checks.
+#[derive(Default)]
+struct Warnings {
+    fatal: HashSet<WarningKind>,
+    // Set when a warning was turned into an error. Warnings are also issued by the threads linking
+    // the inputs, hence the atomic.
+    promoted: AtomicBool,
+    /// Where diagnostics are reported instead of being logged.
+    sink: Option<Arc<dyn DiagnosticSink>>,
+}
+
+impl Warnings {
+    fn warn(&self, kind: WarningKind, message: std::fmt::Arguments<'_>) {
+        self.report(Diagnostic::warning(kind, message.to_string()))
+    }
+
+    fn report(&self, mut diagnostic: Diagnostic) {
+        if let Some(kind) = diagnostic.kind {
+            if self.fatal.contains(&kind) {
+                self.promoted.store(true, Ordering::Relaxed);
+                diagnostic.severity = Severity::Error;
+            }
+        }
+        match &self.sink {
+            Some(sink) => sink.report(diagnostic),
+            None => log_diagnostic(&diagnostic),
+        }
+    }
+}
+
+fn log_diagnostic(diagnostic: &Diagnostic) {
+    let Diagnostic {
+        severity,
+        kind,
+        message,
+        ..
+    } = diagnostic;
+    match (severity, kind) {
+        (Severity::Error, Some(kind)) => error!("{} [{}]", message, kind),
+        (Severity::Error, None) => error!("{}", message),
+        (Severity::Warning, _) => warn!("{}", message),
+    }
+}
+
 /// Optimization level
 #[derive(Clone, Copy, Debug)]
 pub enum OptLevel {
@@ -136,9 +367,12 @@ pub enum OptLevel {
     Default,
     /// Aggressive optimizations. Equivalent to -O3.
     Aggressive,
-    /// Optimize for size. Equivalent to -Os.
+    /// Optimize for size. Equivalent to -Os, but also hoists the instructions common to both sides
+    /// of branches, since BPF programs are limited in instructions rather than in bytes.
     Size,
-    /// Aggressively optimize for size. Equivalent to -Oz.
+    /// Aggressively optimize for size. Equivalent to -Oz, but keeps the loop transformations of
+    /// -Os, since -Oz leaves loops with more branches for the verifier to explore, and hoists
+    /// instructions like [`OptLevel::Size`].
     SizeMin,
 }
 
@@ -151,19 +385,42 @@ pub struct BufferInput<'a> {
     bytes: &'a [u8],
 }
 
+pub struct ReaderInput<'a> {
+    name: &'a str,
+    reader: Box<dyn Read + 'a>,
+}
+
+/// An input of the linker: LLVM bitcode, an object file with embedded bitcode, or a static
+/// archive (`.a` or `.rlib`) of those.
+///
+/// The type of an input is detected from its contents. The members of archives are linked one by
+/// one, and the ones that aren't bitcode, like the metadata of rlibs, are ignored.
 pub enum LinkerInput<'a> {
     File(FileInput<'a>),
     Buffer(BufferInput<'a>),
+    Reader(ReaderInput<'a>),
 }
 
 impl<'a> LinkerInput<'a> {
+    /// Creates an input read from the file at `path`.
     pub fn new_from_file(path: &'a Path) -> Self {
         LinkerInput::File(FileInput { path })
     }
 
+    /// Creates an input from `bytes`, named `name` in diagnostics.
     pub fn new_from_buffer(name: &'a str, bytes: &'a [u8]) -> Self {
         LinkerInput::Buffer(BufferInput { name, bytes })
     }
+
+    /// Creates an input read from `reader`, like a pipe or a socket, named `name` in diagnostics.
+    ///
+    /// The input is read to the end when linking starts.
+    pub fn new_from_reader(name: &'a str, reader: impl Read + 'a) -> Self {
+        LinkerInput::Reader(ReaderInput {
+            name,
+            reader: Box::new(reader),
+        })
+    }
 }
 
 enum InputReader<'a> {
@@ -175,6 +432,10 @@ enum InputReader<'a> {
         name: &'a str,
         cursor: io::Cursor<&'a [u8]>,
     },
+    Stream {
+        name: &'a str,
+        cursor: io::Cursor<Vec<u8>>,
+    },
 }
 
 impl Seek for InputReader<'_> {
@@ -182,6 +443,7 @@ impl Seek for InputReader<'_> {
         match self {
             InputReader::File { file, .. } => file.seek(pos),
             InputReader::Buffer { cursor, .. } => cursor.seek(pos),
+            InputReader::Stream { cursor, .. } => cursor.seek(pos),
         }
     }
 }
@@ -191,6 +453,7 @@ impl Read for InputReader<'_> {
         match self {
             InputReader::File { file, .. } => file.read(buf),
             InputReader::Buffer { cursor, .. } => cursor.read(buf),
+            InputReader::Stream { cursor, .. } => cursor.read(buf),
         }
     }
 }
@@ -206,6 +469,8 @@ enum InputType {
     MachO,
     /// Archive file. (.a)
     Archive,
+    /// BPF assembly. (.s)
+    Assembly,
 }
 
 impl std::fmt::Display for InputType {
@@ -218,6 +483,7 @@ impl std::fmt::Display for InputType {
                 Self::Elf => "elf",
                 Self::MachO => "Mach-O",
                 Self::Archive => "archive",
+                Self::Assembly => "assembly",
             }
         )
     }
@@ -234,6 +500,372 @@ pub enum OutputType {
     LlvmAssembly,
     /// ELF object file.
     Object,
+    /// The `.BTF` section of the ELF object file, as a standalone file. Requires
+    /// [`LinkerOptions::btf`].
+    Btf,
+    /// The `.BTF.ext` section of the ELF object file, as a standalone file. Its strings are
+    /// stored in the `.BTF` section. Requires [`LinkerOptions::btf`].
+    BtfExt,
+}
+
+/// Destination of the linker output
+pub enum OutputSink<'a> {
+    /// Write the output to the file at the given path.
+    Path(&'a Path),
+    /// Write the output to the given file descriptor.
+    Fd(BorrowedFd<'a>),
+    /// Write the output to the given writer.
+    Writer(&'a mut dyn Write),
+    /// Write the output in chunks of at most `chunk_size` bytes to the given
+    /// [`ChunkedSink`], retrying each chunk up to `max_retries` times when the
+    /// sink reports a transient failure.
+    Chunked {
+        sink: &'a mut dyn ChunkedSink,
+        chunk_size: NonZeroUsize,
+        max_retries: u32,
+    },
+    /// Keep the output in memory and return it.
+    Buffer,
+}
+
+/// Error returned by a [`ChunkedSink`].
+#[derive(Debug)]
+pub enum ChunkError {
+    /// The chunk could not be written, but writing it again may succeed.
+    Transient(io::Error),
+    /// The chunk could not be written and the output must be abandoned.
+    Fatal(io::Error),
+}
+
+/// A destination that receives the linker output in chunks, eg an object
+/// store or a network filesystem.
+pub trait ChunkedSink {
+    /// Write `chunk` at `offset` bytes from the start of the output.
+    ///
+    /// Chunks are written in order. A chunk for which
+    /// [`ChunkError::Transient`] is returned is written again at the same
+    /// offset.
+    fn write_chunk(&mut self, offset: u64, chunk: &[u8]) -> Result<(), ChunkError>;
+
+    /// Called once all the chunks have been written.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn write_chunked(
+    data: &[u8],
+    sink: &mut dyn ChunkedSink,
+    chunk_size: NonZeroUsize,
+    max_retries: u32,
+) -> io::Result<()> {
+    let mut offset = 0;
+    for chunk in data.chunks(chunk_size.get()) {
+        let mut retries = 0;
+        loop {
+            match sink.write_chunk(offset, chunk) {
+                Ok(()) => break,
+                Err(ChunkError::Transient(err)) if retries < max_retries => {
+                    retries += 1;
+                    debug!(
+                        "retrying chunk at offset {} ({}/{}): {}",
+                        offset, retries, max_retries, err
+                    );
+                }
+                Err(ChunkError::Transient(err) | ChunkError::Fatal(err)) => return Err(err),
+            }
+        }
+        offset += chunk.len() as u64;
+    }
+    sink.finish()
+}
+
+/// Relocation model of the generated code
+#[derive(Clone, Copy, Debug, Default)]
+pub enum RelocModel {
+    /// The target's default relocation model.
+    #[default]
+    Default,
+    /// Non-relocatable code.
+    Static,
+    /// Position independent code.
+    Pic,
+    /// Relocatable external references, non-relocatable code.
+    DynamicNoPic,
+}
+
+/// Code model of the generated code
+#[derive(Clone, Copy, Debug, Default)]
+pub enum CodeModel {
+    /// The target's default code model.
+    #[default]
+    Default,
+    Tiny,
+    Small,
+    Kernel,
+    Medium,
+    Large,
+}
+
+/// Optimization level of the code generator
+#[derive(Clone, Copy, Debug, Default)]
+pub enum CodeGenOptLevel {
+    /// No optimizations. Equivalent to -O0.
+    None,
+    /// Equivalent to -O1.
+    Less,
+    /// Equivalent to -O2.
+    Default,
+    /// Equivalent to -O3.
+    #[default]
+    Aggressive,
+}
+
+/// Options used to create the target machine, in addition to the target,
+/// CPU and CPU features set in [`LinkerOptions`].
+///
+/// # Example
+///
+/// ```rust
+/// # use bpf_linker::{RelocModel, TargetMachineOptions};
+/// let options = TargetMachineOptions::new().reloc_model(RelocModel::Pic);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TargetMachineOptions {
+    reloc_model: RelocModel,
+    code_model: CodeModel,
+    opt_level: CodeGenOptLevel,
+    abi: Option<CString>,
+    asm_verbose: Option<bool>,
+}
+
+impl TargetMachineOptions {
+    /// Create the default target machine options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the relocation model. Defaults to [`RelocModel::Default`].
+    pub fn reloc_model(mut self, reloc_model: RelocModel) -> Self {
+        self.reloc_model = reloc_model;
+        self
+    }
+
+    /// Set the code model. Defaults to [`CodeModel::Default`].
+    pub fn code_model(mut self, code_model: CodeModel) -> Self {
+        self.code_model = code_model;
+        self
+    }
+
+    /// Set the optimization level of the code generator. Defaults to
+    /// [`CodeGenOptLevel::Aggressive`].
+    pub fn opt_level(mut self, opt_level: CodeGenOptLevel) -> Self {
+        self.opt_level = opt_level;
+        self
+    }
+
+    /// Set the target ABI. Defaults to the target's default ABI.
+    pub fn abi(mut self, abi: CString) -> Self {
+        self.abi = Some(abi);
+        self
+    }
+
+    /// Enable or disable comments in the generated assembly. Defaults to
+    /// enabled.
+    pub fn asm_verbose(mut self, verbose: bool) -> Self {
+        self.asm_verbose = Some(verbose);
+        self
+    }
+}
+
+/// Sizes of data above which the linker warns.
+///
+/// The `.bss`, `.data` and `.rodata` sections are loaded as maps, so large globals turn into
+/// large map allocations at load time.
+#[derive(Clone, Copy, Debug)]
+pub struct DataSizeLimits {
+    /// Size in bytes of a `.bss`, `.data` or `.rodata` section. `None` disables the warning.
+    pub section: Option<u64>,
+    /// Size in bytes of a single global variable in one of those sections. `None` disables the
+    /// warning.
+    pub global: Option<u64>,
+}
+
+impl Default for DataSizeLimits {
+    fn default() -> Self {
+        Self {
+            section: Some(1024 * 1024),
+            global: Some(512 * 1024),
+        }
+    }
+}
+
+/// How the linker handles symbols that are referenced but defined in none of the inputs.
+///
+/// Symbols resolved by the loader, like kfuncs, kernel symbols and kernel config variables, are
+/// always allowed.
+#[derive(Clone, Debug, Default)]
+pub enum UndefinedSymbols {
+    /// Undefined symbols are errors.
+    #[default]
+    Deny,
+    /// Undefined symbols are allowed, and left for the loader to resolve.
+    Allow,
+    /// Only the given undefined symbols are allowed.
+    AllowOnly(HashSet<String>),
+}
+
+/// How the linker handles symbols that more than one input defines.
+///
+/// Only definitions with external linkage collide: weak and `linkonce` definitions are merged by
+/// LLVM whatever the policy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateSymbols {
+    /// Duplicate symbols are errors.
+    #[default]
+    Error,
+    /// The definition of the first input defining the symbol is kept.
+    FirstWins,
+    /// The definition of the last input defining the symbol is kept.
+    LastWins,
+    /// The definitions of the inputs after the first are renamed to `<symbol>.<n>`, so that the
+    /// code of each input defining the symbol keeps using its own definition.
+    RenameSecond,
+}
+
+impl std::fmt::Display for DuplicateSymbols {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            Self::Error => "error",
+            Self::FirstWins => "first-wins",
+            Self::LastWins => "last-wins",
+            Self::RenameSecond => "rename-second",
+        })
+    }
+}
+
+impl FromStr for DuplicateSymbols {
+    type Err = LinkerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "error" => Self::Error,
+            "first-wins" => Self::FirstWins,
+            "last-wins" => Self::LastWins,
+            "rename-second" => Self::RenameSecond,
+            _ => return Err(LinkerError::InvalidDuplicateSymbols(s.to_string())),
+        })
+    }
+}
+
+/// How much line info is emitted in `.BTF.ext`, when emitting BTF.
+///
+/// Line info lets the verifier log and tools like `bpftool prog dump` show the source of the
+/// instructions, but the verifier goes through all of it when loading programs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineInfo {
+    /// Line info whenever the source line of the instructions changes.
+    #[default]
+    Full,
+    /// Line info for the first instruction of each source line in a basic block, so that code
+    /// of several lines interleaved by the optimizations doesn't get line info for every switch
+    /// between the lines.
+    Statements,
+    /// Line info for the entry of functions only.
+    Entry,
+}
+
+impl std::fmt::Display for LineInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            Self::Full => "full",
+            Self::Statements => "statements",
+            Self::Entry => "entry",
+        })
+    }
+}
+
+impl FromStr for LineInfo {
+    type Err = LinkerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "full" => Self::Full,
+            "statements" => Self::Statements,
+            "entry" => Self::Entry,
+            _ => return Err(LinkerError::InvalidLineInfo(s.to_string())),
+        })
+    }
+}
+
+/// Section convention of map definitions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapSection {
+    /// The `maps` section, for legacy `struct bpf_map_def` definitions.
+    Legacy,
+    /// The `.maps` section, for BTF map definitions.
+    Btf,
+}
+
+impl std::fmt::Display for MapSection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            Self::Legacy => "maps",
+            Self::Btf => ".maps",
+        })
+    }
+}
+
+/// Places the maps whose name matches `pattern` in `section`.
+///
+/// Only the section of the maps changes, so their definitions must already be laid out as the
+/// loader expects for `section`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MapSectionRule {
+    /// Demangled name of the maps. `*` matches any sequence of characters.
+    pub pattern: String,
+    pub section: MapSection,
+}
+
+impl FromStr for MapSectionRule {
+    type Err = LinkerError;
+
+    /// Parses a `<pattern>=maps` or `<pattern>=.maps` rule.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pattern, section) = match s.rsplit_once('=') {
+            Some((pattern, "maps")) if !pattern.is_empty() => (pattern, MapSection::Legacy),
+            Some((pattern, ".maps")) if !pattern.is_empty() => (pattern, MapSection::Btf),
+            _ => return Err(LinkerError::InvalidMapSectionRule(s.to_string())),
+        };
+        Ok(Self {
+            pattern: pattern.to_string(),
+            section,
+        })
+    }
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any sequence of characters.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // There's always a first part, which is the whole pattern when it has no wildcard.
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts = parts.peekable();
+    if parts.peek().is_none() {
+        return rest.is_empty();
+    }
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    true
 }
 
 /// Options to configure the linker
@@ -274,18 +906,78 @@ pub struct Linker {
     context: LLVMContext,
     diagnostic_handler: llvm::InstalledDiagnosticHandler<DiagnosticHandler>,
     dump_module: Option<PathBuf>,
+    target_machine_options: TargetMachineOptions,
+    fast_isel: Option<bool>,
+    global_isel: Option<bool>,
+    machine_outliner: Option<bool>,
+    data_size_limits: DataSizeLimits,
+    warnings: Warnings,
+    undefined_symbols: UndefinedSymbols,
+    duplicate_symbols: DuplicateSymbols,
+    map_sections: Vec<MapSectionRule>,
+    const_propagation: bool,
+    identical_code_folding: bool,
+    gc_maps: bool,
+    line_info: LineInfo,
+    demangle_btf_names: bool,
+    input_size_limit: Option<u64>,
+    jobs: Option<NonZeroUsize>,
+    verifier_lints: bool,
+    datasec_check: bool,
+    hooks: Option<Arc<dyn LinkerHooks>>,
+    output_stats: bool,
+    outlining: bool,
+    remarks: Option<CString>,
+    passes: Option<CString>,
+    inline_threshold: Option<i32>,
+    unroll_max_count: Option<u32>,
+    // Set once the command line options of LLVM are set, at the first link.
+    llvm_args_parsed: Cell<bool>,
+    // Set when an error was reported outside of the diagnostic handler of `context`: by LLVM in
+    // one of the per-thread contexts used to link the inputs, or by the checks of the module and
+    // of the object.
+    link_errors: Cell<bool>,
+    stats: RefCell<LinkStats>,
 }
 
 impl Linker {
     /// Create a new linker instance with the given options.
     pub fn new(options: LinkerOptions) -> Self {
-        let (context, diagnostic_handler) = llvm_init(&options);
+        let (context, diagnostic_handler) = llvm_init();
 
         Self {
             options,
             context,
             diagnostic_handler,
             dump_module: None,
+            target_machine_options: TargetMachineOptions::default(),
+            fast_isel: None,
+            global_isel: None,
+            machine_outliner: None,
+            data_size_limits: DataSizeLimits::default(),
+            warnings: Warnings::default(),
+            undefined_symbols: UndefinedSymbols::default(),
+            duplicate_symbols: DuplicateSymbols::default(),
+            map_sections: Vec::new(),
+            const_propagation: true,
+            identical_code_folding: false,
+            gc_maps: false,
+            line_info: LineInfo::default(),
+            demangle_btf_names: false,
+            input_size_limit: None,
+            jobs: None,
+            verifier_lints: false,
+            datasec_check: true,
+            hooks: None,
+            output_stats: false,
+            outlining: true,
+            remarks: None,
+            passes: None,
+            inline_threshold: None,
+            unroll_max_count: None,
+            llvm_args_parsed: Cell::new(false),
+            link_errors: Cell::new(false),
+            stats: RefCell::default(),
         }
     }
 
@@ -296,12 +988,219 @@ impl Linker {
     /// - The directory is created if it does not already exist.
     /// - A "pre-opt.ll" file is written with the IR before optimization.
     /// - A "post-opt.ll" file is written with the IR after optimization.
+    /// - The optimized module is verified, and a warning is logged if it is malformed.
     pub fn set_dump_module_path(&mut self, path: impl AsRef<Path>) {
         self.dump_module = Some(path.as_ref().to_path_buf())
     }
 
+    /// Set the options used to create the target machine.
+    pub fn set_target_machine_options(&mut self, options: TargetMachineOptions) {
+        self.target_machine_options = options
+    }
+
+    /// Enable or disable the FastISel instruction selector. Defaults to the target's choice.
+    pub fn set_fast_isel(&mut self, enable: bool) {
+        self.fast_isel = Some(enable)
+    }
+
+    /// Enable or disable the GlobalISel instruction selector. Defaults to the target's choice.
+    pub fn set_global_isel(&mut self, enable: bool) {
+        self.global_isel = Some(enable)
+    }
+
+    /// Enable or disable the machine outliner of the code generator. Defaults to the target's
+    /// choice. See [`Linker::set_outlining`] to disable all the transforms that outline code.
+    pub fn set_machine_outliner(&mut self, enable: bool) {
+        self.machine_outliner = Some(enable)
+    }
+
+    /// Set the sizes of data above which the linker warns.
+    pub fn set_data_size_limits(&mut self, limits: DataSizeLimits) {
+        self.data_size_limits = limits
+    }
+
+    /// Set how symbols that are referenced but not defined are handled.
+    pub fn set_undefined_symbols(&mut self, undefined_symbols: UndefinedSymbols) {
+        self.undefined_symbols = undefined_symbols
+    }
+
+    /// Set how symbols defined by more than one input are handled.
+    pub fn set_duplicate_symbols(&mut self, duplicate_symbols: DuplicateSymbols) {
+        self.duplicate_symbols = duplicate_symbols
+    }
+
+    /// Set the rules placing maps in the `maps` or `.maps` section. The first rule matching a map
+    /// applies, and maps matched by none stay in their section.
+    pub fn set_map_sections(&mut self, rules: impl IntoIterator<Item = MapSectionRule>) {
+        self.map_sections = rules.into_iter().collect()
+    }
+
+    /// Enable or disable folding the values of global variables into the code using them, across
+    /// all the linked modules. Enabled by default.
+    ///
+    /// Disable it when the loader patches global variables before loading the programs, like
+    /// configuration constants in `.rodata`, so that the code reads their patched value.
+    pub fn set_const_propagation(&mut self, enable: bool) {
+        self.const_propagation = enable
+    }
+
+    /// Enable or disable merging identical functions after optimization. Call sites are updated to
+    /// call the function that's kept, and its BTF and func_info are the only ones emitted.
+    /// Disabled by default.
+    ///
+    /// Useful in programs using many instances of generic Rust functions, which often compile to
+    /// the same code.
+    pub fn set_identical_code_folding(&mut self, enable: bool) {
+        self.identical_code_folding = enable
+    }
+
+    /// Remove the maps that no function refers to after optimization, unless they're exported.
+    /// Disabled by default, since loaders create every map of the object, and programs loaded
+    /// separately may share them by pinning.
+    ///
+    /// Functions that aren't exported are always removed when no program calls them.
+    pub fn set_gc_maps(&mut self, enable: bool) {
+        self.gc_maps = enable
+    }
+
+    /// Set how much line info is emitted in `.BTF.ext` when emitting BTF.
+    pub fn set_line_info(&mut self, line_info: LineInfo) {
+        self.line_info = line_info
+    }
+
+    /// Enable or disable renaming the global variables that aren't exported and have a mangled Rust
+    /// name after their demangled name, when emitting BTF. BTF names variables after their symbol,
+    /// so tools like `bpftool map dump` then show eg `CONFIG` rather than its mangled name.
+    /// Disabled by default.
+    ///
+    /// Variables keep their mangled name when another symbol already has their demangled name.
+    pub fn set_demangle_btf_names(&mut self, enable: bool) {
+        self.demangle_btf_names = enable
+    }
+
+    /// Set the maximum total size of the inputs, in bytes, including the members of archives.
+    /// Linking fails with [`LinkerError::InputSizeLimitExceeded`] before any input is linked when
+    /// their sizes exceed it, rather than running out of memory later on. Unlimited by default.
+    ///
+    /// The linker only holds the inputs being linked in memory, but the linked module is usually
+    /// several times larger than all the inputs.
+    pub fn set_input_size_limit(&mut self, limit: Option<u64>) {
+        self.input_size_limit = limit
+    }
+
+    /// Set the number of threads parsing and linking the inputs, each in its own LLVM context.
+    /// Defaults to the available parallelism.
+    ///
+    /// The modules linked by each thread are then merged, which takes extra time and memory. With
+    /// a single job, the inputs are linked directly into the final module.
+    pub fn set_jobs(&mut self, jobs: Option<NonZeroUsize>) {
+        self.jobs = jobs
+    }
+
+    /// Enable or disable the warnings about code patterns that the verifier is likely to reject,
+    /// of kind [`WarningKind::Verifier`]: loops, large memory copies, calls through function
+    /// pointers and calls with more than 5 arguments. Disabled by default.
+    ///
+    /// The linked module is checked after optimization, and the warnings point to the source of
+    /// the code when it has debug info. The verifier may still accept the code, eg loops that it
+    /// can prove terminate.
+    pub fn set_verifier_lints(&mut self, enable: bool) {
+        self.verifier_lints = enable
+    }
+
+    /// Enable or disable the check that the DATASECs of the BTF of the generated objects match the
+    /// layout of the sections they describe, done when [`LinkerOptions::btf`] is set. Enabled by
+    /// default.
+    ///
+    /// Loaders create the maps of data sections from their DATASEC, so mismatches are reported as
+    /// errors.
+    pub fn set_datasec_check(&mut self, enable: bool) {
+        self.datasec_check = enable
+    }
+
+    /// Set where errors and warnings are reported, as [`Diagnostic`]s with the symbol and the
+    /// source location they are about when known, instead of being logged. Errors still make
+    /// [`Linker::has_errors`] return true.
+    pub fn set_diagnostic_sink(&mut self, sink: impl DiagnosticSink + 'static) {
+        let sink: Arc<dyn DiagnosticSink> = Arc::new(sink);
+        self.diagnostic_handler
+            .with_view(|h| *h.sink.borrow_mut() = Some(Arc::clone(&sink)));
+        self.warnings.sink = Some(sink);
+    }
+
+    /// Set the hooks called as the link progresses, eg to show progress or time the phases of the
+    /// link.
+    pub fn set_hooks(&mut self, hooks: impl LinkerHooks + 'static) {
+        self.hooks = Some(Arc::new(hooks));
+    }
+
+    /// Enable or disable the statistics of the generated objects returned by [`Linker::stats`]:
+    /// [`LinkStats::instructions`] and [`LinkStats::btf_size`]. Disabled by default, since the
+    /// objects are disassembled to count their instructions, and read back when written to a file.
+    pub fn set_output_stats(&mut self, enable: bool) {
+        self.output_stats = enable
+    }
+
+    /// Enable or disable the transforms that move code out of functions into new functions:
+    /// machine outlining, hot/cold splitting and partial inlining. Enabled by default.
+    ///
+    /// Some loaders reject the functions they create. This sets LLVM command line options, so it
+    /// only takes effect when called before the first link.
+    pub fn set_outlining(&mut self, enable: bool) {
+        self.outlining = enable
+    }
+
+    /// Collect the optimization remarks of the LLVM passes matching the regular expression
+    /// `passes`, eg `inline|loop-unroll`, including missed optimizations and the analysis
+    /// explaining them. See [`Linker::remarks`].
+    ///
+    /// This sets LLVM command line options, so it only takes effect when called before the first
+    /// link.
+    pub fn set_remarks(&mut self, passes: Option<CString>) {
+        self.remarks = passes
+    }
+
+    /// Set the pipeline of the new pass manager to run, eg `default<O2>,loop-unroll<O3>`, instead
+    /// of the default pipeline of the optimization level. Include the default pipeline, as the
+    /// first pass, to add passes to it.
+    pub fn set_passes(&mut self, pipeline: Option<CString>) {
+        self.passes = pipeline
+    }
+
+    /// Set the threshold of the inliner, overriding the one of the optimization level. Functions
+    /// whose cost is above the threshold aren't inlined, so lower values make smaller programs
+    /// and higher values give the verifier fewer calls to go through.
+    ///
+    /// The threshold also applies to the default pipelines included in the pipeline set with
+    /// [`Linker::set_passes`].
+    /// With LLVM 19, whose C API can't set it, it sets an LLVM command line option instead, so it
+    /// only takes effect when called before the first link.
+    pub fn set_inline_threshold(&mut self, threshold: Option<i32>) {
+        self.inline_threshold = threshold
+    }
+
+    /// Set the largest number of times a loop is unrolled. Limiting it keeps unrolled loops under
+    /// the instruction limits of the verifier.
+    ///
+    /// This sets an LLVM command line option, so it only takes effect when called before the
+    /// first link.
+    pub fn set_unroll_max_count(&mut self, count: Option<u32>) {
+        self.unroll_max_count = count
+    }
+
+    /// Set the kinds of warnings that are turned into errors, and reported by
+    /// [`Linker::has_errors`].
+    pub fn set_fatal_warnings(&mut self, kinds: impl IntoIterator<Item = WarningKind>) {
+        self.warnings.fatal = kinds.into_iter().collect();
+        let fatal_llvm_warnings = self.warnings.fatal.contains(&WarningKind::Llvm);
+        self.diagnostic_handler
+            .with_view(|h| h.fatal_warnings.set(fatal_llvm_warnings));
+    }
+
     /// Link and generate the output code to file.
     ///
+    /// Export symbols containing `*` are patterns, which export all the symbols they match.
+    ///
     /// # Example
     ///
     /// ```rust,no_run
@@ -351,8 +1250,8 @@ impl Linker {
         E: IntoIterator<Item = &'a str>,
         P: AsRef<Path>,
     {
-        let (linked_module, target_machine) = self.link(inputs, export_symbols)?;
-        codegen_to_file(
+        let (linked_module, target_machine) = self.link_module(inputs, export_symbols)?;
+        self.codegen_file(
             &linked_module,
             &target_machine,
             output.as_ref(),
@@ -361,7 +1260,11 @@ impl Linker {
         Ok(())
     }
 
-    /// Link and generate the output code to an in-memory buffer.
+    /// Link and generate several outputs, eg an object file and its assembly,
+    /// from a single link.
+    ///
+    /// Inputs are parsed, linked and optimized only once, regardless of the
+    /// number of outputs.
     ///
     /// # Example
     ///
@@ -370,7 +1273,6 @@ impl Linker {
     /// # use bpf_linker::{Cpu, Linker, LinkerInput, LinkerOptions, OptLevel, OutputType};
     /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
     /// let path = Path::new("/path/to/object-or-bitcode");
-    /// let bytes: &[u8] = &[]; // An in memory object/bitcode
     /// # let options = LinkerOptions {
     /// #     target: None,
     /// #     cpu: Cpu::Generic,
@@ -388,52 +1290,617 @@ impl Linker {
     ///
     /// let export_symbols = ["my_sym_1", "my_sym_2"];
     ///
-    /// let out_buf = linker.link_to_buffer(
+    /// linker.link_to_files(
+    ///     [LinkerInput::new_from_file(path)],
     ///     [
-    ///         LinkerInput::new_from_file(path),
-    ///         LinkerInput::new_from_buffer("my buffer", bytes), // In memory buffer needs a name
+    ///         (OutputType::Object, Path::new("/path/to/output.o")),
+    ///         (OutputType::Assembly, Path::new("/path/to/output.s")),
     ///     ],
-    ///     OutputType::Bitcode,
     ///     export_symbols,
     /// )?;
-    ///
-    /// // Use the buffer as slice of u8
-    /// let bytes = out_buf.as_slice();
-    /// println!("Linked {} bytes into memory)", bytes.len());
-    ///
     /// # Ok(())
     /// # }
     /// ```
-    pub fn link_to_buffer<'i, 'a, I, E>(
+    pub fn link_to_files<'i, 'o, 'a, I, O, E>(
         &self,
         inputs: I,
-        output_type: OutputType,
+        outputs: O,
+        export_symbols: E,
+    ) -> Result<(), LinkerError>
+    where
+        I: IntoIterator<Item = LinkerInput<'i>>,
+        O: IntoIterator<Item = (OutputType, &'o Path)>,
+        E: IntoIterator<Item = &'a str>,
+    {
+        let outputs = outputs.into_iter().collect::<Vec<_>>();
+        let (linked_module, target_machine) = self.link_module(inputs, export_symbols)?;
+        for (index, (output_type, output)) in outputs.iter().enumerate() {
+            let is_last = index + 1 == outputs.len();
+            match output_type {
+                // Code generation modifies the module, so unless this is the last output, it's
+                // done on a copy to keep the module intact for the following outputs.
+                OutputType::Assembly
+                | OutputType::Object
+                | OutputType::Btf
+                | OutputType::BtfExt
+                    if !is_last =>
+                {
+                    let module = linked_module.clone();
+                    self.codegen_file(&module, &target_machine, output, *output_type)?;
+                }
+                _ => self.codegen_file(&linked_module, &target_machine, output, *output_type)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Link and generate an object file, along with an object file for each program section in
+    /// `split_dir`, so that programs can be shipped independently.
+    ///
+    /// Each section object has the programs of the section, and the functions and maps they use.
+    /// It's named after the section, with `/` replaced by `_`, eg `xdp_foo.o` for `xdp/foo`.
+    /// Returns the paths of the section objects.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use std::{collections::HashSet, path::Path, borrow::Cow, ffi::CString};
+    /// # use bpf_linker::{Cpu, Linker, LinkerInput, LinkerOptions, OptLevel, OutputType};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let path = Path::new("/path/to/object-or-bitcode");
+    /// # let options = LinkerOptions {
+    /// #     target: None,
+    /// #     cpu: Cpu::Generic,
+    /// #     cpu_features: CString::default(),
+    /// #     optimize: OptLevel::Default,
+    /// #     unroll_loops: false,
+    /// #     ignore_inline_never: false,
+    /// #     llvm_args: vec![],
+    /// #     disable_expand_memcpy_in_order: false,
+    /// #     disable_memory_builtins: false,
+    /// #     allow_bpf_trap: false,
+    /// #     btf: false,
+    /// # };
+    /// # let linker = Linker::new(options);
+    ///
+    /// let export_symbols = ["my_sym_1", "my_sym_2"];
+    ///
+    /// let sections = linker.link_to_split_files(
+    ///     [LinkerInput::new_from_file(path)],
+    ///     Path::new("/path/to/output.o"),
+    ///     Path::new("/path/to/sections"),
+    ///     export_symbols,
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn link_to_split_files<'i, 'a, I, E>(
+        &self,
+        inputs: I,
+        output: &Path,
+        split_dir: &Path,
+        export_symbols: E,
+    ) -> Result<Vec<PathBuf>, LinkerError>
+    where
+        I: IntoIterator<Item = LinkerInput<'i>>,
+        E: IntoIterator<Item = &'a str>,
+    {
+        let (linked_module, target_machine) = self.link_module(inputs, export_symbols)?;
+        std::fs::create_dir_all(split_dir)
+            .map_err(|err| LinkerError::IoError(split_dir.to_owned(), err))?;
+
+        let mut paths = Vec::new();
+        for section in llvm::program_sections(&linked_module) {
+            let mut module = linked_module.clone();
+            llvm::retain_program_section(&target_machine, &mut module, &section)
+                .map_err(LinkerError::OptimizeError)?;
+            let path = split_dir.join(format!("{}.o", section.replace('/', "_")));
+            self.codegen_file(&module, &target_machine, &path, OutputType::Object)?;
+            paths.push(path);
+        }
+        self.codegen_file(&linked_module, &target_machine, output, OutputType::Object)?;
+        Ok(paths)
+    }
+
+    /// Link and generate an output file for each of the given target triples.
+    ///
+    /// The inputs are linked and optimized once, and the resulting module is
+    /// copied into a fresh LLVM context for each output, so that code generation
+    /// for one target doesn't affect the others. Optimizations run for the
+    /// target selected by [`LinkerOptions::target`], so the code must not depend
+    /// on properties that differ between the targets, like byte order.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use std::{ffi::CString, path::Path};
+    /// # use bpf_linker::{Cpu, Linker, LinkerInput, LinkerOptions, OptLevel, OutputType};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let path = Path::new("/path/to/object-or-bitcode");
+    /// # let options = LinkerOptions {
+    /// #     target: None,
+    /// #     cpu: Cpu::Generic,
+    /// #     cpu_features: CString::default(),
+    /// #     optimize: OptLevel::Default,
+    /// #     unroll_loops: false,
+    /// #     ignore_inline_never: false,
+    /// #     llvm_args: vec![],
+    /// #     disable_expand_memcpy_in_order: false,
+    /// #     disable_memory_builtins: false,
+    /// #     allow_bpf_trap: false,
+    /// #     btf: false,
+    /// # };
+    /// # let linker = Linker::new(options);
+    ///
+    /// let export_symbols = ["my_sym_1", "my_sym_2"];
+    ///
+    /// linker.link_to_files_for_targets(
+    ///     [LinkerInput::new_from_file(path)],
+    ///     [
+    ///         (c"bpfel", OutputType::Object, Path::new("/path/to/output.bpfel.o")),
+    ///         (c"bpfeb", OutputType::Object, Path::new("/path/to/output.bpfeb.o")),
+    ///     ],
+    ///     export_symbols,
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn link_to_files_for_targets<'i, 'o, 'a, I, O, E>(
+        &self,
+        inputs: I,
+        outputs: O,
+        export_symbols: E,
+    ) -> Result<(), LinkerError>
+    where
+        I: IntoIterator<Item = LinkerInput<'i>>,
+        O: IntoIterator<Item = (&'o CStr, OutputType, &'o Path)>,
+        E: IntoIterator<Item = &'a str>,
+    {
+        let (linked_module, _) = self.link_module(inputs, export_symbols)?;
+        for (triple, output_type, output) in outputs {
+            let target = llvm::target_from_triple(triple)
+                .map_err(|_msg| LinkerError::InvalidTarget(triple.to_string_lossy().to_string()))?;
+            let target_machine = build_target_machine(self, triple, target)?;
+
+            let mut context = LLVMContext::new();
+            let diagnostic_handler = context.set_diagnostic_handler(DiagnosticHandler {
+                fatal_warnings: Cell::new(self.warnings.fatal.contains(&WarningKind::Llvm)),
+                inline_asm_sites: RefCell::new(llvm::inline_asm_sites(&linked_module)),
+                function_sources: RefCell::new(llvm::function_sources(&linked_module)),
+                sink: RefCell::new(self.warnings.sink.clone()),
+                ..Default::default()
+            });
+            {
+                let mut module = linked_module
+                    .clone_into(&context)
+                    .ok_or(LinkerError::CreateModuleError)?;
+                module.set_target(triple, &target_machine);
+                self.codegen_file(&module, &target_machine, output, output_type)?;
+            }
+            if diagnostic_handler.with_view(|h| h.has_errors) {
+                self.link_errors.set(true);
+            }
+        }
+        Ok(())
+    }
+
+    /// Link and generate the output code to an in-memory buffer.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use std::{collections::HashSet, path::Path, borrow::Cow, ffi::CString};
+    /// # use bpf_linker::{Cpu, Linker, LinkerInput, LinkerOptions, OptLevel, OutputType};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let path = Path::new("/path/to/object-or-bitcode");
+    /// let bytes: &[u8] = &[]; // An in memory object/bitcode
+    /// # let options = LinkerOptions {
+    /// #     target: None,
+    /// #     cpu: Cpu::Generic,
+    /// #     cpu_features: CString::default(),
+    /// #     optimize: OptLevel::Default,
+    /// #     unroll_loops: false,
+    /// #     ignore_inline_never: false,
+    /// #     llvm_args: vec![],
+    /// #     disable_expand_memcpy_in_order: false,
+    /// #     disable_memory_builtins: false,
+    /// #     allow_bpf_trap: false,
+    /// #     btf: false,
+    /// # };
+    /// # let linker = Linker::new(options);
+    ///
+    /// let export_symbols = ["my_sym_1", "my_sym_2"];
+    ///
+    /// let out_buf = linker.link_to_buffer(
+    ///     [
+    ///         LinkerInput::new_from_file(path),
+    ///         LinkerInput::new_from_buffer("my buffer", bytes), // In memory buffer needs a name
+    ///     ],
+    ///     OutputType::Bitcode,
+    ///     export_symbols,
+    /// )?;
+    ///
+    /// // Use the buffer as slice of u8
+    /// let bytes = out_buf.as_slice();
+    /// println!("Linked {} bytes into memory)", bytes.len());
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn link_to_buffer<'i, 'a, I, E>(
+        &self,
+        inputs: I,
+        output_type: OutputType,
         export_symbols: E,
     ) -> Result<LinkerOutput, LinkerError>
     where
         I: IntoIterator<Item = LinkerInput<'i>>,
         E: IntoIterator<Item = &'a str>,
     {
-        let (linked_module, target_machine) = self.link(inputs, export_symbols)?;
-        codegen_to_buffer(&linked_module, &target_machine, output_type)
+        let (linked_module, target_machine) = self.link_module(inputs, export_symbols)?;
+        self.codegen_buffer(&linked_module, &target_machine, output_type)
+    }
+
+    /// Link and verify the linked module without generating any output, to check that the inputs
+    /// link.
+    ///
+    /// Code generation is skipped, unless [`LinkerOptions::btf`] is set: BTF is generated by the
+    /// backend, so the object is then generated in memory and discarded, to report the errors of
+    /// BTF generation.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use std::{path::Path, ffi::CString};
+    /// # use bpf_linker::{Cpu, Linker, LinkerInput, LinkerOptions, OptLevel};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let path = Path::new("/path/to/object-or-bitcode");
+    /// # let options = LinkerOptions {
+    /// #     target: None,
+    /// #     cpu: Cpu::Generic,
+    /// #     cpu_features: CString::default(),
+    /// #     optimize: OptLevel::Default,
+    /// #     unroll_loops: false,
+    /// #     ignore_inline_never: false,
+    /// #     llvm_args: vec![],
+    /// #     disable_expand_memcpy_in_order: false,
+    /// #     disable_memory_builtins: false,
+    /// #     allow_bpf_trap: false,
+    /// #     btf: false,
+    /// # };
+    /// # let linker = Linker::new(options);
+    ///
+    /// linker.check([LinkerInput::new_from_file(path)], ["my_sym_1", "my_sym_2"])?;
+    /// if linker.has_errors() {
+    ///     println!("LLVM reported errors");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn check<'i, 'a, I, E>(&self, inputs: I, export_symbols: E) -> Result<(), LinkerError>
+    where
+        I: IntoIterator<Item = LinkerInput<'i>>,
+        E: IntoIterator<Item = &'a str>,
+    {
+        let (linked_module, target_machine) = self.link_module(inputs, export_symbols)?;
+        linked_module
+            .verify()
+            .map_err(|err| LinkerError::MalformedModule(err.functions, err.message))?;
+        if self.options.btf {
+            let _: LinkerOutput =
+                self.codegen_buffer(&linked_module, &target_machine, OutputType::Object)?;
+        }
+        Ok(())
+    }
+
+    /// Link and parse the generated object file into an [`aya_obj::Object`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use std::{path::Path, ffi::CString};
+    /// # use bpf_linker::{Cpu, Linker, LinkerInput, LinkerOptions, OptLevel};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let path = Path::new("/path/to/object-or-bitcode");
+    /// # let options = LinkerOptions {
+    /// #     target: None,
+    /// #     cpu: Cpu::Generic,
+    /// #     cpu_features: CString::default(),
+    /// #     optimize: OptLevel::Default,
+    /// #     unroll_loops: false,
+    /// #     ignore_inline_never: false,
+    /// #     llvm_args: vec![],
+    /// #     disable_expand_memcpy_in_order: false,
+    /// #     disable_memory_builtins: false,
+    /// #     allow_bpf_trap: false,
+    /// #     btf: false,
+    /// # };
+    /// # let linker = Linker::new(options);
+    ///
+    /// let export_symbols = ["my_sym_1", "my_sym_2"];
+    ///
+    /// let object = linker.link_to_aya_object([LinkerInput::new_from_file(path)], export_symbols)?;
+    /// println!("Linked {} programs", object.programs.len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "aya-obj")]
+    pub fn link_to_aya_object<'i, 'a, I, E>(
+        &self,
+        inputs: I,
+        export_symbols: E,
+    ) -> Result<aya_obj::Object, LinkerError>
+    where
+        I: IntoIterator<Item = LinkerInput<'i>>,
+        E: IntoIterator<Item = &'a str>,
+    {
+        let output = self.link_to_buffer(inputs, OutputType::Object, export_symbols)?;
+        aya_obj::Object::parse(output.as_slice()).map_err(LinkerError::ParseObjectError)
+    }
+
+    /// Link and generate the output code to the given [`OutputSink`].
+    ///
+    /// Returns the output when `output` is [`OutputSink::Buffer`], and `None`
+    /// otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use std::{collections::HashSet, path::Path, borrow::Cow, ffi::CString};
+    /// # use bpf_linker::{Cpu, Linker, LinkerInput, LinkerOptions, OptLevel, OutputSink, OutputType};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let path = Path::new("/path/to/object-or-bitcode");
+    /// # let options = LinkerOptions {
+    /// #     target: None,
+    /// #     cpu: Cpu::Generic,
+    /// #     cpu_features: CString::default(),
+    /// #     optimize: OptLevel::Default,
+    /// #     unroll_loops: false,
+    /// #     ignore_inline_never: false,
+    /// #     llvm_args: vec![],
+    /// #     disable_expand_memcpy_in_order: false,
+    /// #     disable_memory_builtins: false,
+    /// #     allow_bpf_trap: false,
+    /// #     btf: false,
+    /// # };
+    /// # let linker = Linker::new(options);
+    ///
+    /// let export_symbols = ["my_sym_1", "my_sym_2"];
+    ///
+    /// let mut stdout = std::io::stdout();
+    /// linker.link(
+    ///     [LinkerInput::new_from_file(path)],
+    ///     OutputSink::Writer(&mut stdout),
+    ///     OutputType::LlvmAssembly,
+    ///     export_symbols,
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn link<'i, 'a, I, E>(
+        &self,
+        inputs: I,
+        output: OutputSink<'_>,
+        output_type: OutputType,
+        export_symbols: E,
+    ) -> Result<Option<LinkerOutput>, LinkerError>
+    where
+        I: IntoIterator<Item = LinkerInput<'i>>,
+        E: IntoIterator<Item = &'a str>,
+    {
+        let (linked_module, target_machine) = self.link_module(inputs, export_symbols)?;
+        match output {
+            OutputSink::Path(path) => {
+                self.codegen_file(&linked_module, &target_machine, path, output_type)?;
+                Ok(None)
+            }
+            OutputSink::Fd(fd) => {
+                let output = self.codegen_buffer(&linked_module, &target_machine, output_type)?;
+                let _span = info_span!("emit", size = output.len()).entered();
+                let file = fd
+                    .try_clone_to_owned()
+                    .map(File::from)
+                    .map_err(LinkerError::WriteOutputError)?;
+                output
+                    .write_to(file)
+                    .map_err(LinkerError::WriteOutputError)?;
+                Ok(None)
+            }
+            OutputSink::Writer(writer) => {
+                let output = self.codegen_buffer(&linked_module, &target_machine, output_type)?;
+                let _span = info_span!("emit", size = output.len()).entered();
+                output
+                    .write_to(writer)
+                    .map_err(LinkerError::WriteOutputError)?;
+                Ok(None)
+            }
+            OutputSink::Chunked {
+                sink,
+                chunk_size,
+                max_retries,
+            } => {
+                let output = self.codegen_buffer(&linked_module, &target_machine, output_type)?;
+                let _span = info_span!("emit", size = output.len()).entered();
+                write_chunked(output.as_slice(), sink, chunk_size, max_retries)
+                    .map_err(LinkerError::WriteOutputError)?;
+                Ok(None)
+            }
+            OutputSink::Buffer => self
+                .codegen_buffer(&linked_module, &target_machine, output_type)
+                .map(Some),
+        }
+    }
+
+    /// Link into `context`, provided by the caller, and return the linked and optimized module
+    /// instead of generating the output code, so that the caller can keep working on it.
+    ///
+    /// LLVM reports the diagnostics of `context` to the linker as for the other `link_*` methods,
+    /// and [`Linker::has_errors`] accounts for those reported while linking.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use std::{path::Path, ffi::CString};
+    /// # use bpf_linker::{
+    /// #     Cpu, LLVMContextWrapped, Linker, LinkerInput, LinkerOptions, OptLevel, OutputType,
+    /// # };
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let path = Path::new("/path/to/object-or-bitcode");
+    /// # let options = LinkerOptions {
+    /// #     target: None,
+    /// #     cpu: Cpu::Generic,
+    /// #     cpu_features: CString::default(),
+    /// #     optimize: OptLevel::Default,
+    /// #     unroll_loops: false,
+    /// #     ignore_inline_never: false,
+    /// #     llvm_args: vec![],
+    /// #     disable_expand_memcpy_in_order: false,
+    /// #     disable_memory_builtins: false,
+    /// #     allow_bpf_trap: false,
+    /// #     btf: false,
+    /// # };
+    /// # let linker = Linker::new(options);
+    ///
+    /// let context = LLVMContextWrapped::new();
+    /// let module = linker.link_in_context(&context, [LinkerInput::new_from_file(path)], ["prog"])?;
+    ///
+    /// // Work on `module.as_raw()` with the LLVM C API, then generate the code.
+    /// let object = module.codegen(OutputType::Object)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn link_in_context<'ctx, 'i, 'a, I, E>(
+        &self,
+        context: &'ctx LLVMContextWrapped,
+        inputs: I,
+        export_symbols: E,
+    ) -> Result<LLVMModuleWrapped<'ctx>, LinkerError>
+    where
+        I: IntoIterator<Item = LinkerInput<'i>>,
+        E: IntoIterator<Item = &'a str>,
+    {
+        let LLVMContextWrapped {
+            context,
+            diagnostic_handler,
+        } = context;
+        diagnostic_handler.with_view(|h| {
+            h.fatal_warnings
+                .set(self.warnings.fatal.contains(&WarningKind::Llvm));
+            *h.sink.borrow_mut() = self.warnings.sink.clone();
+        });
+        let linked = self.link_module_in(context, diagnostic_handler, inputs, export_symbols);
+        if diagnostic_handler.with_view(|h| h.has_errors) {
+            self.link_errors.set(true);
+        }
+        let (module, target_machine) = linked?;
+        Ok(LLVMModuleWrapped {
+            module,
+            target_machine,
+        })
+    }
+
+    /// Link and return the linked and optimized module, so that its functions and global variables
+    /// can be inspected without generating the output code and parsing it back.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use std::{path::Path, ffi::CString};
+    /// # use bpf_linker::{Cpu, Linker, LinkerInput, LinkerOptions, OptLevel};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let path = Path::new("/path/to/object-or-bitcode");
+    /// # let options = LinkerOptions {
+    /// #     target: None,
+    /// #     cpu: Cpu::Generic,
+    /// #     cpu_features: CString::default(),
+    /// #     optimize: OptLevel::Default,
+    /// #     unroll_loops: false,
+    /// #     ignore_inline_never: false,
+    /// #     llvm_args: vec![],
+    /// #     disable_expand_memcpy_in_order: false,
+    /// #     disable_memory_builtins: false,
+    /// #     allow_bpf_trap: false,
+    /// #     btf: false,
+    /// # };
+    /// # let linker = Linker::new(options);
+    ///
+    /// let module = linker.link_to_module([LinkerInput::new_from_file(path)], ["prog"])?;
+    /// for function in module.functions().filter(|function| !function.is_declaration()) {
+    ///     println!("{} in {:?}", function.name(), function.section());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn link_to_module<'i, 'a, I, E>(
+        &self,
+        inputs: I,
+        export_symbols: E,
+    ) -> Result<LLVMModuleWrapped<'_>, LinkerError>
+    where
+        I: IntoIterator<Item = LinkerInput<'i>>,
+        E: IntoIterator<Item = &'a str>,
+    {
+        let (module, target_machine) = self.link_module(inputs, export_symbols)?;
+        Ok(LLVMModuleWrapped {
+            module,
+            target_machine,
+        })
     }
 
-    /// Link and generate the output code.
-    fn link<'ctx, 'i, 'a, I, E>(
+    /// Link the inputs and optimize the resulting module.
+    fn link_module<'ctx, 'i, 'a, I, E>(
         &'ctx self,
         inputs: I,
         export_symbols: E,
     ) -> Result<(LLVMModule<'ctx>, LLVMTargetMachine), LinkerError>
+    where
+        I: IntoIterator<Item = LinkerInput<'i>>,
+        E: IntoIterator<Item = &'a str>,
+    {
+        self.link_module_in(
+            &self.context,
+            &self.diagnostic_handler,
+            inputs,
+            export_symbols,
+        )
+    }
+
+    /// Link the inputs into `context` and optimize the resulting module.
+    fn link_module_in<'ctx, 'i, 'a, I, E>(
+        &self,
+        context: &'ctx LLVMContext,
+        diagnostic_handler: &llvm::InstalledDiagnosticHandler<DiagnosticHandler>,
+        inputs: I,
+        export_symbols: E,
+    ) -> Result<(LLVMModule<'ctx>, LLVMTargetMachine), LinkerError>
     where
         I: IntoIterator<Item = LinkerInput<'i>>,
         E: IntoIterator<Item = &'a str>,
     {
         let Self {
             options,
-            context,
             dump_module,
+            data_size_limits,
+            warnings,
+            undefined_symbols,
+            duplicate_symbols,
+            map_sections,
+            const_propagation,
+            line_info,
+            demangle_btf_names,
+            input_size_limit,
+            jobs,
+            verifier_lints,
+            hooks,
             ..
         } = self;
+        let hooks = hooks.as_deref();
+
+        if !self.llvm_args_parsed.replace(true) {
+            let args = self.llvm_args();
+            info!("LLVM command line: {:?}", args);
+            llvm::parse_command_line(args.as_slice(), c"BPF linker");
+        }
 
         let inputs = inputs
             .into_iter()
@@ -453,12 +1920,47 @@ impl Linker {
                         cursor: io::Cursor::new(bytes),
                     })
                 }
+                LinkerInput::Reader(reader_input) => {
+                    let ReaderInput { name, mut reader } = reader_input;
+
+                    // Streams can't be rewound after detecting the type of the input.
+                    let mut bytes = Vec::new();
+                    let _: usize = reader.read_to_end(&mut bytes).map_err(|err| {
+                        LinkerError::IoError(PathBuf::from(format!("stream::{name}")), err)
+                    })?;
+                    Ok(InputReader::Stream {
+                        name,
+                        cursor: io::Cursor::new(bytes),
+                    })
+                }
             })
             .collect::<Result<Vec<_>, _>>()?;
 
-        let mut module = link_modules(context, inputs)?;
+        let mut stats = LinkStats::default();
+        let link_span = info_span!("link").entered();
+        let start = Instant::now();
+        let (mut module, linked_inputs, has_errors, symbol_origins) = link_modules(
+            context,
+            inputs,
+            *input_size_limit,
+            *jobs,
+            *duplicate_symbols,
+            warnings,
+            hooks,
+        )?;
+        stats.inputs = linked_inputs;
+        stats.link_duration = start.elapsed();
+        let module_stats = module_stats(&module);
+        stats.functions_before = module_stats.functions;
+        if let Some(hooks) = hooks {
+            hooks.link_done(module_stats, stats.link_duration);
+        }
+        drop(link_span);
+        if has_errors {
+            self.link_errors.set(true);
+        }
 
-        let target_machine = create_target_machine(options, &module)?;
+        let target_machine = create_target_machine(self, &module)?;
 
         if let Some(path) = dump_module {
             std::fs::create_dir_all(path)
@@ -472,13 +1974,52 @@ impl Linker {
                 .write_ir_to_path(&path)
                 .map_err(LinkerError::WriteIRError)?;
         };
-        optimize(
-            options,
-            context,
-            &target_machine,
-            &mut module,
-            export_symbols,
-        )?;
+        place_maps(&mut module, map_sections);
+        if !const_propagation {
+            llvm::keep_global_values(&mut module);
+        }
+        let optimize_span = info_span!("optimize").entered();
+        let start = Instant::now();
+        optimize(self, context, &target_machine, &mut module, export_symbols)?;
+        stats.optimize_duration = start.elapsed();
+        let module_stats = module_stats(&module);
+        stats.functions_after = module_stats.functions;
+        *self.stats.borrow_mut() = stats;
+        if let Some(hooks) = hooks {
+            hooks.optimize_done(module_stats, stats.optimize_duration);
+        }
+        drop(optimize_span);
+        check_data_sizes(&module, &target_machine, data_size_limits, warnings);
+        if !check_maps(&module, &target_machine, warnings) {
+            self.link_errors.set(true);
+        }
+        if !check_helpers(&module, &target_machine, warnings) {
+            self.link_errors.set(true);
+        }
+        if *verifier_lints {
+            check_verifier_lints(&module, warnings);
+        }
+        if !check_undefined_symbols(&module, undefined_symbols, &symbol_origins, warnings) {
+            self.link_errors.set(true);
+        }
+        // Code generation errors, and those about inline assembly in particular, don't tell much
+        // about where the offending code comes from, so record what's needed to tell it.
+        let inline_asm_sites = llvm::inline_asm_sites(&module);
+        let function_sources = llvm::function_sources(&module);
+        diagnostic_handler.with_view(|h| {
+            *h.inline_asm_sites.borrow_mut() = inline_asm_sites;
+            *h.function_sources.borrow_mut() = function_sources;
+        });
+        if options.btf {
+            match line_info {
+                LineInfo::Full => {}
+                LineInfo::Statements => llvm::strip_debug_locations(&mut module, true),
+                LineInfo::Entry => llvm::strip_debug_locations(&mut module, false),
+            }
+            if *demangle_btf_names {
+                llvm::demangle_global_names(&mut module);
+            }
+        }
         if let Some(path) = dump_module {
             // dump IR before optimization
             let path = path.join("post-opt.ll");
@@ -486,26 +2027,336 @@ impl Linker {
             module
                 .write_ir_to_path(&path)
                 .map_err(LinkerError::WriteIRError)?;
+            if let Err(err) = module.verify() {
+                warn!(
+                    "optimized module is malformed (functions: {:?}): {}",
+                    err.functions, err.message
+                );
+            }
         };
 
         Ok((module, target_machine))
     }
 
+    /// Generates an output of `output_type` to `output`.
+    fn codegen_file(
+        &self,
+        module: &LLVMModule<'_>,
+        target_machine: &LLVMTargetMachine,
+        output: &Path,
+        output_type: OutputType,
+    ) -> Result<(), LinkerError> {
+        let _span = info_span!("codegen", ?output_type).entered();
+        let start = Instant::now();
+        codegen_to_file(module, target_machine, output, output_type)?;
+        let elapsed = start.elapsed();
+        // Objects are emitted straight to the file, so they're read back from it to be checked and
+        // for their statistics.
+        let object = match output_type {
+            OutputType::Object if self.output_stats || self.checks_object() => Some(
+                std::fs::read(output)
+                    .map_err(|err| LinkerError::IoError(output.to_owned(), err))?,
+            ),
+            _ => None,
+        };
+        if let Some(object) = &object {
+            self.check_object(object)?;
+        }
+        self.codegen_done(output_type, elapsed, object.as_deref());
+        Ok(())
+    }
+
+    /// Generates an output of `output_type` in memory.
+    fn codegen_buffer(
+        &self,
+        module: &LLVMModule<'_>,
+        target_machine: &LLVMTargetMachine,
+        output_type: OutputType,
+    ) -> Result<LinkerOutput, LinkerError> {
+        let _span = info_span!("codegen", ?output_type).entered();
+        let start = Instant::now();
+        let output = codegen_to_buffer(module, target_machine, output_type)?;
+        let elapsed = start.elapsed();
+        if let OutputType::Object = output_type {
+            self.check_object(output.as_slice())?;
+        }
+        self.codegen_done(output_type, elapsed, Some(output.as_slice()));
+        Ok(output)
+    }
+
+    /// Whether the generated objects are checked, by [`Linker::check_object`].
+    fn checks_object(&self) -> bool {
+        self.options.btf
+    }
+
+    /// Checks the generated `object`, where the loaders read it differently from the module it's
+    /// generated from: the maps of data sections are created from their DATASEC, and the maps of
+    /// `.maps` are read from their BTF definition.
+    fn check_object(&self, object: &[u8]) -> Result<(), LinkerError> {
+        if !self.checks_object() {
+            return Ok(());
+        }
+        let object =
+            object::File::parse(object).map_err(|err| LinkerError::CheckObjectError(err.into()))?;
+        if self.datasec_check {
+            let mismatches = check_datasecs(&object).map_err(LinkerError::CheckObjectError)?;
+            for mismatch in mismatches {
+                let mut diagnostic = Diagnostic::error(mismatch.to_string());
+                match mismatch {
+                    DatasecMismatch::SectionSize { .. } => {}
+                    DatasecMismatch::VariableOffset { variable, .. }
+                    | DatasecMismatch::VariableSize { variable, .. } => {
+                        diagnostic = diagnostic.with_symbol(variable)
+                    }
+                }
+                self.link_errors.set(true);
+                self.warnings.report(diagnostic);
+            }
+        }
+        let problems = check_btf_maps(&object).map_err(LinkerError::CheckObjectError)?;
+        for problem in problems {
+            let diagnostic = if problem.error {
+                self.link_errors.set(true);
+                Diagnostic::error(problem.to_string())
+            } else {
+                Diagnostic::warning(WarningKind::BtfMap, problem.to_string())
+            };
+            self.warnings.report(diagnostic.with_symbol(problem.map));
+        }
+        Ok(())
+    }
+
+    /// Records the generation of an output of `output_type`, which took `elapsed`, in the stats
+    /// and reports it to the hooks.
+    fn codegen_done(&self, output_type: OutputType, elapsed: Duration, output: Option<&[u8]>) {
+        let mut stats = self.stats.borrow_mut();
+        stats.codegen_duration += elapsed;
+        match (output_type, output) {
+            (OutputType::Object, Some(object)) if self.output_stats => {
+                stats.instructions = object::File::parse(object)
+                    .ok()
+                    .and_then(|file| instruction_counts(&file).ok())
+                    .map(|functions| functions.iter().map(|function| function.insns).sum());
+                stats.btf_size = section_range(object, ".BTF")
+                    .ok()
+                    .map(|range| range.len() as u64);
+            }
+            (OutputType::Btf, Some(btf)) if self.output_stats => {
+                stats.btf_size = Some(btf.len() as u64)
+            }
+            _ => {}
+        }
+        drop(stats);
+        if let Some(hooks) = &self.hooks {
+            hooks.codegen_done(output_type, elapsed);
+        }
+    }
+
+    /// Returns the command line options of LLVM set by the options and settings of the linker.
+    fn llvm_args(&self) -> Vec<Cow<'_, CStr>> {
+        let Self {
+            options,
+            outlining,
+            remarks,
+            unroll_max_count,
+            ..
+        } = self;
+        let mut args = Vec::<Cow<'_, CStr>>::new();
+        args.push(c"bpf-linker".into());
+        // Disable cold call site detection. Many accessors in aya-ebpf return Result<T, E>
+        // where the layout is larger than 64 bits, but the LLVM BPF target only supports
+        // up to 64 bits return values. Since the accessors are tiny in terms of code, we
+        // avoid the issue by annotating them with #[inline(always)]. If they are classified
+        // as cold though - and they often are starting from LLVM17 - #[inline(always)]
+        // is ignored and the BPF target fails codegen.
+        args.push(c"--cold-callsite-rel-freq=0".into());
+        if options.unroll_loops {
+            // setting cmdline arguments is the only way to customize the unroll pass with the
+            // C API.
+            args.extend([
+                c"--unroll-runtime".into(),
+                c"--unroll-runtime-multi-exit".into(),
+                CString::new(format!("--unroll-max-upperbound={}", u32::MAX))
+                    .unwrap()
+                    .into(),
+                CString::new(format!("--unroll-threshold={}", u32::MAX))
+                    .unwrap()
+                    .into(),
+            ]);
+        }
+        if let Some(count) = unroll_max_count {
+            args.push(
+                CString::new(format!("--unroll-max-count={count}"))
+                    .unwrap()
+                    .into(),
+            );
+        }
+        // LLVM 19 has no C API for the threshold of the inliner, only this option.
+        #[cfg(feature = "llvm-19")]
+        if let Some(threshold) =
+            llvm::effective_inline_threshold(options.optimize, self.inline_threshold)
+        {
+            args.push(
+                CString::new(format!("--inline-threshold={threshold}"))
+                    .unwrap()
+                    .into(),
+            );
+        }
+        if !options.disable_expand_memcpy_in_order {
+            args.push(c"--bpf-expand-memcpy-in-order".into());
+        }
+        if !options.allow_bpf_trap {
+            // TODO: Remove this once ksyms support is guaranteed.
+            // LLVM introduces __bpf_trap calls at points where __builtin_trap would normally be
+            // emitted. This is currently not supported by aya because __bpf_trap requires a .ksyms
+            // section, but this is not trivial to support. In the meantime, using this flag
+            // returns LLVM to the old behaviour, which did not introduce these calls and therefore
+            // does not require the .ksyms section.
+            args.push(c"--bpf-disable-trap-unreachable".into());
+        }
+        if !outlining {
+            args.extend([
+                c"--enable-machine-outliner=never".into(),
+                c"--hot-cold-split=false".into(),
+                c"--enable-partial-inlining=false".into(),
+            ]);
+        }
+        if let Some(remarks) = remarks {
+            let remarks = remarks.to_string_lossy();
+            args.extend(
+                [
+                    "pass-remarks",
+                    "pass-remarks-missed",
+                    "pass-remarks-analysis",
+                ]
+                .iter()
+                .map(|option| {
+                    CString::new(format!("--{option}={remarks}"))
+                        .unwrap()
+                        .into()
+                }),
+            );
+        }
+        args.extend(options.llvm_args.iter().map(Into::into));
+        args
+    }
+
+    /// Returns the statistics of the last link, and of the outputs generated from it.
+    pub fn stats(&self) -> LinkStats {
+        self.stats.borrow().clone()
+    }
+
+    /// Returns the optimization remarks issued by LLVM so far, for the passes set with
+    /// [`Linker::set_remarks`].
+    pub fn remarks(&self) -> Vec<Remark> {
+        self.diagnostic_handler.with_view(|h| h.remarks.clone())
+    }
+
     pub fn has_errors(&self) -> bool {
         self.diagnostic_handler.with_view(|h| h.has_errors)
+            || self.link_errors.get()
+            || self.warnings.promoted.load(Ordering::Relaxed)
     }
 }
 
-fn link_modules<'ctx, 'i, I>(
-    context: &'ctx LLVMContext,
-    inputs: I,
-) -> Result<LLVMModule<'ctx>, LinkerError>
+/// A module read from a linker input, ready to be parsed and linked.
+///
+/// Archives are expanded into one `InputModule` per member.
+struct InputModule {
+    /// Name of the module, used in diagnostics.
+    path: PathBuf,
+    /// Path of the archive containing the module, if any.
+    archive: Option<PathBuf>,
+    /// Contents of the module.
+    data: Vec<u8>,
+    /// Type of the module. It's unknown for archive members until their contents are inspected.
+    in_type: Option<InputType>,
+}
+
+/// Reader of a linker input whose type has been detected.
+enum InputSource<'i> {
+    Module(InputType, InputReader<'i>),
+    Archive(Archive<InputReader<'i>>),
+}
+
+/// A module of the linker inputs, not read yet: an input, or a member of an archive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ModuleRef {
+    /// Index of the input.
+    input: usize,
+    /// Index of the member, if the input is an archive.
+    member: Option<usize>,
+}
+
+/// The linker inputs, opened but not read.
+///
+/// Modules are only read when they are linked, so that no more than one module per linking
+/// thread is held in memory. The readers are behind locks since the members of an archive may be
+/// linked by different threads.
+struct OpenInputs<'i> {
+    inputs: Vec<(PathBuf, Mutex<InputSource<'i>>)>,
+    /// The modules of the inputs, in link order.
+    modules: Vec<ModuleRef>,
+}
+
+impl OpenInputs<'_> {
+    fn read(&self, module: ModuleRef) -> Result<InputModule, LinkerError> {
+        let (path, source) = &self.inputs[module.input];
+        let mut source = source.lock().unwrap();
+        match (&mut *source, module.member) {
+            (InputSource::Module(in_type, input), None) => {
+                let mut data = Vec::new();
+                let _: usize = input
+                    .read_to_end(&mut data)
+                    .map_err(|e| LinkerError::IoError(path.clone(), e))?;
+                Ok(InputModule {
+                    path: path.clone(),
+                    archive: None,
+                    data,
+                    in_type: Some(*in_type),
+                })
+            }
+            (InputSource::Archive(archive), Some(member)) => {
+                let mut item = archive
+                    .jump_to_entry(member)
+                    .map_err(|e| LinkerError::ReadArchiveError(path.clone(), e))?;
+                let name = PathBuf::from(OsStr::from_bytes(item.header().identifier()));
+                let mut data = Vec::new();
+                let _: usize = item
+                    .read_to_end(&mut data)
+                    .map_err(|_| LinkerError::LinkArchiveModuleError(path.clone(), name.clone()))?;
+                Ok(InputModule {
+                    path: name,
+                    archive: Some(path.clone()),
+                    data,
+                    in_type: None,
+                })
+            }
+            _ => unreachable!("module {module:?} doesn't match its input"),
+        }
+    }
+}
+
+/// Opens the inputs, detecting their types and listing the members of archives, and checks their
+/// total size against `size_limit` without reading them.
+fn open_inputs<'i, I>(inputs: I, size_limit: Option<u64>) -> Result<OpenInputs<'i>, LinkerError>
 where
     I: IntoIterator<Item = InputReader<'i>>,
 {
-    let mut module = context
-        .create_module(c"linked_module")
-        .ok_or(LinkerError::CreateModuleError)?;
+    let mut opened = Vec::new();
+    let mut modules = Vec::new();
+    let mut total_size = 0u64;
+    let mut check_size = |path: &Path, size: u64| {
+        total_size = total_size.saturating_add(size);
+        match size_limit {
+            Some(limit) if total_size > limit => Err(LinkerError::InputSizeLimitExceeded(
+                path.to_owned(),
+                total_size,
+                limit,
+            )),
+            _ => Ok(()),
+        }
+    };
 
     // buffer used to perform file type detection
     let mut buf = [0u8; 8];
@@ -513,7 +2364,9 @@ where
         let path = match input {
             InputReader::File { path, .. } => path.into(),
             InputReader::Buffer { name, .. } => PathBuf::from(format!("in_memory::{}", name)),
+            InputReader::Stream { name, .. } => PathBuf::from(format!("stream::{}", name)),
         };
+        let index = opened.len();
 
         // determine whether the input is bitcode, ELF with embedded bitcode, an archive file
         // or an invalid file
@@ -523,102 +2376,419 @@ where
         input
             .rewind()
             .map_err(|e| LinkerError::IoError(path.clone(), e))?;
-        let in_type =
-            detect_input_type(&buf).ok_or_else(|| LinkerError::InvalidInputType(path.clone()))?;
+        let in_type = detect_input_type(&buf)
+            .or_else(|| detect_assembly(&path))
+            .ok_or_else(|| LinkerError::InvalidInputType(path.clone()))?;
 
-        match in_type {
+        let source = match in_type {
             InputType::Archive => {
-                info!("linking archive {:?}", path);
+                info!("reading archive {:?}", path);
 
-                // Extract the archive and call link_reader() for each item.
                 let mut archive = Archive::new(input);
-                while let Some(Ok(item)) = archive.next_entry() {
-                    let name = PathBuf::from(OsStr::from_bytes(item.header().identifier()));
-                    info!("linking archive item {:?}", name);
-
-                    match link_reader(context, &mut module, &name, item, None) {
-                        Ok(_) => continue,
-                        Err(LinkerError::InvalidInputType(_)) => {
-                            info!("ignoring archive item {:?}: invalid type", name);
-                            continue;
-                        }
-                        Err(LinkerError::MissingBitcodeSection(_)) => {
-                            warn!("ignoring archive item {:?}: no embedded bitcode", name);
-                            continue;
-                        }
-                        Err(_) => return Err(LinkerError::LinkArchiveModuleError(path, name)),
-                    };
+                let read_error = |e| LinkerError::ReadArchiveError(path.clone(), e);
+                // Stopping at the first member that can't be read would silently drop the
+                // following ones.
+                for member in 0..archive.count_entries().map_err(read_error)? {
+                    let mut item = archive.jump_to_entry(member).map_err(read_error)?;
+                    let name = Path::new(OsStr::from_bytes(item.header().identifier()));
+                    check_size(
+                        &PathBuf::from(format!("{}({})", path.display(), name.display())),
+                        item.header().size(),
+                    )?;
+                    // Skip the contents rather than reading them.
+                    let _: u64 = item.seek(io::SeekFrom::End(0)).map_err(read_error)?;
+                    modules.push(ModuleRef {
+                        input: index,
+                        member: Some(member),
+                    });
                 }
+                InputSource::Archive(archive)
             }
             ty => {
+                let size = input
+                    .seek(io::SeekFrom::End(0))
+                    .and_then(|size| input.rewind().map(|()| size))
+                    .map_err(|e| LinkerError::IoError(path.clone(), e))?;
+                check_size(&path, size)?;
+                modules.push(ModuleRef {
+                    input: index,
+                    member: None,
+                });
+                InputSource::Module(ty, input)
+            }
+        };
+        opened.push((path, Mutex::new(source)));
+    }
+
+    Ok(OpenInputs {
+        inputs: opened,
+        modules,
+    })
+}
+
+/// Links the inputs into a new module. Returns the module, the number of input modules, whether
+/// LLVM issued errors in the per-thread contexts, and the input defining each symbol.
+fn link_modules<'ctx, 'i, I>(
+    context: &'ctx LLVMContext,
+    inputs: I,
+    input_size_limit: Option<u64>,
+    jobs: Option<NonZeroUsize>,
+    duplicate_symbols: DuplicateSymbols,
+    warnings: &Warnings,
+    hooks: Option<&dyn LinkerHooks>,
+) -> Result<(LLVMModule<'ctx>, usize, bool, HashMap<String, PathBuf>), LinkerError>
+where
+    I: IntoIterator<Item = InputReader<'i>>,
+{
+    let inputs = open_inputs(inputs, input_size_limit)?;
+    let input_count = inputs.modules.len();
+
+    let mut module = context
+        .create_module(c"linked_module")
+        .ok_or(LinkerError::CreateModuleError)?;
+
+    let jobs = jobs
+        .or_else(|| thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get)
+        .min(input_count);
+    if jobs <= 1 {
+        // Modules are read one by one, so that their data is released as soon as they are
+        // linked.
+        let mut symbol_origins = HashMap::new();
+        for &input in &inputs.modules {
+            link_input_module(
+                context,
+                &mut module,
+                inputs.read(input)?,
+                duplicate_symbols,
+                warnings,
+                hooks,
+                &mut symbol_origins,
+            )?;
+        }
+        return Ok((module, input_count, false, symbol_origins));
+    }
+
+    // Parsing and linking is done in per-thread contexts, since an LLVM context can't be used
+    // from multiple threads at the same time. Modules can't be linked across contexts, so each
+    // thread serializes its partially linked module to bitcode, which is then parsed and linked
+    // into the final context.
+    debug!("linking {} modules using {} threads", input_count, jobs);
+    let chunk_size = input_count.div_ceil(jobs);
+    // Spans are entered per thread, so the worker threads enter the span of the link themselves.
+    let span = Span::current();
+    let inputs = &inputs;
+    let mut has_errors = false;
+    let mut symbol_origins = HashMap::new();
+    thread::scope(|s| -> Result<(), LinkerError> {
+        let handles = inputs
+            .modules
+            .chunks(chunk_size)
+            .enumerate()
+            .map(|(index, chunk)| {
+                let span = span.clone();
+                s.spawn(move || {
+                    let _span = span.entered();
+                    link_partial_module(index, inputs, chunk, duplicate_symbols, warnings, hooks)
+                })
+            })
+            .collect::<Vec<_>>();
+        // Partial modules are linked in order as soon as their thread is done, so that each
+        // one's bitcode is dropped before the next one is taken.
+        for (index, handle) in handles.into_iter().enumerate() {
+            let PartialModule {
+                bitcode,
+                has_errors: partial_has_errors,
+                symbol_origins: partial_symbol_origins,
+            } = handle.join().expect("link thread panicked")?;
+            has_errors |= partial_has_errors;
+            for (symbol, origin) in partial_symbol_origins {
+                let _: &mut PathBuf = symbol_origins.entry(symbol).or_insert(origin);
+            }
+            llvm::link_bitcode_buffer(
+                context,
+                &mut module,
+                bitcode.as_slice(),
+                duplicate_symbols,
+                None,
+            )
+            .map_err(|e| {
+                link_bitcode_error(PathBuf::from(format!("partial_module::{index}")), e)
+            })?;
+        }
+        Ok(())
+    })?;
+
+    Ok((module, input_count, has_errors, symbol_origins))
+}
+
+/// Bitcode of the modules linked by a worker thread.
+struct PartialModule {
+    bitcode: MemoryBuffer,
+    /// Whether LLVM issued an error diagnostic in the worker context.
+    has_errors: bool,
+    /// The input defining each symbol of the modules.
+    symbol_origins: HashMap<String, PathBuf>,
+}
+
+fn link_partial_module(
+    index: usize,
+    inputs: &OpenInputs<'_>,
+    modules: &[ModuleRef],
+    duplicate_symbols: DuplicateSymbols,
+    warnings: &Warnings,
+    hooks: Option<&dyn LinkerHooks>,
+) -> Result<PartialModule, LinkerError> {
+    let mut context = LLVMContext::new();
+    let diagnostic_handler = context.set_diagnostic_handler(DiagnosticHandler {
+        fatal_warnings: Cell::new(warnings.fatal.contains(&WarningKind::Llvm)),
+        sink: RefCell::new(warnings.sink.clone()),
+        ..Default::default()
+    });
+
+    let mut symbol_origins = HashMap::new();
+    let bitcode = {
+        let mut module = context
+            .create_module(c"partial_module")
+            .ok_or(LinkerError::CreateModuleError)?;
+        // Name the module the same way as linking errors about it do.
+        module.set_identifier(format!("partial_module::{index}").as_bytes());
+        for &input in modules {
+            link_input_module(
+                &context,
+                &mut module,
+                inputs.read(input)?,
+                duplicate_symbols,
+                warnings,
+                hooks,
+                &mut symbol_origins,
+            )?;
+        }
+        module.write_bitcode_to_memory()
+    };
+
+    Ok(PartialModule {
+        bitcode,
+        has_errors: diagnostic_handler.with_view(|h| h.has_errors),
+        symbol_origins,
+    })
+}
+
+fn link_input_module<'ctx>(
+    context: &'ctx LLVMContext,
+    module: &mut LLVMModule<'ctx>,
+    input: InputModule,
+    duplicate_symbols: DuplicateSymbols,
+    warnings: &Warnings,
+    hooks: Option<&dyn LinkerHooks>,
+    symbol_origins: &mut HashMap<String, PathBuf>,
+) -> Result<(), LinkerError> {
+    let name = match &input.archive {
+        Some(archive) => PathBuf::from(format!("{}({})", archive.display(), input.path.display())),
+        None => input.path.clone(),
+    };
+    let _span = info_span!("parse", input = %name.display()).entered();
+    let start = Instant::now();
+    if let Some(hooks) = hooks {
+        hooks.parse_start(&name);
+    }
+    let linked = link_input_module_data(
+        context,
+        module,
+        input,
+        duplicate_symbols,
+        warnings,
+        symbol_origins,
+    );
+    if let Some(hooks) = hooks {
+        hooks.parse_end(&name, start.elapsed());
+    }
+    linked
+}
+
+fn link_input_module_data<'ctx>(
+    context: &'ctx LLVMContext,
+    module: &mut LLVMModule<'ctx>,
+    input: InputModule,
+    duplicate_symbols: DuplicateSymbols,
+    warnings: &Warnings,
+    symbol_origins: &mut HashMap<String, PathBuf>,
+) -> Result<(), LinkerError> {
+    let InputModule {
+        path,
+        archive,
+        data,
+        in_type,
+    } = input;
+    let mut record_origins = |symbols: Vec<String>, origin: &Path| {
+        for symbol in symbols {
+            let _: &mut PathBuf = symbol_origins
+                .entry(symbol)
+                .or_insert_with(|| origin.to_owned());
+        }
+    };
+
+    match archive {
+        Some(archive) => {
+            info!("linking archive item {:?}", path);
+            let item_path = || PathBuf::from(format!("{}({})", archive.display(), path.display()));
+
+            match link_data(context, module, &path, data, in_type, duplicate_symbols) {
+                Ok(symbols) => record_origins(symbols, &archive),
+                Err(LinkerError::InvalidInputType(_)) => {
+                    info!("ignoring archive item {:?}: invalid type", path);
+                }
+                Err(LinkerError::MissingBitcodeSection(_)) => {
+                    warnings.warn(
+                        WarningKind::MissingBitcode,
+                        format_args!("ignoring archive item {:?}: no embedded bitcode", path),
+                    );
+                }
+                Err(LinkerError::InvalidBitcode(_)) => {
+                    return Err(LinkerError::InvalidBitcode(item_path()))
+                }
+                Err(LinkerError::IncompatibleBitcode(_, producer, version)) => {
+                    return Err(LinkerError::IncompatibleBitcode(
+                        item_path(),
+                        producer,
+                        version,
+                    ))
+                }
+                Err(LinkerError::InputByteOrderMismatch(_, order)) => {
+                    return Err(LinkerError::InputByteOrderMismatch(item_path(), order))
+                }
+                Err(LinkerError::DataLayoutMismatch(_, expected, found)) => {
+                    return Err(LinkerError::DataLayoutMismatch(
+                        item_path(),
+                        expected,
+                        found,
+                    ))
+                }
+                Err(LinkerError::TargetTripleMismatch(_, expected, found)) => {
+                    return Err(LinkerError::TargetTripleMismatch(
+                        item_path(),
+                        expected,
+                        found,
+                    ))
+                }
+                Err(_) => return Err(LinkerError::LinkArchiveModuleError(archive, path)),
+            }
+        }
+        None => {
+            if let Some(ty) = in_type {
                 info!("linking file {:?} type {}", path, ty);
-                match link_reader(context, &mut module, &path, input, Some(ty)) {
-                    Ok(_) => {}
-                    Err(LinkerError::InvalidInputType(_)) => {
-                        info!("ignoring file {:?}: invalid type", path);
-                        continue;
-                    }
-                    Err(LinkerError::MissingBitcodeSection(_)) => {
-                        warn!("ignoring file {:?}: no embedded bitcode", path);
-                    }
-                    Err(err) => return Err(err),
+            }
+            match link_data(context, module, &path, data, in_type, duplicate_symbols) {
+                Ok(symbols) => record_origins(symbols, &path),
+                Err(LinkerError::InvalidInputType(_)) => {
+                    info!("ignoring file {:?}: invalid type", path);
+                }
+                Err(LinkerError::MissingBitcodeSection(_)) => {
+                    warnings.warn(
+                        WarningKind::MissingBitcode,
+                        format_args!("ignoring file {:?}: no embedded bitcode", path),
+                    );
                 }
+                Err(err) => return Err(err),
             }
         }
     }
 
-    Ok(module)
+    Ok(())
 }
 
-// link in a `Read`-er, which can be a file or an archive item
-fn link_reader<'ctx>(
+// link the contents of a file or an archive item, returning the symbols it defines
+fn link_data<'ctx>(
     context: &'ctx LLVMContext,
     module: &mut LLVMModule<'ctx>,
     path: &Path,
-    mut reader: impl Read,
+    data: Vec<u8>,
     in_type: Option<InputType>,
-) -> Result<(), LinkerError> {
-    let mut data = Vec::new();
-    let _: usize = reader
-        .read_to_end(&mut data)
-        .map_err(|e| LinkerError::IoError(path.to_owned(), e))?;
+    duplicate_symbols: DuplicateSymbols,
+) -> Result<Vec<String>, LinkerError> {
     // in_type is unknown when we're linking an item from an archive file
     let in_type = in_type
         .or_else(|| detect_input_type(&data))
+        .or_else(|| detect_assembly(path))
         .ok_or_else(|| LinkerError::InvalidInputType(path.to_owned()))?;
 
     let bitcode = match in_type {
         InputType::Bitcode => data,
-        InputType::Elf => match llvm::find_embedded_bitcode(context, &data) {
-            Ok(Some(bitcode)) => bitcode,
-            Ok(None) => return Err(LinkerError::MissingBitcodeSection(path.to_owned())),
-            Err(e) => return Err(LinkerError::EmbeddedBitcodeError(e)),
-        },
+        InputType::Elf => {
+            let bitcode = llvm::find_embedded_bitcode(context, &data);
+            // the object file isn't needed anymore once the bitcode has been extracted
+            drop(data);
+            match bitcode {
+                Ok(Some(bitcode)) => bitcode,
+                Ok(None) => return Err(LinkerError::MissingBitcodeSection(path.to_owned())),
+                Err(e) => return Err(LinkerError::EmbeddedBitcodeError(e)),
+            }
+        }
         // we need to handle this here since archive files could contain
         // mach-o files, eg somecrate.rlib containing lib.rmeta which is
         // mach-o on macos
         InputType::MachO => return Err(LinkerError::InvalidInputType(path.to_owned())),
         // this can't really happen
         InputType::Archive => panic!("nested archives not supported duh"),
+        InputType::Assembly => {
+            if !llvm::link_assembly(context, module, &data) {
+                return Err(LinkerError::LinkModuleError(path.to_owned()));
+            }
+            // The symbols defined by assembly aren't known until it's assembled.
+            return Ok(Vec::new());
+        }
     };
 
-    if !llvm::link_bitcode_buffer(context, module, &bitcode) {
-        return Err(LinkerError::LinkModuleError(path.to_owned()));
+    let mut symbols = Vec::new();
+    llvm::link_bitcode_buffer(
+        context,
+        module,
+        &bitcode,
+        duplicate_symbols,
+        Some(&mut symbols),
+    )
+    .map_err(|e| match e {
+        LinkBitcodeError::Parse => invalid_bitcode(path.to_owned(), &bitcode),
+        e => link_bitcode_error(path.to_owned(), e),
+    })?;
+
+    Ok(symbols)
+}
+
+/// Returns the error for `bitcode` that LLVM failed to parse, naming its producer when it's
+/// another version of LLVM, whose bitcode is the usual reason for opaque parse errors like
+/// "Invalid record".
+fn invalid_bitcode(path: PathBuf, bitcode: &[u8]) -> LinkerError {
+    let (major, minor, patch) = llvm::version();
+    match bitcode_producer(bitcode) {
+        Some(producer) if producer_llvm_major(&producer).is_some_and(|m| m != major) => {
+            LinkerError::IncompatibleBitcode(path, producer, format!("{major}.{minor}.{patch}"))
+        }
+        _ => LinkerError::InvalidBitcode(path),
     }
+}
 
-    Ok(())
+fn link_bitcode_error(path: PathBuf, error: LinkBitcodeError) -> LinkerError {
+    match error {
+        LinkBitcodeError::Parse => LinkerError::InvalidBitcode(path),
+        LinkBitcodeError::Link => LinkerError::LinkModuleError(path),
+        LinkBitcodeError::ByteOrder { big_endian } => {
+            LinkerError::InputByteOrderMismatch(path, byte_order(big_endian))
+        }
+        LinkBitcodeError::DataLayout { expected, found } => {
+            LinkerError::DataLayoutMismatch(path, expected, found)
+        }
+        LinkBitcodeError::Triple { expected, found } => {
+            LinkerError::TargetTripleMismatch(path, expected, found)
+        }
+    }
 }
 
 fn create_target_machine(
-    options: &LinkerOptions,
+    linker: &Linker,
     module: &LLVMModule<'_>,
 ) -> Result<LLVMTargetMachine, LinkerError> {
-    let LinkerOptions {
-        target,
-        cpu,
-        cpu_features,
-        ..
-    } = options;
+    let LinkerOptions { target, .. } = &linker.options;
     // Here's how the output target is selected:
     //
     // 1) rustc with builtin BPF support: cargo build --target=bpf[el|eb]-unknown-none
@@ -630,43 +2800,154 @@ fn create_target_machine(
     //
     // 3) rustc with no BPF support: cargo rustc -- -C linker-flavor=bpf-linker -C linker=bpf-linker
     //      the input modules are configured for the *host* target, the output target isn't
-    //      set via `--target`, so default to `bpf` (bpfel or bpfeb depending on the host
-    //      endianness)
+    //      set via `--target`, so default to `bpfel` or `bpfeb` depending on the byte order of
+    //      the input data layout, or to `bpf` (the host endianness) when the inputs have none
+    let big_endian = module.big_endian();
     let (triple, target) = match target {
         // case 1
-        Some(c_triple) => (c_triple.as_c_str(), llvm::target_from_triple(c_triple)),
+        Some(c_triple) => {
+            if target_big_endian(c_triple).is_none() {
+                return Err(LinkerError::InvalidTarget(
+                    c_triple.to_string_lossy().to_string(),
+                ));
+            }
+            (c_triple.as_c_str(), llvm::target_from_triple(c_triple))
+        }
         None => {
             let c_triple = module.get_target();
-            let c_triple = unsafe { CStr::from_ptr(c_triple) };
             if c_triple.to_bytes().starts_with(b"bpf") {
                 // case 2
                 (c_triple, llvm::target_from_module(module))
             } else {
                 // case 3.
-                info!("detected non-bpf input target {:?} and no explicit output --target specified, selecting `bpf'", c_triple);
-                let c_triple = c"bpf";
+                let c_triple = match big_endian {
+                    Some(true) => c"bpfeb",
+                    Some(false) => c"bpfel",
+                    None => c"bpf",
+                };
+                info!("detected non-bpf input target {:?} and no explicit output --target specified, selecting `{}'", module.get_target(), c_triple.to_string_lossy());
                 (c_triple, llvm::target_from_triple(c_triple))
             }
         }
     };
     let target =
         target.map_err(|_msg| LinkerError::InvalidTarget(triple.to_string_lossy().to_string()))?;
+    if let (Some(inputs), Some(target)) = (big_endian, target_big_endian(triple)) {
+        if inputs != target {
+            return Err(LinkerError::TargetByteOrderMismatch(
+                triple.to_string_lossy().to_string(),
+                byte_order(target),
+                byte_order(inputs),
+                if inputs { "bpfeb" } else { "bpfel" },
+            ));
+        }
+    }
+
+    build_target_machine(linker, triple, target)
+}
+
+/// Whether the BPF target `triple` is big-endian, or `None` if it isn't a BPF target. `bpf` has
+/// the byte order of the host.
+fn target_big_endian(triple: &CStr) -> Option<bool> {
+    let arch = triple.to_bytes().split(|&b| b == b'-').next()?;
+    match arch {
+        b"bpfeb" => Some(true),
+        b"bpfel" => Some(false),
+        b"bpf" => Some(cfg!(target_endian = "big")),
+        _ => None,
+    }
+}
+
+fn byte_order(big_endian: bool) -> &'static str {
+    if big_endian {
+        "big-endian"
+    } else {
+        "little-endian"
+    }
+}
+
+fn build_target_machine(
+    linker: &Linker,
+    triple: &CStr,
+    target: LLVMTarget,
+) -> Result<LLVMTargetMachine, LinkerError> {
+    let Linker {
+        options: LinkerOptions {
+            cpu, cpu_features, ..
+        },
+        target_machine_options,
+        fast_isel,
+        global_isel,
+        machine_outliner,
+        ..
+    } = linker;
 
     debug!(
-        "creating target machine: triple: {} cpu: {} features: {}",
+        "creating target machine: triple: {} cpu: {} features: {} options: {:?}",
         triple.to_string_lossy(),
         cpu,
         cpu_features.to_string_lossy(),
+        target_machine_options,
     );
 
-    let target_machine = LLVMTargetMachine::new(target, triple, cpu.as_c_str(), cpu_features)
+    let TargetMachineOptions {
+        reloc_model,
+        code_model,
+        opt_level,
+        abi,
+        asm_verbose,
+    } = target_machine_options;
+    let mut builder = LLVMTargetMachineBuilder::new()
+        .cpu(cpu.as_c_str())
+        .features(cpu_features)
+        .opt_level(match opt_level {
+            CodeGenOptLevel::None => LLVMCodeGenOptLevel::LLVMCodeGenLevelNone,
+            CodeGenOptLevel::Less => LLVMCodeGenOptLevel::LLVMCodeGenLevelLess,
+            CodeGenOptLevel::Default => LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+            CodeGenOptLevel::Aggressive => LLVMCodeGenOptLevel::LLVMCodeGenLevelAggressive,
+        })
+        .reloc_mode(match reloc_model {
+            RelocModel::Default => LLVMRelocMode::LLVMRelocDefault,
+            RelocModel::Static => LLVMRelocMode::LLVMRelocStatic,
+            RelocModel::Pic => LLVMRelocMode::LLVMRelocPIC,
+            RelocModel::DynamicNoPic => LLVMRelocMode::LLVMRelocDynamicNoPic,
+        })
+        .code_model(match code_model {
+            CodeModel::Default => LLVMCodeModel::LLVMCodeModelDefault,
+            CodeModel::Tiny => LLVMCodeModel::LLVMCodeModelTiny,
+            CodeModel::Small => LLVMCodeModel::LLVMCodeModelSmall,
+            CodeModel::Kernel => LLVMCodeModel::LLVMCodeModelKernel,
+            CodeModel::Medium => LLVMCodeModel::LLVMCodeModelMedium,
+            CodeModel::Large => LLVMCodeModel::LLVMCodeModelLarge,
+        });
+    if let Some(abi) = abi {
+        builder = builder.abi(abi);
+    }
+
+    let mut target_machine = builder
+        .build(target, triple)
         .ok_or_else(|| LinkerError::InvalidTarget(triple.to_string_lossy().to_string()))?;
+    if let Some(enable) = fast_isel {
+        target_machine.set_fast_isel(*enable);
+    }
+    if let Some(enable) = global_isel {
+        target_machine.set_global_isel(*enable);
+    }
+    if let Some(enable) = machine_outliner {
+        target_machine.set_machine_outliner(*enable);
+    }
+    if let Some(verbose) = asm_verbose {
+        target_machine.set_asm_verbosity(*verbose);
+    }
 
     Ok(target_machine)
 }
 
+/// Memory functions exported unless [`LinkerOptions::disable_memory_builtins`] is set.
+const MEMORY_BUILTINS: &[&str] = &["memcpy", "memmove", "memset", "memcmp", "bcmp"];
+
 fn optimize<'ctx, 'a, E>(
-    options: &LinkerOptions,
+    linker: &Linker,
     context: &'ctx LLVMContext,
     target_machine: &LLVMTargetMachine,
     module: &mut LLVMModule<'ctx>,
@@ -675,6 +2956,15 @@ fn optimize<'ctx, 'a, E>(
 where
     E: IntoIterator<Item = &'a str>,
 {
+    let Linker {
+        options,
+        warnings,
+        identical_code_folding,
+        gc_maps,
+        passes,
+        inline_threshold,
+        ..
+    } = linker;
     let LinkerOptions {
         disable_memory_builtins,
         optimize,
@@ -683,17 +2973,27 @@ where
         ..
     } = options;
 
-    let mut export_symbols: HashSet<Cow<'_, [u8]>> = export_symbols
+    let (patterns, names): (Vec<_>, Vec<_>) =
+        export_symbols.into_iter().partition(|s| s.contains('*'));
+    let mut export_symbols: HashSet<Cow<'_, [u8]>> = names
         .into_iter()
         .map(|s| Cow::Borrowed(s.as_bytes()))
         .collect();
-
-    if !disable_memory_builtins {
+    if !patterns.is_empty() {
         export_symbols.extend(
-            ["memcpy", "memmove", "memset", "memcmp", "bcmp"]
+            llvm::defined_symbols(module)
                 .into_iter()
-                .map(|s| s.as_bytes().into()),
+                .filter(|symbol| {
+                    patterns
+                        .iter()
+                        .any(|pattern| matches_pattern(pattern, symbol))
+                })
+                .map(|symbol| Cow::Owned(symbol.into_bytes())),
         );
+    }
+
+    if !disable_memory_builtins {
+        export_symbols.extend(MEMORY_BUILTINS.iter().map(|s| s.as_bytes().into()));
     };
     debug!(
         "linking exporting symbols {:?}, opt level {:?}",
@@ -704,7 +3004,16 @@ where
 
     if *btf {
         // if we want to emit BTF, we need to sanitize the debug information
-        llvm::DISanitizer::new(context, module).run(&export_symbols);
+        let skipped_types = llvm::DISanitizer::new(context, module).run(&export_symbols);
+        if !skipped_types.is_empty() {
+            warnings.warn(
+                WarningKind::DebugInfo,
+                format_args!(
+                    "debug info was not emitted for the following types: {}",
+                    skipped_types.join(", ")
+                ),
+            );
+        }
     } else {
         // if we don't need BTF emission, we can strip DI
         let ok = module.strip_debug_info();
@@ -714,15 +3023,357 @@ where
     llvm::optimize(
         target_machine,
         module,
-        options.optimize,
-        *ignore_inline_never,
+        &llvm::Passes {
+            opt_level: *optimize,
+            pipeline: passes.as_deref(),
+            inline_threshold: *inline_threshold,
+            ignore_inline_never: *ignore_inline_never,
+            merge_functions: *identical_code_folding,
+        },
         &export_symbols,
     )
     .map_err(LinkerError::OptimizeError)?;
 
+    if *gc_maps {
+        for map in llvm::remove_unused_maps(module, &export_symbols) {
+            info!("removing unused map `{}`", demangle(&map));
+        }
+    }
+
+    // Programs are placed in their own section, so an exported function in .text is most likely
+    // missing its program attribute.
+    for function in llvm::exported_text_functions(module) {
+        if MEMORY_BUILTINS.contains(&function.as_str()) {
+            continue;
+        }
+        warnings.warn(
+            WarningKind::TextFunction,
+            format_args!(
+                "exported function `{}` has no program section and will be placed in .text, \
+                 where it can't be loaded as a program; is it missing a program attribute?",
+                demangle(&function)
+            ),
+        );
+    }
+
     Ok(())
 }
 
+fn check_data_sizes(
+    module: &LLVMModule<'_>,
+    target_machine: &LLVMTargetMachine,
+    limits: &DataSizeLimits,
+    warnings: &Warnings,
+) {
+    let DataSizeLimits {
+        section: section_limit,
+        global: global_limit,
+    } = *limits;
+    if section_limit.is_none() && global_limit.is_none() {
+        return;
+    }
+
+    let mut section_sizes = BTreeMap::<String, u64>::new();
+    for global in llvm::data_globals(module, target_machine) {
+        let llvm::DataGlobal {
+            name,
+            section,
+            size,
+        } = global;
+        if let Some(limit) = global_limit.filter(|&limit| size > limit) {
+            warnings.warn(
+                WarningKind::DataSize,
+                format_args!(
+                    "global `{}` in {} is {} bytes, more than {} bytes",
+                    name, section, size, limit
+                ),
+            );
+        }
+        *section_sizes.entry(section).or_default() += size;
+    }
+    if let Some(limit) = section_limit {
+        for (section, size) in section_sizes {
+            if size > limit {
+                warnings.warn(
+                    WarningKind::DataSize,
+                    format_args!(
+                        "section {} is {} bytes, more than {} bytes",
+                        section, size, limit
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Moves the maps of the module to the section given by the first of `rules` matching them.
+fn place_maps(module: &mut LLVMModule<'_>, rules: &[MapSectionRule]) {
+    if rules.is_empty() {
+        return;
+    }
+    llvm::move_maps(module, |name, section| {
+        let target = rules
+            .iter()
+            .find(|rule| matches_pattern(&rule.pattern, name))?
+            .section;
+        let placed = match target {
+            MapSection::Legacy => section == "maps" || section.starts_with("maps/"),
+            MapSection::Btf => section == ".maps",
+        };
+        if placed {
+            return None;
+        }
+        debug!("moving map `{}` from {} to {}", name, section, target);
+        Some(match target {
+            MapSection::Legacy => c"maps",
+            MapSection::Btf => c".maps",
+        })
+    });
+}
+
+/// Checks the legacy map definitions of the module, and the names they're pinned under. Returns
+/// false if a map would be rejected by the kernel.
+fn check_maps(
+    module: &LLVMModule<'_>,
+    target_machine: &LLVMTargetMachine,
+    warnings: &Warnings,
+) -> bool {
+    let mut valid = true;
+    let definitions = llvm::map_definitions(module, target_machine);
+    let mut pinned = Vec::new();
+    for definition in &definitions {
+        let llvm::MapDefinition {
+            name,
+            section,
+            bytes,
+            big_endian,
+        } = definition;
+        let Some(def) = bytes
+            .as_deref()
+            .and_then(|bytes| maps::MapDef::parse(bytes, *big_endian))
+        else {
+            debug!("skipping map `{}`: can't evaluate its definition", name);
+            continue;
+        };
+        for problem in maps::check(&def) {
+            if problem.is_error() {
+                valid = false;
+                warnings.report(
+                    Diagnostic::error(format!("map `{}`: {}", name, problem))
+                        .with_symbol(name.as_str()),
+                );
+            } else {
+                warnings.warn(
+                    WarningKind::Map,
+                    format_args!("map `{}`: {}", name, problem),
+                );
+            }
+        }
+        if def.is_pinned() {
+            pinned.push((name.as_str(), maps::pin_name(name, section)));
+        }
+    }
+    for (name, problem) in maps::check_pinning(&pinned) {
+        warnings.warn(
+            WarningKind::Map,
+            format_args!("map `{}`: {}", name, problem),
+        );
+    }
+    valid
+}
+
+/// Checks the symbols that are referenced but not defined against `policy`. Returns false if some
+/// aren't allowed.
+fn check_undefined_symbols(
+    module: &LLVMModule<'_>,
+    policy: &UndefinedSymbols,
+    symbol_origins: &HashMap<String, PathBuf>,
+    warnings: &Warnings,
+) -> bool {
+    let mut valid = true;
+    for undefined in llvm::undefined_symbols(module) {
+        let llvm::UndefinedSymbol { symbol, users } = undefined;
+        let allowed = match policy {
+            UndefinedSymbols::Deny => false,
+            UndefinedSymbols::Allow => true,
+            UndefinedSymbols::AllowOnly(symbols) => symbols.contains(&symbol),
+        };
+        if allowed {
+            debug!("leaving undefined symbol `{}` to the loader", symbol);
+            continue;
+        }
+        valid = false;
+        let users = users
+            .iter()
+            .map(|user| format!("`{user}`"))
+            .collect::<Vec<_>>();
+        let hint = suggest_symbol(&symbol, symbol_origins)
+            .map(|(candidate, origin)| {
+                format!(
+                    "; did you mean `{}` from `{}`?",
+                    demangle(candidate),
+                    origin.display()
+                )
+            })
+            .unwrap_or_default();
+        let message = if users.is_empty() {
+            format!("undefined symbol `{}`{hint}", demangle(&symbol))
+        } else {
+            format!(
+                "undefined symbol `{}`, referenced by {}{hint}",
+                demangle(&symbol),
+                users.join(", ")
+            )
+        };
+        warnings.report(Diagnostic::error(message).with_symbol(demangle(&symbol)));
+    }
+    valid
+}
+
+/// Returns the defined symbol closest to the undefined `symbol`, along with the input defining
+/// it, if it's close enough to be a likely typo.
+///
+/// Symbols are compared both by their demangled path and by the last segment of the path, so that
+/// a Rust declaration of a symbol defined by another crate under a different path still matches.
+fn suggest_symbol<'a>(
+    symbol: &str,
+    symbol_origins: &'a HashMap<String, PathBuf>,
+) -> Option<(&'a str, &'a Path)> {
+    fn names(symbol: &str) -> (String, String) {
+        let path = format!("{:#}", rustc_demangle::demangle(symbol));
+        let name = path.rsplit("::").next().unwrap_or(&path).to_owned();
+        (path, name)
+    }
+
+    let (path, name) = names(symbol);
+    let max_distance = (name.chars().count() / 3).max(1);
+    symbol_origins
+        .iter()
+        .filter(|(candidate, _)| candidate.as_str() != symbol)
+        .filter_map(|(candidate, origin)| {
+            let (candidate_path, candidate_name) = names(candidate);
+            let distance =
+                edit_distance(&path, &candidate_path).min(edit_distance(&name, &candidate_name));
+            (distance <= max_distance).then_some((distance, candidate.as_str(), origin.as_path()))
+        })
+        .min()
+        .map(|(_, candidate, origin)| (candidate, origin))
+}
+
+/// Levenshtein distance between `a` and `b`, in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Warns about the code patterns that the verifier is likely to reject.
+fn check_verifier_lints(module: &LLVMModule<'_>, warnings: &Warnings) {
+    for lint in llvm::verifier_lints(module) {
+        let llvm::VerifierLint {
+            function,
+            location,
+            kind,
+        } = lint;
+        // The memory builtins are only called when copies can't be expanded, which is reported
+        // at the call site.
+        if MEMORY_BUILTINS.contains(&function.as_str()) {
+            continue;
+        }
+        let at = match &location {
+            Some((file, line)) => format!(" at {file}:{line}"),
+            None => String::new(),
+        };
+        let message = match kind {
+            llvm::VerifierLintKind::Loop => {
+                "has a loop, which the verifier must prove terminates, and kernels before 5.3 \
+                 reject; consider `bpf_loop` or unrolling it"
+                    .to_string()
+            }
+            llvm::VerifierLintKind::MemoryIntrinsic {
+                name,
+                len: Some(len),
+            } => format!("calls `{name}` on {len} bytes, which expands into long code, if at all"),
+            llvm::VerifierLintKind::MemoryIntrinsic { name, len: None } => format!(
+                "calls `{name}` with a variable length, which can't be expanded into loads and \
+                 stores"
+            ),
+            llvm::VerifierLintKind::IndirectCall => {
+                "calls through a function pointer, which the verifier rejects".to_string()
+            }
+            llvm::VerifierLintKind::TooManyArguments { callee, arguments } => format!(
+                "calls `{callee}` with {arguments} arguments, more than the 5 that BPF calls can \
+                 pass"
+            ),
+        };
+        warnings.report(
+            Diagnostic::warning(
+                WarningKind::Verifier,
+                format!("function `{function}`{at} {message}"),
+            )
+            .with_symbol(function)
+            .with_location(location),
+        );
+    }
+}
+
+/// Checks that GPL-only helpers are only called when the license of the module is GPL compatible.
+/// Returns false if a program would be rejected by the kernel.
+fn check_helpers(
+    module: &LLVMModule<'_>,
+    target_machine: &LLVMTargetMachine,
+    warnings: &Warnings,
+) -> bool {
+    // Loaders differ in how they treat objects without a license, so there's nothing to check.
+    let Some(license) = llvm::license(module, target_machine) else {
+        debug!("skipping helper checks: no license");
+        return true;
+    };
+    if helpers::is_gpl_compatible(&license) {
+        return true;
+    }
+
+    let mut valid = true;
+    for call in llvm::helper_calls(module) {
+        let llvm::HelperCall { function, helper } = call;
+        if let Some(name) = helpers::gpl_only_helper(helper) {
+            valid = false;
+            let message = format!(
+                "function `{}` calls {}, which requires a GPL compatible license, but the license \
+                 is \"{}\"",
+                function,
+                name,
+                license.escape_debug()
+            );
+            warnings.report(Diagnostic::error(message).with_symbol(function));
+        }
+    }
+    valid
+}
+
+/// Returns the number of functions and global variables defined in `module`.
+fn module_stats(module: &LLVMModule<'_>) -> ModuleStats {
+    let defined = |values: Vec<_>| {
+        values
+            .into_iter()
+            .filter(|&value| !llvm::is_declaration(value))
+            .count()
+    };
+    ModuleStats {
+        functions: defined(llvm::functions(module)),
+        globals: defined(llvm::globals(module)),
+    }
+}
+
 fn codegen_to_file(
     module: &LLVMModule<'_>,
     target_machine: &LLVMTargetMachine,
@@ -730,20 +3381,25 @@ fn codegen_to_file(
     output_type: OutputType,
 ) -> Result<(), LinkerError> {
     info!("writing {:?} to {:?}", output_type, output);
-    let output = CString::new(output.as_os_str().as_encoded_bytes()).unwrap();
+    let c_output = || CString::new(output.as_os_str().as_encoded_bytes()).unwrap();
     match output_type {
         OutputType::Bitcode => module
-            .write_bitcode_to_path(&output)
+            .write_bitcode_to_path(output)
             .map_err(LinkerError::WriteBitcodeError),
         OutputType::LlvmAssembly => module
-            .write_ir_to_path(&output)
+            .write_ir_to_path(&c_output())
             .map_err(LinkerError::WriteIRError),
         OutputType::Assembly => target_machine
-            .emit_to_file(module, &output, LLVMCodeGenFileType::LLVMAssemblyFile)
+            .emit_to_file(module, &c_output(), LLVMCodeGenFileType::LLVMAssemblyFile)
             .map_err(LinkerError::EmitCodeError),
         OutputType::Object => target_machine
-            .emit_to_file(module, &output, LLVMCodeGenFileType::LLVMObjectFile)
+            .emit_to_file(module, &c_output(), LLVMCodeGenFileType::LLVMObjectFile)
             .map_err(LinkerError::EmitCodeError),
+        OutputType::Btf | OutputType::BtfExt => {
+            let output_buffer = codegen_to_buffer(module, target_machine, output_type)?;
+            std::fs::write(output, output_buffer.as_slice())
+                .map_err(|e| LinkerError::IoError(output.to_owned(), e))
+        }
     }
 }
 
@@ -752,66 +3408,50 @@ fn codegen_to_buffer(
     target_machine: &LLVMTargetMachine,
     output_type: OutputType,
 ) -> Result<LinkerOutput, LinkerError> {
-    let memory_buffer = match output_type {
-        OutputType::Bitcode => module.write_bitcode_to_memory(),
-        OutputType::LlvmAssembly => module.write_ir_to_memory(),
-        OutputType::Assembly => target_machine
-            .emit_to_memory_buffer(module, LLVMCodeGenFileType::LLVMAssemblyFile)
-            .map_err(LinkerError::EmitCodeError)?,
-        OutputType::Object => target_machine
-            .emit_to_memory_buffer(module, LLVMCodeGenFileType::LLVMObjectFile)
-            .map_err(LinkerError::EmitCodeError)?,
+    let inner = match output_type {
+        OutputType::Bitcode => OutputBuffer::MemoryBuffer(module.write_bitcode_to_memory()),
+        OutputType::LlvmAssembly => OutputBuffer::Message(module.write_ir_to_memory()),
+        OutputType::Assembly => OutputBuffer::MemoryBuffer(
+            target_machine
+                .emit_to_memory_buffer(module, LLVMCodeGenFileType::LLVMAssemblyFile)
+                .map_err(LinkerError::EmitCodeError)?,
+        ),
+        OutputType::Object => OutputBuffer::MemoryBuffer(
+            target_machine
+                .emit_to_memory_buffer(module, LLVMCodeGenFileType::LLVMObjectFile)
+                .map_err(LinkerError::EmitCodeError)?,
+        ),
+        OutputType::Btf | OutputType::BtfExt => {
+            let object = target_machine
+                .emit_to_memory_buffer(module, LLVMCodeGenFileType::LLVMObjectFile)
+                .map_err(LinkerError::EmitCodeError)?;
+            let section = match output_type {
+                OutputType::BtfExt => ".BTF.ext",
+                _ => ".BTF",
+            };
+            let range = section_range(object.as_slice(), section)?;
+            OutputBuffer::Section { object, range }
+        }
     };
 
-    Ok(LinkerOutput {
-        inner: memory_buffer,
-    })
+    Ok(LinkerOutput { inner })
+}
+
+/// Range of the bytes of `section` in the object file `object`.
+fn section_range(object: &[u8], section: &'static str) -> Result<Range<usize>, LinkerError> {
+    let file = object::File::parse(object).map_err(LinkerError::ReadObjectError)?;
+    let (offset, size) = file
+        .section_by_name(section)
+        .and_then(|section| section.file_range())
+        .ok_or(LinkerError::MissingBtfSection(section))?;
+    Ok(offset as usize..(offset + size) as usize)
 }
 
-fn llvm_init(
-    options: &LinkerOptions,
-) -> (
+fn llvm_init() -> (
     LLVMContext,
     llvm::InstalledDiagnosticHandler<DiagnosticHandler>,
 ) {
-    let mut args = Vec::<Cow<'_, CStr>>::new();
-    args.push(c"bpf-linker".into());
-    // Disable cold call site detection. Many accessors in aya-ebpf return Result<T, E>
-    // where the layout is larger than 64 bits, but the LLVM BPF target only supports
-    // up to 64 bits return values. Since the accessors are tiny in terms of code, we
-    // avoid the issue by annotating them with #[inline(always)]. If they are classified
-    // as cold though - and they often are starting from LLVM17 - #[inline(always)]
-    // is ignored and the BPF target fails codegen.
-    args.push(c"--cold-callsite-rel-freq=0".into());
-    if options.unroll_loops {
-        // setting cmdline arguments is the only way to customize the unroll pass with the
-        // C API.
-        args.extend([
-            c"--unroll-runtime".into(),
-            c"--unroll-runtime-multi-exit".into(),
-            CString::new(format!("--unroll-max-upperbound={}", u32::MAX))
-                .unwrap()
-                .into(),
-            CString::new(format!("--unroll-threshold={}", u32::MAX))
-                .unwrap()
-                .into(),
-        ]);
-    }
-    if !options.disable_expand_memcpy_in_order {
-        args.push(c"--bpf-expand-memcpy-in-order".into());
-    }
-    if !options.allow_bpf_trap {
-        // TODO: Remove this once ksyms support is guaranteed.
-        // LLVM introduces __bpf_trap calls at points where __builtin_trap would normally be
-        // emitted. This is currently not supported by aya because __bpf_trap requires a .ksyms
-        // section, but this is not trivial to support. In the meantime, using this flag
-        // returns LLVM to the old behaviour, which did not introduce these calls and therefore
-        // does not require the .ksyms section.
-        args.push(c"--bpf-disable-trap-unreachable".into());
-    }
-    args.extend(options.llvm_args.iter().map(Into::into));
-    info!("LLVM command line: {:?}", args);
-    llvm::init(args.as_slice(), c"BPF linker");
+    llvm::init();
 
     let mut context = LLVMContext::new();
 
@@ -828,10 +3468,84 @@ fn llvm_init(
 #[derive(Default)]
 pub(crate) struct DiagnosticHandler {
     pub(crate) has_errors: bool,
+    /// Whether warnings are turned into errors.
+    pub(crate) fatal_warnings: Cell<bool>,
+    /// Calls to inline assembly in the module being compiled, used to tell where inline assembly
+    /// errors come from.
+    pub(crate) inline_asm_sites: RefCell<Vec<llvm::InlineAsmSite>>,
+    /// Sources of the functions in the module being compiled, by symbol name, used to tell where
+    /// code generation errors come from.
+    pub(crate) function_sources: RefCell<HashMap<String, llvm::FunctionSource>>,
+    /// Where diagnostics are reported instead of being logged.
+    pub(crate) sink: RefCell<Option<Arc<dyn DiagnosticSink>>>,
+    /// The optimization remarks issued so far.
+    pub(crate) remarks: Vec<Remark>,
     // The handler is passed to LLVM as a raw pointer so it must not be moved.
     _marker: std::marker::PhantomPinned,
 }
 
+impl DiagnosticHandler {
+    /// Describes the code a diagnostic is about, if it can be told.
+    fn origin(&self, message: &str) -> Option<String> {
+        if let Some(site) = self.inline_asm_site(message) {
+            return Some(format!("inline assembly of {site}"));
+        }
+        self.function_source(message)
+            .map(|function| function.to_string())
+    }
+
+    /// Returns the symbol and the source location a diagnostic is about, if they can be told.
+    fn source(&self, message: &str) -> (Option<String>, Option<(String, u32)>) {
+        if let Some(site) = self.inline_asm_site(message) {
+            return (Some(site.function), site.location);
+        }
+        match self.function_source(message) {
+            Some(function) => (Some(function.name), function.location),
+            None => (None, None),
+        }
+    }
+
+    /// Returns the source of the function a diagnostic is about, if it names one.
+    fn function_source(&self, message: &str) -> Option<llvm::FunctionSource> {
+        // Diagnostics about a function, like unsupported operations found during instruction
+        // selection, are formatted as "<location>: in function <symbol> <type>: <message>".
+        let (_, function) = message.split_once("in function ")?;
+        let symbol = function.split(' ').next()?;
+        self.function_sources.borrow().get(symbol).cloned()
+    }
+
+    /// Returns the inline assembly call site an inline assembly diagnostic is about, if it can be
+    /// told.
+    fn inline_asm_site(&self, message: &str) -> Option<llvm::InlineAsmSite> {
+        const MATCHERS: &[&str] = &["inline asm", "inline assembly"];
+
+        if !MATCHERS.iter().any(|matcher| message.contains(matcher))
+            && !message.starts_with("couldn't allocate")
+        {
+            return None;
+        }
+
+        let sites = self.inline_asm_sites.borrow();
+        // Assembler errors quote the offending line of the template after the message itself.
+        let quoted_line = message
+            .lines()
+            .nth(1)
+            .map(str::trim)
+            .filter(|line| !line.is_empty());
+        let site = quoted_line
+            .and_then(|quoted_line| {
+                sites
+                    .iter()
+                    .find(|site| site.asm.lines().any(|line| line.trim() == quoted_line))
+            })
+            .or(match sites.as_slice() {
+                [site] => Some(site),
+                _ => None,
+            });
+        site.cloned()
+    }
+}
+
 impl llvm::LLVMDiagnosticHandler for DiagnosticHandler {
     fn handle_diagnostic(
         &mut self,
@@ -851,22 +3565,65 @@ impl llvm::LLVMDiagnosticHandler for DiagnosticHandler {
             "A call to built-in function 'strlen' is not supported.\n",
         ];
 
-        match severity {
+        let (severity, kind) = match severity {
             llvm_sys::LLVMDiagnosticSeverity::LLVMDSError => {
                 if MATCHERS.iter().any(|matcher| message.ends_with(matcher)) {
                     return;
                 }
-                self.has_errors = true;
-
-                error!("llvm: {}", message)
+                (Severity::Error, None)
+            }
+            llvm_sys::LLVMDiagnosticSeverity::LLVMDSWarning if self.fatal_warnings.get() => {
+                (Severity::Error, Some(WarningKind::Llvm))
+            }
+            llvm_sys::LLVMDiagnosticSeverity::LLVMDSWarning => {
+                (Severity::Warning, Some(WarningKind::Llvm))
+            }
+            llvm_sys::LLVMDiagnosticSeverity::LLVMDSRemark => {
+                debug!("remark: {}", message);
+                self.remarks.push(Remark::parse(&demangle_text(&message)));
+                return;
+            }
+            llvm_sys::LLVMDiagnosticSeverity::LLVMDSNote => {
+                debug!("note: {}", message);
+                return;
             }
-            llvm_sys::LLVMDiagnosticSeverity::LLVMDSWarning => warn!("llvm: {}", message),
-            llvm_sys::LLVMDiagnosticSeverity::LLVMDSRemark => debug!("remark: {}", message),
-            llvm_sys::LLVMDiagnosticSeverity::LLVMDSNote => debug!("note: {}", message),
+        };
+        if severity == Severity::Error {
+            self.has_errors = true;
+        }
+
+        if let Some(sink) = self.sink.borrow().as_ref() {
+            let (symbol, location) = self.source(&message);
+            sink.report(Diagnostic {
+                severity,
+                kind,
+                message: demangle_text(&message),
+                symbol,
+                location,
+            });
+            return;
         }
+        // The origin is found from the symbols of the message, so it's demangled after.
+        let origin = self
+            .origin(&message)
+            .map(|origin| format!(" (in {origin})"))
+            .unwrap_or_default();
+        let message = format!("llvm: {}{origin}", demangle_text(&message));
+        log_diagnostic(&Diagnostic {
+            severity,
+            kind,
+            message,
+            symbol: None,
+            location: None,
+        });
     }
 }
 
+/// Assembly has no magic number, so it's told by the extension of the input.
+fn detect_assembly(path: &Path) -> Option<InputType> {
+    (path.extension() == Some(OsStr::new("s"))).then_some(InputType::Assembly)
+}
+
 fn detect_input_type(data: &[u8]) -> Option<InputType> {
     if data.len() < 8 {
         return None;
@@ -886,13 +3643,47 @@ fn detect_input_type(data: &[u8]) -> Option<InputType> {
     }
 }
 
+/// The output of a link, kept in memory.
+///
+/// The output is [`Send`], so it can be handed to another thread or held across `.await` points,
+/// eg to write it to an async sink with [`LinkerOutput::as_slice`].
 pub struct LinkerOutput {
-    inner: MemoryBuffer,
+    inner: OutputBuffer,
+}
+
+/// Storage of a [`LinkerOutput`], as allocated by LLVM.
+enum OutputBuffer {
+    /// Bitcode and code generated by the target machine.
+    MemoryBuffer(MemoryBuffer),
+    /// Textual LLVM IR.
+    Message(Message),
+    /// A section of an object file generated by the target machine.
+    Section {
+        object: MemoryBuffer,
+        range: Range<usize>,
+    },
 }
 
 impl LinkerOutput {
+    /// Returns the output as a byte slice. The bytes are borrowed from the
+    /// buffer LLVM emitted the output into, no copy is made.
     pub fn as_slice(&self) -> &[u8] {
-        self.inner.as_slice()
+        match &self.inner {
+            OutputBuffer::MemoryBuffer(memory_buffer) => memory_buffer.as_slice(),
+            OutputBuffer::Message(message) => message.as_bytes(),
+            OutputBuffer::Section { object, range } => &object.as_slice()[range.clone()],
+        }
+    }
+
+    /// Writes the output to `writer` directly from the LLVM buffer, without
+    /// any intermediate allocation.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(self.as_slice())
+    }
+
+    /// Returns a reader of the output, which reads directly from the LLVM buffer.
+    pub fn into_reader(self) -> io::Cursor<Self> {
+        io::Cursor::new(self)
     }
 }
 
@@ -909,3 +3700,460 @@ impl Deref for LinkerOutput {
         self.as_slice()
     }
 }
+
+/// Statistics of the last link of a [`Linker`], returned by [`Linker::stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    /// Number of input modules, counting each archive member.
+    pub inputs: usize,
+    /// Number of functions defined once the inputs are linked, before dead code is removed.
+    pub functions_before: usize,
+    /// Number of functions defined after optimization.
+    pub functions_after: usize,
+    /// Number of instructions of the last object generated, counting `ld_imm64` instructions as
+    /// two. Only set with [`Linker::set_output_stats`].
+    pub instructions: Option<u64>,
+    /// Size of the `.BTF` section of the last object or BTF output generated. Only set with
+    /// [`Linker::set_output_stats`].
+    pub btf_size: Option<u64>,
+    pub link_duration: Duration,
+    pub optimize_duration: Duration,
+    /// Time spent generating the outputs.
+    pub codegen_duration: Duration,
+}
+
+/// An LLVM context owned by the application embedding the linker, to link into with
+/// [`Linker::link_in_context`].
+///
+/// The context is disposed when dropped, along with the modules it holds.
+pub struct LLVMContextWrapped {
+    context: LLVMContext,
+    diagnostic_handler: llvm::InstalledDiagnosticHandler<DiagnosticHandler>,
+}
+
+impl LLVMContextWrapped {
+    /// Creates a new LLVM context.
+    pub fn new() -> Self {
+        Self::with_context(LLVMContext::new())
+    }
+
+    /// Takes ownership of `context`, which is disposed when the returned value is dropped.
+    ///
+    /// The diagnostic handler of the context is replaced by the one of the linker.
+    ///
+    /// # Safety
+    ///
+    /// `context` must be a valid context that isn't disposed elsewhere.
+    pub unsafe fn from_raw(context: LLVMContextRef) -> Self {
+        Self::with_context(unsafe { LLVMContext::from_raw(context) })
+    }
+
+    fn with_context(mut context: LLVMContext) -> Self {
+        let diagnostic_handler = context.set_diagnostic_handler(DiagnosticHandler::default());
+        Self {
+            context,
+            diagnostic_handler,
+        }
+    }
+
+    /// Returns the raw LLVM context, which remains valid as long as `self` is.
+    pub fn as_raw(&self) -> LLVMContextRef {
+        self.context.as_mut_ptr()
+    }
+}
+
+impl Default for LLVMContextWrapped {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A module linked by [`Linker::link_in_context`] or [`Linker::link_to_module`], along with the
+/// target machine it was linked for.
+pub struct LLVMModuleWrapped<'ctx> {
+    module: LLVMModule<'ctx>,
+    target_machine: LLVMTargetMachine,
+}
+
+impl LLVMModuleWrapped<'_> {
+    /// Returns the raw LLVM module, which remains valid as long as `self` is. The module can be
+    /// changed through it, but must not be disposed.
+    pub fn as_raw(&self) -> LLVMModuleRef {
+        self.module.as_mut_ptr()
+    }
+
+    /// Returns the raw target machine the module was linked for, which remains valid as long as
+    /// `self` is.
+    pub fn target_machine(&self) -> LLVMTargetMachineRef {
+        self.target_machine.as_mut_ptr()
+    }
+
+    /// Generates the output code of the module, as it is when called.
+    pub fn codegen(&self, output_type: OutputType) -> Result<LinkerOutput, LinkerError> {
+        codegen_to_buffer(&self.module, &self.target_machine, output_type)
+    }
+
+    /// Returns the functions of the module, including the declarations of those it calls without
+    /// defining them, like BPF helpers.
+    pub fn functions(&self) -> impl Iterator<Item = ModuleSymbol<'_>> {
+        llvm::functions(&self.module)
+            .into_iter()
+            .map(ModuleSymbol::new)
+    }
+
+    /// Returns the global variables of the module.
+    pub fn globals(&self) -> impl Iterator<Item = ModuleSymbol<'_>> {
+        llvm::globals(&self.module)
+            .into_iter()
+            .map(ModuleSymbol::new)
+    }
+
+    /// Returns the sections the functions and the global variables of the module are explicitly
+    /// placed in, in the order they first appear.
+    pub fn sections(&self) -> Vec<String> {
+        let mut sections = Vec::new();
+        for symbol in self.functions().chain(self.globals()) {
+            if let Some(section) = symbol.section() {
+                if !sections.iter().any(|s| s == &section) {
+                    sections.push(section.into_owned());
+                }
+            }
+        }
+        sections
+    }
+}
+
+/// A function or a global variable of a [`LLVMModuleWrapped`].
+#[derive(Clone, Copy)]
+pub struct ModuleSymbol<'m> {
+    value: LLVMValueRef,
+    _marker: PhantomData<&'m ()>,
+}
+
+impl ModuleSymbol<'_> {
+    fn new(value: LLVMValueRef) -> Self {
+        Self {
+            value,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the symbol name.
+    pub fn name(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(llvm::symbol_name(self.value))
+    }
+
+    /// Returns the section the symbol is explicitly placed in, if any.
+    pub fn section(&self) -> Option<Cow<'_, str>> {
+        llvm::explicit_section(self.value).map(String::from_utf8_lossy)
+    }
+
+    /// Whether the symbol is declared but not defined in the module.
+    pub fn is_declaration(&self) -> bool {
+        llvm::is_declaration(self.value)
+    }
+}
+
+impl std::fmt::Debug for ModuleSymbol<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ModuleSymbol")
+            .field("name", &self.name())
+            .field("section", &self.section())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_line_info() {
+        for line_info in [LineInfo::Full, LineInfo::Statements, LineInfo::Entry] {
+            assert_eq!(
+                line_info.to_string().parse::<LineInfo>().unwrap(),
+                line_info
+            );
+        }
+        assert!("all".parse::<LineInfo>().is_err());
+    }
+
+    #[test]
+    fn test_target_big_endian() {
+        assert_eq!(target_big_endian(c"bpfeb"), Some(true));
+        assert_eq!(target_big_endian(c"bpfel-unknown-none"), Some(false));
+        assert_eq!(target_big_endian(c"bpf"), Some(cfg!(target_endian = "big")));
+        assert_eq!(target_big_endian(c"bpfe"), None);
+        assert_eq!(target_big_endian(c"x86_64-unknown-linux-gnu"), None);
+    }
+
+    #[test]
+    fn test_duplicate_symbols() {
+        for duplicate_symbols in [
+            DuplicateSymbols::Error,
+            DuplicateSymbols::FirstWins,
+            DuplicateSymbols::LastWins,
+            DuplicateSymbols::RenameSecond,
+        ] {
+            assert_eq!(
+                duplicate_symbols
+                    .to_string()
+                    .parse::<DuplicateSymbols>()
+                    .unwrap(),
+                duplicate_symbols
+            );
+        }
+        assert!("rename".parse::<DuplicateSymbols>().is_err());
+    }
+
+    #[test]
+    fn test_warning_kind() {
+        for &kind in WarningKind::ALL {
+            assert_eq!(kind.to_string().parse::<WarningKind>().unwrap(), kind);
+        }
+        assert!("verifier-lints".parse::<WarningKind>().is_err());
+    }
+
+    #[test]
+    fn test_read_archive() {
+        let mut archive = ar::Builder::new(Vec::new());
+        for (name, data) in [
+            ("foo.o", b"\x42\x43\xC0\xDE\0\0\0\0".as_slice()),
+            ("lib.rmeta", b"rust\0\0\0\0".as_slice()),
+        ] {
+            archive
+                .append(&ar::Header::new(name.into(), data.len() as u64), data)
+                .unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+
+        let inputs = open_inputs(
+            [InputReader::Buffer {
+                name: "libfoo.rlib",
+                cursor: io::Cursor::new(archive.as_slice()),
+            }],
+            None,
+        )
+        .unwrap();
+        // Members are read in any order.
+        let modules = inputs
+            .modules
+            .iter()
+            .rev()
+            .map(|&module| {
+                let InputModule {
+                    path,
+                    archive,
+                    data,
+                    ..
+                } = inputs.read(module).unwrap();
+                (path, archive, data)
+            })
+            .collect::<Vec<_>>();
+        let archive = PathBuf::from("in_memory::libfoo.rlib");
+        assert_eq!(
+            modules,
+            [
+                (
+                    PathBuf::from("lib.rmeta"),
+                    Some(archive.clone()),
+                    b"rust\0\0\0\0".to_vec()
+                ),
+                (
+                    PathBuf::from("foo.o"),
+                    Some(archive),
+                    b"\x42\x43\xC0\xDE\0\0\0\0".to_vec()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_output_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<LinkerOutput>();
+    }
+
+    #[test]
+    fn test_input_size_limit() {
+        let bitcode = b"\x42\x43\xC0\xDE\0\0\0\0\0\0\0\0".as_slice();
+        let inputs = || {
+            ["a", "b"].map(|name| InputReader::Buffer {
+                name,
+                cursor: io::Cursor::new(bitcode),
+            })
+        };
+
+        assert_eq!(open_inputs(inputs(), Some(24)).unwrap().modules.len(), 2);
+        match open_inputs(inputs(), Some(23)).err() {
+            Some(LinkerError::InputSizeLimitExceeded(path, 24, 23)) => {
+                assert_eq!(path, Path::new("in_memory::b"))
+            }
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_map_section_rule() {
+        assert_eq!(
+            "EVENTS=.maps".parse::<MapSectionRule>().unwrap(),
+            MapSectionRule {
+                pattern: "EVENTS".into(),
+                section: MapSection::Btf,
+            }
+        );
+        assert_eq!(
+            "legacy_*=maps".parse::<MapSectionRule>().unwrap(),
+            MapSectionRule {
+                pattern: "legacy_*".into(),
+                section: MapSection::Legacy,
+            }
+        );
+        for rule in ["EVENTS", "EVENTS=", "=maps", "EVENTS=.bss"] {
+            assert!(rule.parse::<MapSectionRule>().is_err(), "{rule}");
+        }
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("EVENTS", "EVENTS"));
+        assert!(!matches_pattern("EVENTS", "EVENTS_2"));
+        assert!(matches_pattern("*", ""));
+        assert!(matches_pattern("EVENTS*", "EVENTS_2"));
+        assert!(matches_pattern("*_MAP", "PID_MAP"));
+        assert!(!matches_pattern("*_MAP", "PID_MAPS"));
+        assert!(matches_pattern("a*b*c", "a_b_b_c"));
+        assert!(!matches_pattern("a*b*c", "a_c_b"));
+        assert!(!matches_pattern("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("map", ""), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("bpf_map", "bpf_map"), 0);
+        assert_eq!(edit_distance("bpf_mpa", "bpf_map"), 2);
+    }
+
+    #[test]
+    fn test_suggest_symbol() {
+        let symbol_origins = HashMap::from([
+            ("handle_packet".to_owned(), PathBuf::from("libprobe.rlib")),
+            (
+                "_ZN6common12parse_header17h0123456789abcdefE".to_owned(),
+                PathBuf::from("libcommon.rlib"),
+            ),
+        ]);
+        assert_eq!(
+            suggest_symbol("handle_pakcet", &symbol_origins),
+            Some(("handle_packet", Path::new("libprobe.rlib")))
+        );
+        assert_eq!(
+            suggest_symbol("parse_headers", &symbol_origins),
+            Some((
+                "_ZN6common12parse_header17h0123456789abcdefE",
+                Path::new("libcommon.rlib")
+            ))
+        );
+        assert_eq!(suggest_symbol("unrelated", &symbol_origins), None);
+    }
+
+    struct FlakySink {
+        data: Vec<u8>,
+        offsets: Vec<u64>,
+        failures: u32,
+    }
+
+    impl ChunkedSink for FlakySink {
+        fn write_chunk(&mut self, offset: u64, chunk: &[u8]) -> Result<(), ChunkError> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(ChunkError::Transient(io::ErrorKind::TimedOut.into()));
+            }
+            assert_eq!(offset, self.data.len() as u64);
+            self.offsets.push(offset);
+            self.data.extend_from_slice(chunk);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_chunked() {
+        let data = (0..10).collect::<Vec<u8>>();
+        let chunk_size = NonZeroUsize::new(4).unwrap();
+
+        let mut sink = FlakySink {
+            data: Vec::new(),
+            offsets: Vec::new(),
+            failures: 2,
+        };
+        write_chunked(&data, &mut sink, chunk_size, 2).unwrap();
+        assert_eq!(sink.data, data);
+        assert_eq!(sink.offsets, [0, 4, 8]);
+
+        let mut sink = FlakySink {
+            data: Vec::new(),
+            offsets: Vec::new(),
+            failures: 3,
+        };
+        let err = write_chunked(&data, &mut sink, chunk_size, 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_inline_asm_site() {
+        let site = |function: &str, asm: &str| llvm::InlineAsmSite {
+            function: function.to_owned(),
+            asm: asm.to_owned(),
+            location: Some(("src/lib.rs".to_owned(), 42)),
+        };
+        let handler = DiagnosticHandler::default();
+        *handler.inline_asm_sites.borrow_mut() = vec![
+            site("foo", "r0 = 1\nexit"),
+            site("bar", "r0 = *(u64 *)(r1 + 0)"),
+        ];
+
+        let site = handler
+            .inline_asm_site(
+                "<inline asm>:1:14: error: unexpected token\n\tr0 = *(u64 *)(r1 + 0)\n",
+            )
+            .unwrap();
+        assert_eq!(site.function, "bar");
+        assert_eq!(site.to_string(), "function `bar` at src/lib.rs:42");
+
+        // Without a quoted line the call can't be told apart from the other ones.
+        assert!(handler
+            .inline_asm_site("couldn't allocate output register for constraint 'r'")
+            .is_none());
+        assert!(handler
+            .inline_asm_site("A call to built-in function 'memcpy' is not supported.\n")
+            .is_none());
+    }
+
+    #[test]
+    fn test_function_source() {
+        let handler = DiagnosticHandler::default();
+        let _: Option<llvm::FunctionSource> = handler.function_sources.borrow_mut().insert(
+            "_ZN4prog3foo17h0123456789abcdefE".to_owned(),
+            llvm::FunctionSource {
+                name: "prog::foo".to_owned(),
+                location: Some(("src/main.rs".to_owned(), 7)),
+            },
+        );
+
+        assert_eq!(
+            handler
+                .origin(
+                    "src/main.rs:9:5: in function _ZN4prog3foo17h0123456789abcdefE i32 (ptr): \
+                     unsupported signed division"
+                )
+                .as_deref(),
+            Some("function `prog::foo` defined at src/main.rs:7")
+        );
+        assert_eq!(
+            handler.origin("<unknown>:0:0: in function bar void (): unsupported"),
+            None
+        );
+    }
+}
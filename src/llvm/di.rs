@@ -9,7 +9,7 @@ use std::{
 
 use gimli::{DW_TAG_pointer_type, DW_TAG_structure_type, DW_TAG_variant_part};
 use llvm_sys::{core::*, debuginfo::*, prelude::*};
-use tracing::{span, trace, warn, Level};
+use tracing::{span, trace, Level};
 
 use super::types::{
     di::DIType,
@@ -28,6 +28,8 @@ pub(crate) struct DISanitizer<'ctx> {
     builder: LLVMDIBuilderRef,
     visited_nodes: HashSet<u64>,
     replace_operands: HashMap<u64, LLVMMetadataRef>,
+    // The first definition of each composite type, by identifier.
+    unique_types: HashMap<Vec<u8>, LLVMMetadataRef>,
     skipped_types_lossy: Vec<String>,
     // TODO: use references of safe wrappers instead of PhantomData
     _marker: PhantomData<LLVMModule<'ctx>>,
@@ -66,6 +68,7 @@ impl<'ctx> DISanitizer<'ctx> {
             builder: unsafe { LLVMCreateDIBuilder(module.as_mut_ptr()) },
             visited_nodes: HashSet::new(),
             replace_operands: HashMap::new(),
+            unique_types: HashMap::new(),
             skipped_types_lossy: Vec::new(),
             _marker: PhantomData,
         }
@@ -193,6 +196,33 @@ impl<'ctx> DISanitizer<'ctx> {
         }
     }
 
+    // Modules are linked in contexts that don't unique types by identifier, so each module brings
+    // its own copy of the types it uses, and BTF would have a type for each copy. References to
+    // copies of a type are replaced with references to its first definition.
+    fn unique_type(&mut self, value_ref: LLVMValueRef, mdnode: MDNode<'_>) {
+        let Ok(Metadata::DICompositeType(di_composite_type)) = mdnode.try_into() else {
+            return;
+        };
+        let Some(identifier) = di_composite_type.identifier() else {
+            return;
+        };
+        let metadata = unsafe { LLVMValueAsMetadata(value_ref) };
+        let is_declaration = di_composite_type.flags() == LLVMDIFlagFwdDecl;
+        match self.unique_types.get(identifier) {
+            Some(&unique) if unique != metadata => {
+                let _: Option<LLVMMetadataRef> =
+                    self.replace_operands.insert(value_ref as u64, unique);
+            }
+            Some(_) => {}
+            // Declarations are replaced by definitions, but not the other way around.
+            None if is_declaration => {}
+            None => {
+                let _: Option<LLVMMetadataRef> =
+                    self.unique_types.insert(identifier.to_owned(), metadata);
+            }
+        }
+    }
+
     // navigate the tree of LLVMValueRefs (DFS-pre-order)
     fn visit_item(&mut self, mut item: Item) {
         let value_ref = item.value_ref();
@@ -211,6 +241,14 @@ impl<'ctx> DISanitizer<'ctx> {
             (_, item) => panic!("{item:?} has no value"),
         };
 
+        // Types must be uniqued on their first visit, before the operand referring to them is
+        // checked for replacement.
+        if let Value::MDNode(mdnode) = value.clone() {
+            if !self.visited_nodes.contains(&value_id) {
+                self.unique_type(value_ref, mdnode)
+            }
+        }
+
         if let Item::Operand(operand) = &mut item {
             // When we have an operand to replace, we must do so regardless of whether we've already
             // seen its value or not, since the same value can appear as an operand in multiple
@@ -262,7 +300,9 @@ impl<'ctx> DISanitizer<'ctx> {
         }
     }
 
-    pub(crate) fn run(mut self, exported_symbols: &HashSet<Cow<'_, [u8]>>) {
+    /// Sanitizes the debug info of the module. Returns the names of the types for which no debug
+    /// info was emitted.
+    pub(crate) fn run(mut self, exported_symbols: &HashSet<Cow<'_, [u8]>>) -> Vec<String> {
         let module = self.module;
 
         self.replace_operands = self.fix_subprogram_linkage(exported_symbols);
@@ -278,14 +318,9 @@ impl<'ctx> DISanitizer<'ctx> {
             self.visit_item(Item::Function(function));
         }
 
-        if !self.skipped_types_lossy.is_empty() {
-            warn!(
-                "debug info was not emitted for the following types: {}",
-                self.skipped_types_lossy.join(", ")
-            );
-        }
-
         unsafe { LLVMDisposeDIBuilder(self.builder) };
+
+        self.skipped_types_lossy
     }
 
     // Make it so that only exported symbols (programs marked as #[no_mangle]) get BTF
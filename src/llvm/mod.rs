@@ -4,24 +4,47 @@ mod types;
 
 use std::{
     borrow::Cow,
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     ffi::{CStr, CString},
-    os::raw::c_char,
+    fmt,
+    os::raw::{c_char, c_uint},
     ptr, slice, str,
 };
 
 pub(crate) use di::DISanitizer;
-use iter::{IterModuleFunctions as _, IterModuleGlobalAliases as _, IterModuleGlobals as _};
+use iter::{
+    IterBasicBlocks as _, IterInstructions as _, IterModuleFunctions as _,
+    IterModuleGlobalAliases as _, IterModuleGlobals as _,
+};
+#[cfg(not(feature = "llvm-19"))]
+use llvm_sys::transforms::pass_builder::LLVMPassBuilderOptionsSetInlinerThreshold;
 use llvm_sys::{
     bit_reader::LLVMParseBitcodeInContext2,
     core::{
-        LLVMCreateMemoryBufferWithMemoryRange, LLVMDisposeMemoryBuffer, LLVMDisposeMessage,
-        LLVMGetEnumAttributeKindForName, LLVMGetMDString, LLVMGetModuleInlineAsm, LLVMGetTarget,
-        LLVMGetValueName2, LLVMRemoveEnumAttributeAtIndex, LLVMSetLinkage, LLVMSetModuleInlineAsm2,
-        LLVMSetVisibility,
+        LLVMConstIntGetZExtValue, LLVMCountStructElementTypes,
+        LLVMCreateMemoryBufferWithMemoryRange, LLVMDeleteGlobal, LLVMDisposeMemoryBuffer,
+        LLVMDisposeMessage, LLVMDisposeModule, LLVMGetAggregateElement, LLVMGetArrayLength2,
+        LLVMGetBasicBlockParent, LLVMGetBasicBlockTerminator, LLVMGetCalledValue,
+        LLVMGetConstOpcode, LLVMGetDataLayoutStr, LLVMGetDebugLocFilename, LLVMGetDebugLocLine,
+        LLVMGetElementType, LLVMGetEnumAttributeKindForName, LLVMGetFirstUse, LLVMGetInitializer,
+        LLVMGetInlineAsmAsmString, LLVMGetInstructionParent, LLVMGetIntTypeWidth, LLVMGetLinkage,
+        LLVMGetMDString, LLVMGetModuleInlineAsm, LLVMGetNamedFunction, LLVMGetNamedGlobal,
+        LLVMGetNextUse, LLVMGetNumArgOperands, LLVMGetNumSuccessors, LLVMGetOperand, LLVMGetPoison,
+        LLVMGetSection, LLVMGetSuccessor, LLVMGetTarget, LLVMGetTypeKind, LLVMGetUser,
+        LLVMGetValueName2, LLVMGetVersion, LLVMGlobalGetValueType, LLVMInstructionGetDebugLoc,
+        LLVMInstructionSetDebugLoc, LLVMIsACallInst, LLVMIsAConstantExpr, LLVMIsAConstantInt,
+        LLVMIsAFunction, LLVMIsAGlobalValue, LLVMIsAInlineAsm, LLVMIsAInstruction,
+        LLVMIsDeclaration, LLVMIsGlobalConstant, LLVMIsNull, LLVMIsUndef,
+        LLVMModuleCreateWithNameInContext, LLVMRemoveEnumAttributeAtIndex, LLVMReplaceAllUsesWith,
+        LLVMSetExternallyInitialized, LLVMSetLinkage, LLVMSetModuleInlineAsm2, LLVMSetSection,
+        LLVMSetValueName2, LLVMSetVisibility, LLVMStructGetTypeAtIndex, LLVMTypeOf,
+    },
+    debuginfo::{
+        LLVMDIFileGetFilename, LLVMDIScopeGetFile, LLVMDISubprogramGetLine, LLVMGetSubprogram,
     },
     error::{
-        LLVMDisposeErrorMessage, LLVMGetErrorMessage, LLVMGetErrorTypeId, LLVMGetStringErrorTypeId,
+        LLVMDisposeErrorMessage, LLVMErrorRef, LLVMGetErrorMessage, LLVMGetErrorTypeId,
+        LLVMGetStringErrorTypeId,
     },
     linker::LLVMLinkModules2,
     object::{
@@ -32,26 +55,28 @@ use llvm_sys::{
     prelude::{LLVMModuleRef, LLVMValueRef},
     support::LLVMParseCommandLineOptions,
     target::{
+        LLVMABISizeOfType, LLVMByteOrder, LLVMByteOrdering, LLVMDisposeTargetData,
         LLVMInitializeBPFAsmParser, LLVMInitializeBPFAsmPrinter, LLVMInitializeBPFDisassembler,
         LLVMInitializeBPFTarget, LLVMInitializeBPFTargetInfo, LLVMInitializeBPFTargetMC,
+        LLVMOffsetOfElement, LLVMStoreSizeOfType, LLVMTargetDataRef,
     },
-    target_machine::{LLVMGetTargetFromTriple, LLVMTargetRef},
+    target_machine::{LLVMCreateTargetDataLayout, LLVMGetTargetFromTriple},
     transforms::pass_builder::{
         LLVMCreatePassBuilderOptions, LLVMDisposePassBuilderOptions, LLVMRunPasses,
     },
-    LLVMAttributeFunctionIndex, LLVMLinkage, LLVMVisibility,
+    LLVMAttributeFunctionIndex, LLVMLinkage, LLVMOpcode, LLVMTypeKind, LLVMVisibility,
 };
-use tracing::{debug, error};
+use tracing::{debug, error, info, info_span};
 pub(crate) use types::{
     context::{InstalledDiagnosticHandler, LLVMContext},
     memory_buffer::MemoryBuffer,
     module::LLVMModule,
-    target_machine::LLVMTargetMachine,
+    target_machine::{LLVMTarget, LLVMTargetMachine, LLVMTargetMachineBuilder},
 };
 
-use crate::OptLevel;
+use crate::{demangle::demangle, DuplicateSymbols, OptLevel};
 
-pub(crate) fn init(args: &[Cow<'_, CStr>], overview: &CStr) {
+pub(crate) fn init() {
     unsafe {
         LLVMInitializeBPFTarget();
         LLVMInitializeBPFTargetMC();
@@ -60,11 +85,21 @@ pub(crate) fn init(args: &[Cow<'_, CStr>], overview: &CStr) {
         LLVMInitializeBPFAsmParser();
         LLVMInitializeBPFDisassembler();
     }
+}
 
+/// Sets the command line options of LLVM. Each option can only be given once per process.
+pub(crate) fn parse_command_line(args: &[Cow<'_, CStr>], overview: &CStr) {
     let c_ptrs = args.iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
     unsafe { LLVMParseCommandLineOptions(c_ptrs.len() as i32, c_ptrs.as_ptr(), overview.as_ptr()) };
 }
 
+/// Returns the major, minor and patch versions of the LLVM the linker uses.
+pub(crate) fn version() -> (u32, u32, u32) {
+    let (mut major, mut minor, mut patch): (c_uint, c_uint, c_uint) = (0, 0, 0);
+    unsafe { LLVMGetVersion(&mut major, &mut minor, &mut patch) };
+    (major, minor, patch)
+}
+
 pub(crate) fn find_embedded_bitcode(
     context: &LLVMContext,
     data: &[u8],
@@ -93,8 +128,12 @@ pub(crate) fn find_embedded_bitcode(
             let name = unsafe { CStr::from_ptr(name) };
             if name == c".llvmbc" {
                 let buf = unsafe { LLVMGetSectionContents(iter) };
-                let size = unsafe { LLVMGetSectionSize(iter) } as usize;
-                ret = Some(unsafe { slice::from_raw_parts(buf.cast(), size).to_vec() });
+                let size = unsafe { LLVMGetSectionSize(iter) };
+                ret = Some(
+                    usize::try_from(size)
+                        .map(|size| unsafe { slice::from_raw_parts(buf.cast(), size).to_vec() })
+                        .map_err(|_| format!(".llvmbc section of {size} bytes is too large")),
+                );
                 break;
             }
         }
@@ -104,16 +143,32 @@ pub(crate) fn find_embedded_bitcode(
     unsafe { LLVMDisposeBinary(bin) };
     unsafe { LLVMDisposeMemoryBuffer(buffer) };
 
-    Ok(ret)
+    ret.transpose()
+}
+
+/// Errors returned by [`link_bitcode_buffer`].
+#[derive(Debug)]
+pub(crate) enum LinkBitcodeError {
+    /// The buffer isn't valid bitcode.
+    Parse,
+    /// Linking the parsed module failed.
+    Link,
+    /// The byte order of the parsed module isn't the one of the modules linked before it.
+    ByteOrder { big_endian: bool },
+    /// The data layout of the parsed module isn't the one of the modules linked before it.
+    DataLayout { expected: String, found: String },
+    /// The target triple of the parsed module isn't the one of the modules linked before it.
+    Triple { expected: String, found: String },
 }
 
-#[must_use]
 pub(crate) fn link_bitcode_buffer<'ctx>(
     context: &'ctx LLVMContext,
     module: &mut LLVMModule<'ctx>,
     buffer: &[u8],
-) -> bool {
-    let mut linked = false;
+    duplicate_symbols: DuplicateSymbols,
+    defined_symbols: Option<&mut Vec<String>>,
+) -> Result<(), LinkBitcodeError> {
+    let mut ret = Err(LinkBitcodeError::Parse);
     let buffer_name = c"mem_buffer";
     let buffer = unsafe {
         LLVMCreateMemoryBufferWithMemoryRange(
@@ -127,44 +182,244 @@ pub(crate) fn link_bitcode_buffer<'ctx>(
     let mut temp_module = ptr::null_mut();
 
     if unsafe { LLVMParseBitcodeInContext2(context.as_mut_ptr(), buffer, &mut temp_module) } == 0 {
-        linked = unsafe { LLVMLinkModules2(module.as_mut_ptr(), temp_module) } == 0;
+        // The symbols must be collected before linking destroys the temporary module.
+        if let Some(defined_symbols) = defined_symbols {
+            defined_symbols.extend(
+                temp_module
+                    .functions_iter()
+                    .chain(temp_module.globals_iter())
+                    .filter(|&value| {
+                        !symbol_name(value).starts_with(b"llvm.")
+                            && unsafe { LLVMIsDeclaration(value) } == 0
+                    })
+                    .map(|value| String::from_utf8_lossy(symbol_name(value)).into_owned()),
+            );
+        }
+        ret = match check_compatible(module.as_mut_ptr(), temp_module) {
+            Ok(()) => {
+                resolve_duplicate_symbols(module.as_mut_ptr(), temp_module, duplicate_symbols);
+                if unsafe { LLVMLinkModules2(module.as_mut_ptr(), temp_module) } == 0 {
+                    Ok(())
+                } else {
+                    Err(LinkBitcodeError::Link)
+                }
+            }
+            Err(e) => {
+                unsafe { LLVMDisposeModule(temp_module) };
+                Err(e)
+            }
+        };
     }
 
     unsafe { LLVMDisposeMemoryBuffer(buffer) };
 
-    linked
+    ret
+}
+
+/// Checks that `other` can be linked into `module`. LLVM only warns when linking modules with
+/// different data layouts or target triples, without saying which input caused it.
+///
+/// Modules without a data layout or a triple, like the first module linked into an empty one,
+/// are compatible with any module. BPF triples differing only in their spelling, like `bpf` and
+/// `bpfel-unknown-none`, are compatible when the data layouts are.
+fn check_compatible(module: LLVMModuleRef, other: LLVMModuleRef) -> Result<(), LinkBitcodeError> {
+    if let (Some(expected), Some(found)) = (big_endian(module), big_endian(other)) {
+        if expected != found {
+            return Err(LinkBitcodeError::ByteOrder { big_endian: found });
+        }
+    }
+
+    let [expected, found] =
+        [module, other].map(|module| unsafe { CStr::from_ptr(LLVMGetDataLayoutStr(module)) });
+    if !expected.is_empty() && !found.is_empty() && expected != found {
+        return Err(LinkBitcodeError::DataLayout {
+            expected: expected.to_string_lossy().into_owned(),
+            found: found.to_string_lossy().into_owned(),
+        });
+    }
+
+    let [expected, found] =
+        [module, other].map(|module| unsafe { CStr::from_ptr(LLVMGetTarget(module)) });
+    let is_bpf = |triple: &CStr| triple.to_bytes().starts_with(b"bpf");
+    if !expected.is_empty()
+        && !found.is_empty()
+        && expected != found
+        && !(is_bpf(expected) && is_bpf(found))
+    {
+        return Err(LinkBitcodeError::Triple {
+            expected: expected.to_string_lossy().into_owned(),
+            found: found.to_string_lossy().into_owned(),
+        });
+    }
+    Ok(())
+}
+
+/// Whether the data layout of `module` is big-endian, or `None` if the module has no data layout,
+/// like modules made of assembly.
+pub(crate) fn big_endian(module: LLVMModuleRef) -> Option<bool> {
+    let data_layout = unsafe { CStr::from_ptr(LLVMGetDataLayoutStr(module)) }.to_bytes();
+    if data_layout.is_empty() {
+        return None;
+    }
+    // Data layouts are little-endian unless they start with `E`.
+    Some(data_layout.split(|&b| b == b'-').any(|spec| spec == b"E"))
+}
+
+/// Resolves the symbols that both `module` and `other` define with external linkage according to
+/// `policy`, before `other` is linked into `module`. LLVM fails to link such symbols, but keeps the
+/// strong definition of a symbol that also has a weak one.
+fn resolve_duplicate_symbols(
+    module: LLVMModuleRef,
+    other: LLVMModuleRef,
+    policy: DuplicateSymbols,
+) {
+    if policy == DuplicateSymbols::Error {
+        return;
+    }
+    let is_strong_definition = |value: LLVMValueRef| {
+        !value.is_null()
+            && unsafe { LLVMIsDeclaration(value) } == 0
+            && unsafe { LLVMGetLinkage(value) } == LLVMLinkage::LLVMExternalLinkage
+    };
+    let defined = |module: LLVMModuleRef, name: &CStr| {
+        [
+            unsafe { LLVMGetNamedFunction(module, name.as_ptr()) },
+            unsafe { LLVMGetNamedGlobal(module, name.as_ptr()) },
+        ]
+        .into_iter()
+        .find(|&value| is_strong_definition(value))
+    };
+
+    let values = other
+        .functions_iter()
+        .chain(other.globals_iter())
+        .filter(|&value| is_strong_definition(value))
+        .collect::<Vec<_>>();
+    for value in values {
+        let name = symbol_name(value).to_vec();
+        let c_name = CString::new(name.as_slice()).unwrap();
+        let Some(existing) = defined(module, &c_name) else {
+            continue;
+        };
+        let name = String::from_utf8_lossy(&name);
+        match policy {
+            DuplicateSymbols::Error => unreachable!(),
+            // The weak definition is dropped in favor of the strong one when linking.
+            DuplicateSymbols::FirstWins => {
+                info!(
+                    "duplicate symbol `{}`: keeping the first definition",
+                    demangle(&name)
+                );
+                unsafe { LLVMSetLinkage(value, LLVMLinkage::LLVMWeakAnyLinkage) };
+            }
+            DuplicateSymbols::LastWins => {
+                info!(
+                    "duplicate symbol `{}`: keeping the last definition",
+                    demangle(&name)
+                );
+                unsafe { LLVMSetLinkage(existing, LLVMLinkage::LLVMWeakAnyLinkage) };
+            }
+            DuplicateSymbols::RenameSecond => {
+                let renamed = (1..)
+                    .map(|index| CString::new(format!("{name}.{index}")).unwrap())
+                    .find(|renamed| {
+                        [module, other].iter().all(|&module| {
+                            unsafe { LLVMGetNamedFunction(module, renamed.as_ptr()) }.is_null()
+                                && unsafe { LLVMGetNamedGlobal(module, renamed.as_ptr()) }.is_null()
+                        })
+                    })
+                    .unwrap();
+                info!(
+                    "duplicate symbol `{}`: renaming the definition to `{}`",
+                    demangle(&name),
+                    renamed.to_string_lossy()
+                );
+                unsafe { LLVMSetValueName2(value, renamed.as_ptr(), renamed.as_bytes().len()) };
+            }
+        }
+    }
 }
 
-pub(crate) fn target_from_triple(triple: &CStr) -> Result<LLVMTargetRef, String> {
+/// Links BPF assembly into `module`. The assembly becomes module level inline assembly, which is
+/// assembled along with the code generated for the rest of the module.
+#[must_use]
+pub(crate) fn link_assembly<'ctx>(
+    context: &'ctx LLVMContext,
+    module: &mut LLVMModule<'ctx>,
+    asm: &[u8],
+) -> bool {
+    let temp_module =
+        unsafe { LLVMModuleCreateWithNameInContext(c"assembly".as_ptr(), context.as_mut_ptr()) };
+    unsafe { LLVMSetModuleInlineAsm2(temp_module, asm.as_ptr().cast(), asm.len()) };
+    // The temporary module is destroyed by linking.
+    unsafe { LLVMLinkModules2(module.as_mut_ptr(), temp_module) == 0 }
+}
+
+pub(crate) fn target_from_triple(triple: &CStr) -> Result<LLVMTarget, String> {
     let mut target = ptr::null_mut();
     let (ret, message) = Message::with(|message| unsafe {
         LLVMGetTargetFromTriple(triple.as_ptr(), &mut target, message)
     });
     if ret == 0 {
-        Ok(target)
+        Ok(LLVMTarget { target })
     } else {
         Err(message.as_string_lossy().to_string())
     }
 }
 
-pub(crate) fn target_from_module(module: &LLVMModule<'_>) -> Result<LLVMTargetRef, String> {
-    let triple = unsafe { LLVMGetTarget(module.as_mut_ptr()) };
-    unsafe { target_from_triple(CStr::from_ptr(triple)) }
+pub(crate) fn target_from_module(module: &LLVMModule<'_>) -> Result<LLVMTarget, String> {
+    target_from_triple(module.get_target())
+}
+
+/// Inlining threshold of the Oz pipeline, `InlineConstants::OptMinSizeThreshold` in LLVM.
+const OPT_MIN_SIZE_INLINE_THRESHOLD: i32 = 5;
+
+/// Returns the threshold of the inliner at `opt_level`, unless `inline_threshold` overrides it, or
+/// `None` for the threshold of the pipeline.
+pub(crate) fn effective_inline_threshold(
+    opt_level: OptLevel,
+    inline_threshold: Option<i32>,
+) -> Option<i32> {
+    inline_threshold.or(match opt_level {
+        OptLevel::SizeMin => Some(OPT_MIN_SIZE_INLINE_THRESHOLD),
+        _ => None,
+    })
+}
+
+/// Settings of the passes run by [`optimize`].
+pub(crate) struct Passes<'a> {
+    pub(crate) opt_level: OptLevel,
+    /// Pipeline run instead of the default pipeline of `opt_level`.
+    pub(crate) pipeline: Option<&'a CStr>,
+    pub(crate) inline_threshold: Option<i32>,
+    pub(crate) ignore_inline_never: bool,
+    pub(crate) merge_functions: bool,
 }
 
 pub(crate) fn optimize(
     tm: &LLVMTargetMachine,
     module: &mut LLVMModule<'_>,
-    opt_level: OptLevel,
-    ignore_inline_never: bool,
+    passes: &Passes<'_>,
     export_symbols: &HashSet<Cow<'_, [u8]>>,
 ) -> Result<(), String> {
+    let Passes {
+        opt_level,
+        pipeline,
+        inline_threshold,
+        ignore_inline_never,
+        merge_functions,
+    } = *passes;
     if module_asm_is_probestack(module.as_mut_ptr()) {
         unsafe { LLVMSetModuleInlineAsm2(module.as_mut_ptr(), ptr::null_mut(), 0) };
     }
 
+    let internalize_span = info_span!("internalize").entered();
     for sym in module.as_mut_ptr().globals_iter() {
-        internalize(sym, symbol_name(sym), export_symbols);
+        if is_map(sym) {
+            normalize_map(sym);
+        } else {
+            internalize(sym, symbol_name(sym), export_symbols);
+        }
     }
     for sym in module.as_mut_ptr().global_aliases_iter() {
         internalize(sym, symbol_name(sym), export_symbols);
@@ -179,16 +434,23 @@ pub(crate) fn optimize(
             internalize(function, name, export_symbols);
         }
     }
+    drop(internalize_span);
 
-    let passes = [
+    let mut passes = vec![
         // NB: "default<_>" must be the first pass in the list, otherwise it will be ignored.
-        match opt_level {
-            // Pretty much nothing compiles with -O0 so make it an alias for -O1.
-            OptLevel::No | OptLevel::Less => "default<O1>",
-            OptLevel::Default => "default<O2>",
-            OptLevel::Aggressive => "default<O3>",
-            OptLevel::Size => "default<Os>",
-            OptLevel::SizeMin => "default<Oz>",
+        match pipeline {
+            Some(pipeline) => pipeline.to_str().map_err(|err| err.to_string())?,
+            None => match opt_level {
+                // Pretty much nothing compiles with -O0 so make it an alias for -O1.
+                OptLevel::No | OptLevel::Less => "default<O1>",
+                OptLevel::Default => "default<O2>",
+                OptLevel::Aggressive => "default<O3>",
+                // The Oz pipeline disables header duplication in loop rotation, leaving loops with
+                // an extra branch that the verifier must explore on every iteration. BPF
+                // instructions have a fixed size, so Oz doesn't make programs smaller than Os
+                // otherwise, except through less inlining, which is kept below.
+                OptLevel::Size | OptLevel::SizeMin => "default<Os>",
+            },
         },
         // NB: This seems to be included in most default pipelines, but not obviously all of them.
         // See
@@ -196,11 +458,28 @@ pub(crate) fn optimize(
         // for a case which includes DCE only conditionally. Better safe than sorry; include it always.
         "dce",
     ];
+    if pipeline.is_none() && matches!(opt_level, OptLevel::Size | OptLevel::SizeMin) {
+        // Merge the instructions common to both sides of branches, which the size pipelines
+        // only sink. BPF programs are limited in instructions rather than in bytes, and the
+        // verifier explores each side of a branch.
+        passes.push("simplifycfg<hoist-common-insts;sink-common-insts>");
+    }
+    if merge_functions {
+        // Functions in different sections aren't merged, so programs stay in their own section.
+        passes.push("mergefunc");
+    }
 
     let passes = passes.join(",");
     debug!("running passes: {passes}");
     let passes = CString::new(passes).unwrap();
     let options = unsafe { LLVMCreatePassBuilderOptions() };
+    // LLVM 19 has no C API for the threshold, which is then set with `--inline-threshold`.
+    #[cfg(not(feature = "llvm-19"))]
+    if let Some(inline_threshold) = effective_inline_threshold(opt_level, inline_threshold) {
+        unsafe { LLVMPassBuilderOptionsSetInlinerThreshold(options, inline_threshold) };
+    }
+    #[cfg(feature = "llvm-19")]
+    let _ = inline_threshold;
     let error = unsafe {
         LLVMRunPasses(
             module.as_mut_ptr(),
@@ -210,6 +489,10 @@ pub(crate) fn optimize(
         )
     };
     unsafe { LLVMDisposePassBuilderOptions(options) };
+    pass_error(error)
+}
+
+fn pass_error(error: LLVMErrorRef) -> Result<(), String> {
     // Handle the error and print it to stderr.
     if !error.is_null() {
         let error_type_id = unsafe { LLVMGetErrorTypeId(error) };
@@ -226,6 +509,98 @@ pub(crate) fn optimize(
     Ok(())
 }
 
+/// Returns the functions of `module`.
+pub(crate) fn functions(module: &LLVMModule<'_>) -> Vec<LLVMValueRef> {
+    module.as_mut_ptr().functions_iter().collect()
+}
+
+/// Returns the global variables of `module`.
+pub(crate) fn globals(module: &LLVMModule<'_>) -> Vec<LLVMValueRef> {
+    module.as_mut_ptr().globals_iter().collect()
+}
+
+/// Returns the section `value` is explicitly placed in, if any.
+pub(crate) fn explicit_section<'a>(value: LLVMValueRef) -> Option<&'a [u8]> {
+    let section = unsafe { LLVMGetSection(value) };
+    if section.is_null() {
+        return None;
+    }
+    let section = unsafe { CStr::from_ptr(section) }.to_bytes();
+    (!section.is_empty()).then_some(section)
+}
+
+/// Whether `value` is declared but not defined in its module.
+pub(crate) fn is_declaration(value: LLVMValueRef) -> bool {
+    unsafe { LLVMIsDeclaration(value) != 0 }
+}
+
+/// Returns the sections of the programs defined in `module`: the sections of exported functions,
+/// other than `.text`.
+pub(crate) fn program_sections(module: &LLVMModule<'_>) -> Vec<String> {
+    let mut sections = Vec::new();
+    for function in module.as_mut_ptr().functions_iter() {
+        if let Some(section) = program_section(function) {
+            let section = String::from_utf8_lossy(section).into_owned();
+            if !sections.contains(&section) {
+                sections.push(section);
+            }
+        }
+    }
+    sections
+}
+
+fn program_section<'a>(function: LLVMValueRef) -> Option<&'a [u8]> {
+    if unsafe { LLVMIsDeclaration(function) } != 0
+        || unsafe { LLVMGetLinkage(function) } != LLVMLinkage::LLVMExternalLinkage
+    {
+        return None;
+    }
+    let section = unsafe { LLVMGetSection(function) };
+    if section.is_null() {
+        return None;
+    }
+    let section = unsafe { CStr::from_ptr(section) }.to_bytes();
+    (!section.is_empty() && section != b".text").then_some(section)
+}
+
+/// Removes the programs of `module` that aren't in `section`, along with the functions and maps
+/// only they use.
+pub(crate) fn retain_program_section(
+    tm: &LLVMTargetMachine,
+    module: &mut LLVMModule<'_>,
+    section: &str,
+) -> Result<(), String> {
+    for function in module.as_mut_ptr().functions_iter() {
+        if program_section(function).is_some_and(|other| other != section.as_bytes()) {
+            unsafe { LLVMSetLinkage(function, LLVMLinkage::LLVMInternalLinkage) };
+        }
+    }
+
+    let options = unsafe { LLVMCreatePassBuilderOptions() };
+    let error = unsafe {
+        LLVMRunPasses(
+            module.as_mut_ptr(),
+            c"globaldce".as_ptr(),
+            tm.as_mut_ptr(),
+            options,
+        )
+    };
+    unsafe { LLVMDisposePassBuilderOptions(options) };
+    pass_error(error)?;
+
+    // Maps are kept by the pass since they're exported, so the unused ones are removed here.
+    let unused_maps = module
+        .as_mut_ptr()
+        .globals_iter()
+        .filter(|&global| is_map(global) && unsafe { LLVMGetFirstUse(global) }.is_null())
+        .collect::<Vec<_>>();
+    for map in unused_maps {
+        unsafe { LLVMDeleteGlobal(map) };
+    }
+
+    Ok(())
+}
+
 pub(crate) fn module_asm_is_probestack(module: LLVMModuleRef) -> bool {
     let mut len = 0;
     let ptr = unsafe { LLVMGetModuleInlineAsm(module, &mut len) };
@@ -238,6 +613,731 @@ pub(crate) fn module_asm_is_probestack(module: LLVMModuleRef) -> bool {
     haystack.windows(needle.len()).any(|w| w == needle)
 }
 
+/// A call to inline assembly.
+#[derive(Clone, Debug)]
+pub(crate) struct InlineAsmSite {
+    /// Name of the function making the call.
+    pub(crate) function: String,
+    /// The assembly template.
+    pub(crate) asm: String,
+    /// Source file and line of the call, if the module has debug info.
+    pub(crate) location: Option<(String, u32)>,
+}
+
+impl fmt::Display for InlineAsmSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            function,
+            asm: _,
+            location,
+        } = self;
+        write!(f, "function `{function}`")?;
+        if let Some((file, line)) = location {
+            write!(f, " at {file}:{line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns all the calls to inline assembly in the module.
+pub(crate) fn inline_asm_sites(module: &LLVMModule<'_>) -> Vec<InlineAsmSite> {
+    let mut sites = Vec::new();
+    for function in module.as_mut_ptr().functions_iter() {
+        for basic_block in function.basic_blocks_iter() {
+            for instruction in basic_block.instructions_iter() {
+                if unsafe { LLVMIsACallInst(instruction) }.is_null() {
+                    continue;
+                }
+                let callee = unsafe { LLVMGetCalledValue(instruction) };
+                if unsafe { LLVMIsAInlineAsm(callee) }.is_null() {
+                    continue;
+                }
+
+                let mut len = 0;
+                let ptr = unsafe { LLVMGetInlineAsmAsmString(callee, &mut len) };
+                let asm = unsafe { slice::from_raw_parts(ptr.cast(), len) };
+
+                sites.push(InlineAsmSite {
+                    function: demangle(&String::from_utf8_lossy(symbol_name(function))),
+                    asm: String::from_utf8_lossy(asm).into_owned(),
+                    location: debug_location(instruction),
+                });
+            }
+        }
+    }
+    sites
+}
+
+/// Returns the source file and line of `instruction`, if the module has debug info.
+fn debug_location(instruction: LLVMValueRef) -> Option<(String, u32)> {
+    let mut len = 0;
+    let ptr = unsafe { LLVMGetDebugLocFilename(instruction, &mut len) };
+    (!ptr.is_null()).then(|| {
+        let file = unsafe { slice::from_raw_parts(ptr.cast(), len as usize) };
+        let line = unsafe { LLVMGetDebugLocLine(instruction) };
+        (String::from_utf8_lossy(file).into_owned(), line)
+    })
+}
+
+/// Removes the debug locations of the instructions, except those of the first instruction of each
+/// line in a basic block when `keep_line_starts` is true. Line info is emitted from the remaining
+/// locations, and from the function itself for functions without any.
+pub(crate) fn strip_debug_locations(module: &mut LLVMModule<'_>, keep_line_starts: bool) {
+    for function in module.as_mut_ptr().functions_iter() {
+        for basic_block in function.basic_blocks_iter() {
+            let mut lines = HashSet::new();
+            for instruction in basic_block.instructions_iter() {
+                if unsafe { LLVMInstructionGetDebugLoc(instruction) }.is_null() {
+                    continue;
+                }
+                let line = unsafe { LLVMGetDebugLocLine(instruction) };
+                if keep_line_starts && lines.insert(line) {
+                    continue;
+                }
+                unsafe { LLVMInstructionSetDebugLoc(instruction, ptr::null_mut()) };
+            }
+        }
+    }
+}
+
+/// Where a function comes from.
+#[derive(Clone, Debug)]
+pub(crate) struct FunctionSource {
+    /// The demangled name of the function.
+    pub(crate) name: String,
+    /// Source file and line of the function definition, if the module has debug info.
+    pub(crate) location: Option<(String, u32)>,
+}
+
+impl fmt::Display for FunctionSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { name, location } = self;
+        write!(f, "function `{name}`")?;
+        if let Some((file, line)) = location {
+            write!(f, " defined at {file}:{line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the sources of the functions defined in the module, by symbol name.
+pub(crate) fn function_sources(module: &LLVMModule<'_>) -> HashMap<String, FunctionSource> {
+    module
+        .as_mut_ptr()
+        .functions_iter()
+        .filter(|&function| unsafe { LLVMIsDeclaration(function) } == 0)
+        .map(|function| {
+            let symbol = String::from_utf8_lossy(symbol_name(function)).into_owned();
+            let name = demangle(&symbol);
+
+            let subprogram = unsafe { LLVMGetSubprogram(function) };
+            let location = (!subprogram.is_null()).then(|| {
+                let file = unsafe { LLVMDIScopeGetFile(subprogram) };
+                let file = if file.is_null() {
+                    Cow::Borrowed("<unknown>")
+                } else {
+                    let mut len = 0;
+                    let ptr = unsafe { LLVMDIFileGetFilename(file, &mut len) };
+                    String::from_utf8_lossy(unsafe {
+                        slice::from_raw_parts(ptr.cast(), len as usize)
+                    })
+                };
+                let line = unsafe { LLVMDISubprogramGetLine(subprogram) };
+                (file.into_owned(), line)
+            });
+
+            (symbol, FunctionSource { name, location })
+        })
+        .collect()
+}
+
+/// Returns the names of the functions defined in the module that are visible outside of it and
+/// placed in `.text`, whether implicitly or explicitly.
+pub(crate) fn exported_text_functions(module: &LLVMModule<'_>) -> Vec<String> {
+    module
+        .as_mut_ptr()
+        .functions_iter()
+        .filter(|&function| {
+            let name = symbol_name(function);
+            if name.starts_with(b"llvm.")
+                || unsafe { LLVMIsDeclaration(function) } != 0
+                || unsafe { LLVMGetLinkage(function) } != LLVMLinkage::LLVMExternalLinkage
+            {
+                return false;
+            }
+            let section = unsafe { LLVMGetSection(function) };
+            section.is_null() || {
+                let section = unsafe { CStr::from_ptr(section) }.to_bytes();
+                section.is_empty() || section == b".text"
+            }
+        })
+        .map(|function| String::from_utf8_lossy(symbol_name(function)).into_owned())
+        .collect()
+}
+
+/// Returns the names of the functions and global variables defined in `module`.
+pub(crate) fn defined_symbols(module: &LLVMModule<'_>) -> Vec<String> {
+    let module = module.as_mut_ptr();
+    module
+        .functions_iter()
+        .chain(module.globals_iter())
+        .filter(|&value| {
+            !symbol_name(value).starts_with(b"llvm.") && unsafe { LLVMIsDeclaration(value) } == 0
+        })
+        .map(|value| String::from_utf8_lossy(symbol_name(value)).into_owned())
+        .collect()
+}
+
+/// Removes the maps that no function or other global refers to, unless they're exported, and
+/// returns their names. Maps only referred to by removed maps, like the inner maps of a removed
+/// map of maps, are removed too.
+pub(crate) fn remove_unused_maps(
+    module: &mut LLVMModule<'_>,
+    export_symbols: &HashSet<Cow<'_, [u8]>>,
+) -> Vec<String> {
+    let module = module.as_mut_ptr();
+    let mut removed = Vec::new();
+    loop {
+        let unused = module
+            .globals_iter()
+            .filter(|&global| {
+                is_map(global) && !export_symbols.contains(symbol_name(global)) && !is_used(global)
+            })
+            .collect::<Vec<_>>();
+        if unused.is_empty() {
+            return removed;
+        }
+        for global in unused {
+            removed.push(String::from_utf8_lossy(symbol_name(global)).into_owned());
+            // Dead constants may still refer to the map.
+            unsafe { LLVMReplaceAllUsesWith(global, LLVMGetPoison(LLVMTypeOf(global))) };
+            unsafe { LLVMDeleteGlobal(global) };
+        }
+    }
+}
+
+/// Whether an instruction or a global refers to `value`, directly or through constants.
+/// Constants that nothing refers to anymore are left behind by the optimizations.
+fn is_used(value: LLVMValueRef) -> bool {
+    let mut use_ = unsafe { LLVMGetFirstUse(value) };
+    while !use_.is_null() {
+        let user = unsafe { LLVMGetUser(use_) };
+        if !unsafe { LLVMIsAInstruction(user) }.is_null()
+            || !unsafe { LLVMIsAGlobalValue(user) }.is_null()
+            || is_used(user)
+        {
+            return true;
+        }
+        use_ = unsafe { LLVMGetNextUse(use_) };
+    }
+    false
+}
+
+/// A symbol declared but not defined in the module.
+pub(crate) struct UndefinedSymbol {
+    pub(crate) symbol: String,
+    /// The demangled names of the functions referring to the symbol.
+    pub(crate) users: Vec<String>,
+}
+
+/// Returns the symbols that are referenced but not defined in the module.
+///
+/// Declarations placed in a section, like kfuncs and kernel symbols in `.ksyms` or kernel config
+/// variables in `.kconfig`, and weak declarations are resolved by the loader, so they're not
+/// returned.
+pub(crate) fn undefined_symbols(module: &LLVMModule<'_>) -> Vec<UndefinedSymbol> {
+    let module = module.as_mut_ptr();
+    module
+        .functions_iter()
+        .chain(module.globals_iter())
+        .filter(|&value| {
+            let section = unsafe { LLVMGetSection(value) };
+            !symbol_name(value).starts_with(b"llvm.")
+                && unsafe { LLVMIsDeclaration(value) } != 0
+                && (section.is_null() || unsafe { *section } == 0)
+                && unsafe { LLVMGetLinkage(value) } != LLVMLinkage::LLVMExternalWeakLinkage
+        })
+        .filter_map(|value| {
+            let mut users = Vec::new();
+            let mut use_ = unsafe { LLVMGetFirstUse(value) };
+            if use_.is_null() {
+                return None;
+            }
+            while !use_.is_null() {
+                let user = unsafe { LLVMGetUser(use_) };
+                if !unsafe { LLVMIsAInstruction(user) }.is_null() {
+                    let block = unsafe { LLVMGetInstructionParent(user) };
+                    let function = unsafe { LLVMGetBasicBlockParent(block) };
+                    let name = String::from_utf8_lossy(symbol_name(function));
+                    let name = demangle(&name);
+                    if !users.contains(&name) {
+                        users.push(name);
+                    }
+                }
+                use_ = unsafe { LLVMGetNextUse(use_) };
+            }
+            Some(UndefinedSymbol {
+                symbol: String::from_utf8_lossy(symbol_name(value)).into_owned(),
+                users,
+            })
+        })
+        .collect()
+}
+
+/// A global variable placed in a data section.
+pub(crate) struct DataGlobal {
+    /// The demangled name of the global.
+    pub(crate) name: String,
+    pub(crate) section: String,
+    /// Size of the global in bytes.
+    pub(crate) size: u64,
+}
+
+/// Returns the global variables defined in the module that are placed in `.bss`, `.data` or
+/// `.rodata` sections, with their size according to `target_machine`.
+pub(crate) fn data_globals(
+    module: &LLVMModule<'_>,
+    target_machine: &LLVMTargetMachine,
+) -> Vec<DataGlobal> {
+    const DATA_SECTIONS: &[&str] = &[".bss", ".data", ".rodata"];
+
+    let data_layout = unsafe { LLVMCreateTargetDataLayout(target_machine.as_mut_ptr()) };
+    let globals = module
+        .as_mut_ptr()
+        .globals_iter()
+        .filter(|&global| unsafe { LLVMIsDeclaration(global) } == 0)
+        .filter_map(|global| {
+            let section = unsafe { LLVMGetSection(global) };
+            let section = if section.is_null() || unsafe { *section } == 0 {
+                // Same as the section LLVM picks for globals without an explicit one.
+                let initializer = unsafe { LLVMGetInitializer(global) };
+                if unsafe { LLVMIsGlobalConstant(global) } != 0 {
+                    ".rodata".into()
+                } else if !initializer.is_null() && unsafe { LLVMIsNull(initializer) } != 0 {
+                    ".bss".into()
+                } else {
+                    ".data".into()
+                }
+            } else {
+                unsafe { CStr::from_ptr(section) }.to_string_lossy()
+            };
+            let is_data = DATA_SECTIONS.iter().any(|data_section| {
+                section
+                    .strip_prefix(data_section)
+                    .is_some_and(|suffix| suffix.is_empty() || suffix.starts_with('.'))
+            });
+            if !is_data {
+                return None;
+            }
+
+            let ty = unsafe { LLVMGlobalGetValueType(global) };
+            let size = unsafe { LLVMABISizeOfType(data_layout, ty) };
+            let name = String::from_utf8_lossy(symbol_name(global));
+            Some(DataGlobal {
+                name: demangle(&name),
+                section: section.into_owned(),
+                size,
+            })
+        })
+        .collect();
+    unsafe { LLVMDisposeTargetData(data_layout) };
+    globals
+}
+
+/// A legacy map definition, ie a global variable in the `maps` section.
+pub(crate) struct MapDefinition {
+    /// The demangled name of the map.
+    pub(crate) name: String,
+    pub(crate) section: String,
+    /// The value of the definition as laid out in memory, if it could be evaluated.
+    pub(crate) bytes: Option<Vec<u8>>,
+    /// Whether the target is big endian.
+    pub(crate) big_endian: bool,
+}
+
+/// Returns the legacy map definitions in the module, placed in `maps` or `maps/*` sections.
+pub(crate) fn map_definitions(
+    module: &LLVMModule<'_>,
+    target_machine: &LLVMTargetMachine,
+) -> Vec<MapDefinition> {
+    let data_layout = unsafe { LLVMCreateTargetDataLayout(target_machine.as_mut_ptr()) };
+    let big_endian = unsafe { LLVMByteOrder(data_layout) } == LLVMByteOrdering::LLVMBigEndian;
+    let definitions = module
+        .as_mut_ptr()
+        .globals_iter()
+        .filter_map(|global| {
+            let section = unsafe { LLVMGetSection(global) };
+            if section.is_null() {
+                return None;
+            }
+            let section = unsafe { CStr::from_ptr(section) }.to_string_lossy();
+            (section == "maps" || section.starts_with("maps/"))
+                .then(|| (global, section.into_owned()))
+        })
+        .map(|(global, section)| {
+            let initializer = unsafe { LLVMGetInitializer(global) };
+            let bytes = if initializer.is_null() {
+                None
+            } else {
+                let ty = unsafe { LLVMGlobalGetValueType(global) };
+                let size = unsafe { LLVMABISizeOfType(data_layout, ty) };
+                let mut bytes = vec![0; size as usize];
+                constant_bytes(data_layout, big_endian, initializer, &mut bytes).then_some(bytes)
+            };
+            let name = String::from_utf8_lossy(symbol_name(global));
+            MapDefinition {
+                name: format!("{:#}", rustc_demangle::demangle(&name)),
+                section,
+                bytes,
+                big_endian,
+            }
+        })
+        .collect();
+    unsafe { LLVMDisposeTargetData(data_layout) };
+    definitions
+}
+
+/// Moves the map definitions of the module, in the `maps`, `maps/*` or `.maps` sections, to the
+/// section returned by `section` given their demangled name and current section. Maps for which
+/// it returns `None` are left in place.
+pub(crate) fn move_maps(
+    module: &mut LLVMModule<'_>,
+    mut section: impl FnMut(&str, &str) -> Option<&'static CStr>,
+) {
+    for global in module.as_mut_ptr().globals_iter() {
+        if !is_map(global) {
+            continue;
+        }
+        let current = unsafe { CStr::from_ptr(LLVMGetSection(global)) }.to_string_lossy();
+        let name = String::from_utf8_lossy(symbol_name(global));
+        let name = format!("{:#}", rustc_demangle::demangle(&name));
+        if let Some(target) = section(&name, &current) {
+            unsafe { LLVMSetSection(global, target.as_ptr()) };
+        }
+    }
+}
+
+/// Marks the global variables of the module, other than maps, as externally initialized, so that
+/// optimizations don't assume their value is their initializer. Loaders can then patch them.
+pub(crate) fn keep_global_values(module: &mut LLVMModule<'_>) {
+    for global in module.as_mut_ptr().globals_iter() {
+        if symbol_name(global).starts_with(b"llvm.")
+            || unsafe { LLVMIsDeclaration(global) } != 0
+            || is_map(global)
+        {
+            continue;
+        }
+        unsafe { LLVMSetExternallyInitialized(global, 1) };
+    }
+}
+
+/// Renames the internal global variables with a mangled Rust name, other than maps, after the last
+/// segment of their demangled name, unless another global value already has that name.
+pub(crate) fn demangle_global_names(module: &mut LLVMModule<'_>) {
+    let module = module.as_mut_ptr();
+    for global in module.globals_iter() {
+        let linkage = unsafe { LLVMGetLinkage(global) };
+        if !matches!(
+            linkage,
+            LLVMLinkage::LLVMInternalLinkage | LLVMLinkage::LLVMPrivateLinkage
+        ) || is_map(global)
+        {
+            continue;
+        }
+        let name = String::from_utf8_lossy(symbol_name(global)).into_owned();
+        let Ok(demangled) = rustc_demangle::try_demangle(&name) else {
+            continue;
+        };
+        let demangled = format!("{demangled:#}");
+        let demangled = demangled.rsplit("::").next().unwrap_or_default();
+        if demangled.is_empty()
+            || !demangled
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_')
+        {
+            continue;
+        }
+        let c_demangled = CString::new(demangled).unwrap();
+        if !unsafe { LLVMGetNamedGlobal(module, c_demangled.as_ptr()) }.is_null()
+            || !unsafe { LLVMGetNamedFunction(module, c_demangled.as_ptr()) }.is_null()
+        {
+            debug!("keeping `{}`: `{}` is already defined", name, demangled);
+            continue;
+        }
+        debug!("renaming `{}` to `{}`", name, demangled);
+        unsafe { LLVMSetValueName2(global, c_demangled.as_ptr(), demangled.len()) };
+    }
+}
+
+/// Returns the license of the module, ie the string in the `license` section, if there's one and
+/// its value could be evaluated.
+pub(crate) fn license(
+    module: &LLVMModule<'_>,
+    target_machine: &LLVMTargetMachine,
+) -> Option<String> {
+    let data_layout = unsafe { LLVMCreateTargetDataLayout(target_machine.as_mut_ptr()) };
+    let big_endian = unsafe { LLVMByteOrder(data_layout) } == LLVMByteOrdering::LLVMBigEndian;
+    let bytes = module
+        .as_mut_ptr()
+        .globals_iter()
+        .find(|&global| {
+            let section = unsafe { LLVMGetSection(global) };
+            !section.is_null() && unsafe { CStr::from_ptr(section) }.to_bytes() == b"license"
+        })
+        .and_then(|global| {
+            let initializer = unsafe { LLVMGetInitializer(global) };
+            if initializer.is_null() {
+                return None;
+            }
+            let ty = unsafe { LLVMGlobalGetValueType(global) };
+            let size = unsafe { LLVMABISizeOfType(data_layout, ty) };
+            let mut bytes = vec![0; size as usize];
+            constant_bytes(data_layout, big_endian, initializer, &mut bytes).then_some(bytes)
+        });
+    unsafe { LLVMDisposeTargetData(data_layout) };
+    bytes.map(|bytes| {
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..len]).into_owned()
+    })
+}
+
+/// A call to a BPF helper.
+pub(crate) struct HelperCall {
+    /// The demangled name of the function making the call.
+    pub(crate) function: String,
+    /// The id of the helper, from `enum bpf_func_id`.
+    pub(crate) helper: u64,
+}
+
+/// Returns the calls to BPF helpers in the module. Helpers are called through their id cast to a
+/// function pointer.
+pub(crate) fn helper_calls(module: &LLVMModule<'_>) -> Vec<HelperCall> {
+    let mut calls = Vec::new();
+    for function in module.as_mut_ptr().functions_iter() {
+        for basic_block in function.basic_blocks_iter() {
+            for instruction in basic_block.instructions_iter() {
+                if unsafe { LLVMIsACallInst(instruction) }.is_null() {
+                    continue;
+                }
+                let callee = unsafe { LLVMGetCalledValue(instruction) };
+                if unsafe { LLVMIsAConstantExpr(callee) }.is_null()
+                    || unsafe { LLVMGetConstOpcode(callee) } != LLVMOpcode::LLVMIntToPtr
+                {
+                    continue;
+                }
+                let id = unsafe { LLVMGetOperand(callee, 0) };
+                if unsafe { LLVMIsAConstantInt(id) }.is_null() {
+                    continue;
+                }
+
+                let name = String::from_utf8_lossy(symbol_name(function));
+                calls.push(HelperCall {
+                    function: demangle(&name),
+                    helper: unsafe { LLVMConstIntGetZExtValue(id) },
+                });
+            }
+        }
+    }
+    calls
+}
+
+/// Largest number of arguments of calls to BPF functions and helpers, passed in r1 to r5.
+const MAX_CALL_ARGUMENTS: u32 = 5;
+
+/// Memory intrinsics copying or setting more bytes than this are expanded into long sequences of
+/// loads and stores, if at all.
+const LARGE_MEMORY_INTRINSIC: u64 = 256;
+
+/// A code pattern that the verifier is likely to reject, or to take long to go through.
+pub(crate) enum VerifierLintKind {
+    /// A loop, which the verifier must prove terminates.
+    Loop,
+    /// A call to `llvm.memcpy`, `llvm.memmove` or `llvm.memset` with a large length, or a length
+    /// that isn't a constant.
+    MemoryIntrinsic { name: String, len: Option<u64> },
+    /// A call through a function pointer.
+    IndirectCall,
+    /// A call with more arguments than fit in registers.
+    TooManyArguments { callee: String, arguments: u32 },
+}
+
+/// A code pattern that the verifier is likely to reject, found in a function.
+pub(crate) struct VerifierLint {
+    /// The demangled name of the function.
+    pub(crate) function: String,
+    /// Source file and line of the code, if the module has debug info.
+    pub(crate) location: Option<(String, u32)>,
+    pub(crate) kind: VerifierLintKind,
+}
+
+/// Returns the terminators of the basic blocks of `function` that branch back to a block whose
+/// successors are being visited, that is the back edges of its loops.
+fn back_edges(function: LLVMValueRef) -> Vec<LLVMValueRef> {
+    let Some(entry) = function.basic_blocks_iter().next() else {
+        return Vec::new();
+    };
+    // Whether each block reached so far is still being visited.
+    let mut visiting = HashMap::from([(entry, true)]);
+    // The blocks being visited, and the index of their next successor to visit.
+    let mut stack = vec![(entry, 0)];
+    let mut edges = Vec::new();
+    while let Some(&(block, next)) = stack.last() {
+        let terminator = unsafe { LLVMGetBasicBlockTerminator(block) };
+        let successors = if terminator.is_null() {
+            0
+        } else {
+            unsafe { LLVMGetNumSuccessors(terminator) }
+        };
+        if next == successors {
+            let _: Option<bool> = visiting.insert(block, false);
+            let _: Option<_> = stack.pop();
+            continue;
+        }
+        let top = stack.len() - 1;
+        stack[top].1 += 1;
+        let successor = unsafe { LLVMGetSuccessor(terminator, next) };
+        match visiting.get(&successor) {
+            Some(true) => edges.push(terminator),
+            Some(false) => {}
+            None => {
+                let _: Option<bool> = visiting.insert(successor, true);
+                stack.push((successor, 0));
+            }
+        }
+    }
+    edges
+}
+
+/// Returns the code patterns of the module that the verifier is likely to reject: loops, large
+/// memory intrinsics, indirect calls and calls with too many arguments.
+pub(crate) fn verifier_lints(module: &LLVMModule<'_>) -> Vec<VerifierLint> {
+    let mut lints = Vec::new();
+    for function in module.as_mut_ptr().functions_iter() {
+        let name = demangle(&String::from_utf8_lossy(symbol_name(function)));
+        let mut lint = |instruction, kind| {
+            lints.push(VerifierLint {
+                function: name.clone(),
+                location: debug_location(instruction),
+                kind,
+            })
+        };
+        for terminator in back_edges(function) {
+            lint(terminator, VerifierLintKind::Loop);
+        }
+        for basic_block in function.basic_blocks_iter() {
+            for instruction in basic_block.instructions_iter() {
+                if unsafe { LLVMIsACallInst(instruction) }.is_null() {
+                    continue;
+                }
+                let callee = unsafe { LLVMGetCalledValue(instruction) };
+                if !unsafe { LLVMIsAInlineAsm(callee) }.is_null() {
+                    continue;
+                }
+                // Helpers are called through their id cast to a function pointer.
+                let is_helper = !unsafe { LLVMIsAConstantExpr(callee) }.is_null()
+                    && unsafe { LLVMGetConstOpcode(callee) } == LLVMOpcode::LLVMIntToPtr;
+                if unsafe { LLVMIsAFunction(callee) }.is_null() {
+                    if !is_helper {
+                        lint(instruction, VerifierLintKind::IndirectCall);
+                    }
+                    continue;
+                }
+
+                let callee = String::from_utf8_lossy(symbol_name(callee)).into_owned();
+                if ["llvm.memcpy.", "llvm.memmove.", "llvm.memset."]
+                    .iter()
+                    .any(|prefix| callee.starts_with(prefix))
+                {
+                    // The length is the third argument of the three intrinsics.
+                    let len = unsafe { LLVMGetOperand(instruction, 2) };
+                    let len = (!unsafe { LLVMIsAConstantInt(len) }.is_null())
+                        .then(|| unsafe { LLVMConstIntGetZExtValue(len) });
+                    if len.is_none_or(|len| len > LARGE_MEMORY_INTRINSIC) {
+                        let name = callee.split('.').take(2).collect::<Vec<_>>().join(".");
+                        lint(instruction, VerifierLintKind::MemoryIntrinsic { name, len });
+                    }
+                    continue;
+                }
+                let arguments = unsafe { LLVMGetNumArgOperands(instruction) };
+                if arguments > MAX_CALL_ARGUMENTS && !callee.starts_with("llvm.") {
+                    lint(
+                        instruction,
+                        VerifierLintKind::TooManyArguments {
+                            callee: demangle(&callee),
+                            arguments,
+                        },
+                    );
+                }
+            }
+        }
+    }
+    lints
+}
+
+/// Writes the memory representation of `constant` to `bytes`, which must be as large as the
+/// allocation size of its type. Returns false if the constant can't be evaluated, eg because it
+/// contains pointers.
+fn constant_bytes(
+    data_layout: LLVMTargetDataRef,
+    big_endian: bool,
+    constant: LLVMValueRef,
+    bytes: &mut [u8],
+) -> bool {
+    if unsafe { LLVMIsNull(constant) } != 0 || unsafe { LLVMIsUndef(constant) } != 0 {
+        bytes.fill(0);
+        return true;
+    }
+
+    let ty = unsafe { LLVMTypeOf(constant) };
+    match unsafe { LLVMGetTypeKind(ty) } {
+        LLVMTypeKind::LLVMIntegerTypeKind => {
+            if unsafe { LLVMIsAConstantInt(constant) }.is_null()
+                || unsafe { LLVMGetIntTypeWidth(ty) } > 64
+            {
+                return false;
+            }
+            let value = unsafe { LLVMConstIntGetZExtValue(constant) };
+            let size = unsafe { LLVMStoreSizeOfType(data_layout, ty) } as usize;
+            let Some(bytes) = bytes.get_mut(..size) else {
+                return false;
+            };
+            if big_endian {
+                bytes.copy_from_slice(&value.to_be_bytes()[8 - size..]);
+            } else {
+                bytes.copy_from_slice(&value.to_le_bytes()[..size]);
+            }
+            true
+        }
+        LLVMTypeKind::LLVMStructTypeKind => {
+            let count = unsafe { LLVMCountStructElementTypes(ty) };
+            (0..count).all(|index| {
+                let offset = unsafe { LLVMOffsetOfElement(data_layout, ty, index) } as usize;
+                let element_ty = unsafe { LLVMStructGetTypeAtIndex(ty, index) };
+                let size = unsafe { LLVMABISizeOfType(data_layout, element_ty) } as usize;
+                let element = unsafe { LLVMGetAggregateElement(constant, index) };
+                !element.is_null()
+                    && bytes.get_mut(offset..offset + size).is_some_and(|bytes| {
+                        constant_bytes(data_layout, big_endian, element, bytes)
+                    })
+            })
+        }
+        LLVMTypeKind::LLVMArrayTypeKind => {
+            let count = unsafe { LLVMGetArrayLength2(ty) };
+            let element_ty = unsafe { LLVMGetElementType(ty) };
+            let size = unsafe { LLVMABISizeOfType(data_layout, element_ty) } as usize;
+            (0..count).all(|index| {
+                let offset = index as usize * size;
+                let element = unsafe { LLVMGetAggregateElement(constant, index as u32) };
+                !element.is_null()
+                    && bytes.get_mut(offset..offset + size).is_some_and(|bytes| {
+                        constant_bytes(data_layout, big_endian, element, bytes)
+                    })
+            })
+        }
+        _ => false,
+    }
+}
+
 pub(crate) fn symbol_name<'a>(value: *mut llvm_sys::LLVMValue) -> &'a [u8] {
     let mut name_len = 0;
     let ptr = unsafe { LLVMGetValueName2(value, &mut name_len) };
@@ -260,6 +1360,35 @@ pub(crate) fn internalize(
     }
 }
 
+/// Whether `global` is a map definition, ie a global variable defined in the `maps`, `maps/*` or
+/// `.maps` sections.
+fn is_map(global: LLVMValueRef) -> bool {
+    let section = unsafe { LLVMGetSection(global) };
+    if section.is_null() || unsafe { LLVMIsDeclaration(global) } != 0 {
+        return false;
+    }
+    let section = unsafe { CStr::from_ptr(section) }.to_bytes();
+    section == b"maps" || section.starts_with(b"maps/") || section == b".maps"
+}
+
+/// Gives the map `global` the linkage and visibility loaders expect. Loaders find maps through
+/// their global symbols, so maps that are internal, for example because they're Rust statics
+/// without `#[no_mangle]`, would otherwise be missing from the object.
+fn normalize_map(global: LLVMValueRef) {
+    let linkage = unsafe { LLVMGetLinkage(global) };
+    if matches!(
+        linkage,
+        LLVMLinkage::LLVMInternalLinkage | LLVMLinkage::LLVMPrivateLinkage
+    ) {
+        debug!(
+            "making map `{}` global",
+            String::from_utf8_lossy(symbol_name(global))
+        );
+        unsafe { LLVMSetLinkage(global, LLVMLinkage::LLVMExternalLinkage) };
+    }
+    unsafe { LLVMSetVisibility(global, LLVMVisibility::LLVMDefaultVisibility) };
+}
+
 pub(crate) trait LLVMDiagnosticHandler {
     fn handle_diagnostic(
         &mut self,
@@ -272,10 +1401,15 @@ pub(crate) extern "C" fn fatal_error(reason: *const c_char) {
     error!("fatal error: {:?}", unsafe { CStr::from_ptr(reason) })
 }
 
-struct Message {
+/// A string allocated by LLVM, disposed of with `LLVMDisposeMessage`.
+pub(crate) struct Message {
     ptr: *mut c_char,
 }
 
+// A message is a plain allocation that isn't tied to an LLVM context, so it can be moved across
+// threads.
+unsafe impl Send for Message {}
+
 impl Message {
     fn with<T, F: FnOnce(*mut *mut c_char) -> T>(f: F) -> (T, Self) {
         let mut ptr = ptr::null_mut();
@@ -289,6 +1423,11 @@ impl Message {
         (!ptr.is_null()).then(|| unsafe { CStr::from_ptr(ptr) })
     }
 
+    /// Returns the bytes of the string, without the trailing nul byte.
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        self.as_c_str().map(CStr::to_bytes).unwrap_or_default()
+    }
+
     fn as_string_lossy(&self) -> Cow<'_, str> {
         self.as_c_str()
             .map(CStr::to_bytes)
@@ -43,10 +43,22 @@ impl LLVMContext {
     ///
     /// The caller must ensure that the [`LLVMContext`] outlives the pointer this
     /// function returns, or else it will end up dangling.
-    pub(in crate::llvm) const fn as_mut_ptr(&self) -> LLVMContextRef {
+    pub(crate) const fn as_mut_ptr(&self) -> LLVMContextRef {
         self.context
     }
 
+    /// Takes ownership of `context`, which is disposed when the returned value is dropped.
+    ///
+    /// # Safety
+    ///
+    /// `context` must be a valid context that isn't disposed elsewhere.
+    pub(crate) const unsafe fn from_raw(context: LLVMContextRef) -> Self {
+        Self {
+            context,
+            diagnostic_handler: None,
+        }
+    }
+
     pub(crate) fn create_module<'ctx>(&'ctx self, name: &CStr) -> Option<LLVMModule<'ctx>> {
         let module = unsafe { LLVMModuleCreateWithNameInContext(name.as_ptr(), self.context) };
 
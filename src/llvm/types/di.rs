@@ -2,7 +2,10 @@ use std::{marker::PhantomData, slice};
 
 use gimli::DwTag;
 use llvm_sys::{
-    core::{LLVMGetNumOperands, LLVMGetOperand, LLVMReplaceMDNodeOperandWith, LLVMValueAsMetadata},
+    core::{
+        LLVMGetNumOperands, LLVMGetOperand, LLVMIsAMDString, LLVMReplaceMDNodeOperandWith,
+        LLVMValueAsMetadata,
+    },
     debuginfo::{
         LLVMDIFileGetFilename, LLVMDIFlags, LLVMDIScopeGetFile, LLVMDISubprogramGetLine,
         LLVMDITypeGetFlags, LLVMDITypeGetLine, LLVMDITypeGetName, LLVMDITypeGetOffsetInBits,
@@ -228,6 +231,13 @@ enum DICompositeTypeOperand {
     Elements = 4,
     #[cfg(feature = "llvm-21")]
     Elements = 6,
+    /// Unique identifier of the type, used to unique types across modules.
+    /// It follows the elements, the vtable holder and the template params, in
+    /// the same references as [`DICompositeTypeOperand::Elements`].
+    #[cfg(any(feature = "llvm-19", feature = "llvm-20"))]
+    Identifier = 7,
+    #[cfg(feature = "llvm-21")]
+    Identifier = 9,
 }
 
 /// Represents the debug info for a composite type in LLVM IR.
@@ -282,6 +292,15 @@ impl DICompositeType<'_> {
         unsafe { di_type_name(self.metadata_ref) }
     }
 
+    /// Returns the unique identifier of the composite type, if any. rustc gives one to all the
+    /// types it describes.
+    pub(crate) fn identifier(&self) -> Option<&[u8]> {
+        let operand =
+            unsafe { LLVMGetOperand(self.value_ref, DICompositeTypeOperand::Identifier as u32) };
+        (!operand.is_null() && !unsafe { LLVMIsAMDString(operand) }.is_null())
+            .then(|| mdstring(operand))
+    }
+
     /// Returns the file that the composite type belongs to.
     pub(crate) fn file(&self) -> DIFile<'_> {
         unsafe {
@@ -9,6 +9,10 @@ pub(crate) struct MemoryBuffer {
     pub(super) memory_buffer: LLVMMemoryBufferRef,
 }
 
+// A memory buffer owns its contents and isn't tied to an LLVM context, so it
+// can be moved across threads.
+unsafe impl Send for MemoryBuffer {}
+
 impl MemoryBuffer {
     /// Gets a byte slice of this `MemoryBuffer`.
     pub(crate) fn as_slice(&self) -> &[u8] {
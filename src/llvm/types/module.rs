@@ -1,17 +1,33 @@
-use std::{ffi::CStr, marker::PhantomData};
+use std::{ffi::CStr, fs, io, marker::PhantomData, path::Path, ptr};
 
-use libc::c_char;
 use llvm_sys::{
-    bit_writer::LLVMWriteBitcodeToFile,
+    analysis::{LLVMVerifierFailureAction, LLVMVerifyFunction, LLVMVerifyModule},
+    bit_reader::LLVMParseBitcodeInContext2,
     core::{
-        LLVMCreateMemoryBufferWithMemoryRangeCopy, LLVMDisposeMessage, LLVMDisposeModule,
-        LLVMGetTarget, LLVMPrintModuleToFile, LLVMPrintModuleToString,
+        LLVMCloneModule, LLVMDisposeModule, LLVMGetTarget, LLVMPrintModuleToFile,
+        LLVMPrintModuleToString, LLVMSetModuleDataLayout, LLVMSetModuleIdentifier,
+        LLVMSetSourceFileName, LLVMSetTarget,
     },
     debuginfo::LLVMStripModuleDebugInfo,
     prelude::LLVMModuleRef,
+    target::LLVMDisposeTargetData,
+    target_machine::LLVMCreateTargetDataLayout,
 };
 
-use crate::llvm::{types::context::LLVMContext, MemoryBuffer, Message};
+use crate::llvm::{
+    iter::IterModuleFunctions as _, symbol_name, types::context::LLVMContext, LLVMTargetMachine,
+    MemoryBuffer, Message,
+};
+
+/// Details of a module that failed verification.
+#[derive(Debug)]
+pub(crate) struct VerifyError {
+    /// Names of the functions that are malformed. Empty if the problem isn't
+    /// within a function, eg in a global variable or in module metadata.
+    pub(crate) functions: Vec<String>,
+    /// The report of the verifier.
+    pub(crate) message: String,
+}
 
 pub(crate) struct LLVMModule<'ctx> {
     pub(super) module: LLVMModuleRef,
@@ -23,20 +39,55 @@ impl LLVMModule<'_> {
     ///
     /// The caller must ensure that the [`LLVMModule`] outlives the pointer this
     /// function returns, or else it will end up dangling.
-    pub(in crate::llvm) const fn as_mut_ptr(&self) -> LLVMModuleRef {
+    pub(crate) const fn as_mut_ptr(&self) -> LLVMModuleRef {
         self.module
     }
 
-    pub(crate) fn get_target(&self) -> *const c_char {
-        unsafe { LLVMGetTarget(self.module) }
+    /// Returns the target triple of the module.
+    pub(crate) fn get_target(&self) -> &CStr {
+        // The triple is owned by the module and lives as long as it.
+        unsafe { CStr::from_ptr(LLVMGetTarget(self.module)) }
     }
 
-    pub(crate) fn write_bitcode_to_path(&self, path: &CStr) -> Result<(), std::io::Error> {
-        if unsafe { LLVMWriteBitcodeToFile(self.module, path.as_ptr()) } != 0 {
-            return Err(std::io::Error::last_os_error());
-        }
+    /// Whether the data layout of the module is big-endian, or `None` if it has no data layout.
+    pub(crate) fn big_endian(&self) -> Option<bool> {
+        crate::llvm::big_endian(self.module)
+    }
 
-        Ok(())
+    /// Sets the target triple of the module, along with the data layout of
+    /// `target_machine`.
+    pub(crate) fn set_target(&mut self, triple: &CStr, target_machine: &LLVMTargetMachine) {
+        unsafe { LLVMSetTarget(self.module, triple.as_ptr()) };
+        let data_layout = unsafe { LLVMCreateTargetDataLayout(target_machine.as_mut_ptr()) };
+        unsafe { LLVMSetModuleDataLayout(self.module, data_layout) };
+        unsafe { LLVMDisposeTargetData(data_layout) };
+    }
+
+    /// Returns a copy of the module in `context`.
+    ///
+    /// Unlike [`Clone::clone`], the copy can live in a different context than
+    /// the original. Modules can't be copied across contexts directly, so this
+    /// goes through bitcode.
+    pub(crate) fn clone_into<'new>(&self, context: &'new LLVMContext) -> Option<LLVMModule<'new>> {
+        let bitcode = self.write_bitcode_to_memory();
+        let mut module = ptr::null_mut();
+        let ret = unsafe {
+            LLVMParseBitcodeInContext2(context.as_mut_ptr(), bitcode.memory_buffer, &mut module)
+        };
+        (ret == 0).then(|| LLVMModule {
+            module,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Writes the module's bitcode to `path`.
+    ///
+    /// The file is written from Rust rather than by `LLVMWriteBitcodeToFile`,
+    /// which only reports failure as a boolean, so that the returned error
+    /// carries the actual cause (eg `ENOSPC`).
+    pub(crate) fn write_bitcode_to_path(&self, path: &Path) -> Result<(), io::Error> {
+        let buffer = self.write_bitcode_to_memory();
+        fs::write(path, buffer.as_slice())
     }
 
     pub(crate) fn write_bitcode_to_memory(&self) -> MemoryBuffer {
@@ -57,26 +108,53 @@ impl LLVMModule<'_> {
         }
     }
 
-    pub(crate) fn write_ir_to_memory(&self) -> MemoryBuffer {
-        // Format the module to a string, then copy into a MemoryBuffer. We do the extra copy to keep the
-        // internal API simpler, as all the other codegen methods output a MemoryBuffer.
-        unsafe {
-            let ptr = LLVMPrintModuleToString(self.module);
-            let cstr = CStr::from_ptr(ptr);
-            let bytes = cstr.to_bytes();
+    /// Prints the module's IR to an LLVM-owned string. The string is handed
+    /// over as is, without copying it into a [`MemoryBuffer`], since textual
+    /// IR of large modules can be hundreds of megabytes.
+    pub(crate) fn write_ir_to_memory(&self) -> Message {
+        Message {
+            ptr: unsafe { LLVMPrintModuleToString(self.module) },
+        }
+    }
 
-            let buffer_name = c"mem_buffer";
+    /// Checks that the module is well formed.
+    pub(crate) fn verify(&self) -> Result<(), VerifyError> {
+        let (ret, message) = Message::with(|message| unsafe {
+            LLVMVerifyModule(
+                self.module,
+                LLVMVerifierFailureAction::LLVMReturnStatusAction,
+                message,
+            )
+        });
+        if ret == 0 {
+            return Ok(());
+        }
 
-            // Copy bytes into a new LLVMMemoryBuffer so we can safely dispose the message.
-            let memory_buffer = LLVMCreateMemoryBufferWithMemoryRangeCopy(
-                bytes.as_ptr().cast(),
-                bytes.len(),
-                buffer_name.as_ptr(),
-            );
-            LLVMDisposeMessage(ptr);
+        // The module verifier reports all the failures in a single message, so verify functions
+        // one by one to find out which ones are broken.
+        let functions = self
+            .module
+            .functions_iter()
+            .filter(|&function| {
+                let ret = unsafe {
+                    LLVMVerifyFunction(function, LLVMVerifierFailureAction::LLVMReturnStatusAction)
+                };
+                ret != 0
+            })
+            .map(|function| String::from_utf8_lossy(symbol_name(function)).into_owned())
+            .collect();
+        Err(VerifyError {
+            functions,
+            message: message.as_string_lossy().into_owned(),
+        })
+    }
 
-            MemoryBuffer { memory_buffer }
-        }
+    /// Sets the identifier and the source file name of the module.
+    pub(crate) fn set_identifier(&mut self, identifier: &[u8]) {
+        unsafe {
+            LLVMSetModuleIdentifier(self.module, identifier.as_ptr().cast(), identifier.len())
+        };
+        unsafe { LLVMSetSourceFileName(self.module, identifier.as_ptr().cast(), identifier.len()) };
     }
 
     /// strips debug information, returns true if DI got stripped
@@ -85,6 +163,16 @@ impl LLVMModule<'_> {
     }
 }
 
+impl Clone for LLVMModule<'_> {
+    /// Returns a copy of the module in the same context.
+    fn clone(&self) -> Self {
+        Self {
+            module: unsafe { LLVMCloneModule(self.module) },
+            _marker: PhantomData,
+        }
+    }
+}
+
 impl Drop for LLVMModule<'_> {
     fn drop(&mut self) {
         unsafe { LLVMDisposeModule(self.module) };
@@ -1,50 +1,117 @@
 use std::ffi::CStr;
 
 use llvm_sys::target_machine::{
-    LLVMCodeGenFileType, LLVMCodeGenOptLevel, LLVMCodeModel, LLVMCreateTargetMachine,
-    LLVMDisposeTargetMachine, LLVMRelocMode, LLVMTargetMachineEmitToFile,
-    LLVMTargetMachineEmitToMemoryBuffer, LLVMTargetMachineRef, LLVMTargetRef,
+    LLVMCodeGenFileType, LLVMCodeGenOptLevel, LLVMCodeModel, LLVMCreateTargetMachineOptions,
+    LLVMCreateTargetMachineWithOptions, LLVMDisposeTargetMachine, LLVMDisposeTargetMachineOptions,
+    LLVMRelocMode, LLVMSetTargetMachineAsmVerbosity, LLVMSetTargetMachineFastISel,
+    LLVMSetTargetMachineGlobalISel, LLVMSetTargetMachineMachineOutliner,
+    LLVMTargetMachineEmitToFile, LLVMTargetMachineEmitToMemoryBuffer, LLVMTargetMachineOptionsRef,
+    LLVMTargetMachineOptionsSetABI, LLVMTargetMachineOptionsSetCPU,
+    LLVMTargetMachineOptionsSetCodeGenOptLevel, LLVMTargetMachineOptionsSetCodeModel,
+    LLVMTargetMachineOptionsSetFeatures, LLVMTargetMachineOptionsSetRelocMode,
+    LLVMTargetMachineRef, LLVMTargetRef,
 };
 
 use crate::llvm::{types::module::LLVMModule, MemoryBuffer, Message};
 
+/// A target registered with LLVM.
+///
+/// Targets are static objects owned by the LLVM target registry, so they are
+/// valid for the whole lifetime of the process.
+#[derive(Clone, Copy)]
+pub(crate) struct LLVMTarget {
+    pub(in crate::llvm) target: LLVMTargetRef,
+}
+
 pub(crate) struct LLVMTargetMachine {
     target_machine: LLVMTargetMachineRef,
 }
 
-impl LLVMTargetMachine {
-    pub(crate) fn new(
-        target: LLVMTargetRef,
-        triple: &CStr,
-        cpu: &CStr,
-        features: &CStr,
-    ) -> Option<Self> {
+/// Builder for [`LLVMTargetMachine`].
+pub(crate) struct LLVMTargetMachineBuilder {
+    options: LLVMTargetMachineOptionsRef,
+}
+
+impl LLVMTargetMachineBuilder {
+    pub(crate) fn new() -> Self {
+        let options = unsafe { LLVMCreateTargetMachineOptions() };
+        Self { options }
+    }
+
+    pub(crate) fn cpu(self, cpu: &CStr) -> Self {
+        // LLVM copies the string, it doesn't need to outlive the builder.
+        unsafe { LLVMTargetMachineOptionsSetCPU(self.options, cpu.as_ptr()) };
+        self
+    }
+
+    pub(crate) fn features(self, features: &CStr) -> Self {
+        unsafe { LLVMTargetMachineOptionsSetFeatures(self.options, features.as_ptr()) };
+        self
+    }
+
+    pub(crate) fn abi(self, abi: &CStr) -> Self {
+        unsafe { LLVMTargetMachineOptionsSetABI(self.options, abi.as_ptr()) };
+        self
+    }
+
+    pub(crate) fn opt_level(self, opt_level: LLVMCodeGenOptLevel) -> Self {
+        unsafe { LLVMTargetMachineOptionsSetCodeGenOptLevel(self.options, opt_level) };
+        self
+    }
+
+    pub(crate) fn reloc_mode(self, reloc_mode: LLVMRelocMode) -> Self {
+        unsafe { LLVMTargetMachineOptionsSetRelocMode(self.options, reloc_mode) };
+        self
+    }
+
+    pub(crate) fn code_model(self, code_model: LLVMCodeModel) -> Self {
+        unsafe { LLVMTargetMachineOptionsSetCodeModel(self.options, code_model) };
+        self
+    }
+
+    pub(crate) fn build(&self, target: LLVMTarget, triple: &CStr) -> Option<LLVMTargetMachine> {
         let tm = unsafe {
-            LLVMCreateTargetMachine(
-                target,
-                triple.as_ptr(),
-                cpu.as_ptr(),
-                features.as_ptr(),
-                LLVMCodeGenOptLevel::LLVMCodeGenLevelAggressive,
-                LLVMRelocMode::LLVMRelocDefault,
-                LLVMCodeModel::LLVMCodeModelDefault,
-            )
+            LLVMCreateTargetMachineWithOptions(target.target, triple.as_ptr(), self.options)
         };
         if tm.is_null() {
             None
         } else {
-            Some(Self { target_machine: tm })
+            Some(LLVMTargetMachine { target_machine: tm })
         }
     }
+}
 
+impl Drop for LLVMTargetMachineBuilder {
+    fn drop(&mut self) {
+        unsafe { LLVMDisposeTargetMachineOptions(self.options) };
+    }
+}
+
+impl LLVMTargetMachine {
     /// Returns an unsafe mutable pointer to the LLVM target machine.
     ///
     /// The caller must ensure that the [`LLVMTargetMachine`] outlives the pointer this
     /// function returns, or else it will end up dangling.
-    pub(in crate::llvm) const fn as_mut_ptr(&self) -> LLVMTargetMachineRef {
+    pub(crate) const fn as_mut_ptr(&self) -> LLVMTargetMachineRef {
         self.target_machine
     }
 
+    pub(crate) fn set_asm_verbosity(&mut self, verbose: bool) {
+        unsafe { LLVMSetTargetMachineAsmVerbosity(self.target_machine, verbose.into()) };
+    }
+
+    pub(crate) fn set_fast_isel(&mut self, enable: bool) {
+        unsafe { LLVMSetTargetMachineFastISel(self.target_machine, enable.into()) };
+    }
+
+    pub(crate) fn set_global_isel(&mut self, enable: bool) {
+        unsafe { LLVMSetTargetMachineGlobalISel(self.target_machine, enable.into()) };
+    }
+
+    pub(crate) fn set_machine_outliner(&mut self, enable: bool) {
+        unsafe { LLVMSetTargetMachineMachineOutliner(self.target_machine, enable.into()) };
+    }
+
     pub(crate) fn emit_to_file(
         &self,
         module: &LLVMModule<'_>,
@@ -0,0 +1,359 @@
+use std::fmt;
+
+// Map types, from `enum bpf_map_type` in the kernel's `include/uapi/linux/bpf.h`.
+const BPF_MAP_TYPE_ARRAY: u32 = 2;
+const BPF_MAP_TYPE_PROG_ARRAY: u32 = 3;
+const BPF_MAP_TYPE_PERF_EVENT_ARRAY: u32 = 4;
+const BPF_MAP_TYPE_PERCPU_HASH: u32 = 5;
+const BPF_MAP_TYPE_PERCPU_ARRAY: u32 = 6;
+const BPF_MAP_TYPE_CGROUP_ARRAY: u32 = 8;
+const BPF_MAP_TYPE_LRU_PERCPU_HASH: u32 = 10;
+const BPF_MAP_TYPE_ARRAY_OF_MAPS: u32 = 12;
+const BPF_MAP_TYPE_DEVMAP: u32 = 14;
+const BPF_MAP_TYPE_SOCKMAP: u32 = 15;
+const BPF_MAP_TYPE_CPUMAP: u32 = 16;
+const BPF_MAP_TYPE_XSKMAP: u32 = 17;
+const BPF_MAP_TYPE_CGROUP_STORAGE: u32 = 19;
+const BPF_MAP_TYPE_REUSEPORT_SOCKARRAY: u32 = 20;
+const BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE: u32 = 21;
+const BPF_MAP_TYPE_SK_STORAGE: u32 = 24;
+const BPF_MAP_TYPE_RINGBUF: u32 = 27;
+const BPF_MAP_TYPE_INODE_STORAGE: u32 = 28;
+const BPF_MAP_TYPE_TASK_STORAGE: u32 = 29;
+const BPF_MAP_TYPE_USER_RINGBUF: u32 = 31;
+const BPF_MAP_TYPE_CGRP_STORAGE: u32 = 32;
+/// The last map type, `BPF_MAP_TYPE_ARENA`.
+const BPF_MAP_TYPE_MAX: u32 = 33;
+
+/// Maps indexed by a `u32`.
+const ARRAY_MAP_TYPES: &[u32] = &[
+    BPF_MAP_TYPE_ARRAY,
+    BPF_MAP_TYPE_PROG_ARRAY,
+    BPF_MAP_TYPE_PERF_EVENT_ARRAY,
+    BPF_MAP_TYPE_PERCPU_ARRAY,
+    BPF_MAP_TYPE_CGROUP_ARRAY,
+    BPF_MAP_TYPE_ARRAY_OF_MAPS,
+    BPF_MAP_TYPE_DEVMAP,
+    BPF_MAP_TYPE_SOCKMAP,
+    BPF_MAP_TYPE_CPUMAP,
+    BPF_MAP_TYPE_XSKMAP,
+    BPF_MAP_TYPE_REUSEPORT_SOCKARRAY,
+];
+
+/// Maps with a value per CPU.
+const PERCPU_MAP_TYPES: &[u32] = &[
+    BPF_MAP_TYPE_PERCPU_HASH,
+    BPF_MAP_TYPE_PERCPU_ARRAY,
+    BPF_MAP_TYPE_LRU_PERCPU_HASH,
+    BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE,
+];
+
+/// Maps whose entries are attached to kernel objects, which must have no `max_entries`.
+const STORAGE_MAP_TYPES: &[u32] = &[
+    BPF_MAP_TYPE_CGROUP_STORAGE,
+    BPF_MAP_TYPE_PERCPU_CGROUP_STORAGE,
+    BPF_MAP_TYPE_SK_STORAGE,
+    BPF_MAP_TYPE_INODE_STORAGE,
+    BPF_MAP_TYPE_TASK_STORAGE,
+    BPF_MAP_TYPE_CGRP_STORAGE,
+];
+
+/// Maps whose `max_entries` is the size of a ring buffer in bytes.
+const RINGBUF_MAP_TYPES: &[u32] = &[BPF_MAP_TYPE_RINGBUF, BPF_MAP_TYPE_USER_RINGBUF];
+
+/// Largest value of a per-CPU map, `PCPU_MIN_UNIT_SIZE` in the kernel.
+const MAX_PERCPU_VALUE_SIZE: u32 = 32 * 1024;
+
+/// Page size the ring buffer sizes must be a multiple of. This is the smallest page size Linux
+/// supports, so larger pages can still be rejected at load time.
+const PAGE_SIZE: u32 = 4096;
+
+/// Memory a map can use before its definition is considered a mistake.
+const MAX_MAP_SIZE: u64 = 1 << 32;
+
+/// Longest name of a file in bpffs, `NAME_MAX`.
+const MAX_PIN_NAME_LEN: usize = 255;
+
+/// A legacy map definition, laid out as `struct bpf_map_def`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct MapDef {
+    pub(crate) map_type: u32,
+    pub(crate) key_size: u32,
+    pub(crate) value_size: u32,
+    pub(crate) max_entries: u32,
+    /// The `pinning` field that aya and iproute2 append to `struct bpf_map_def`, after `map_flags`
+    /// and `id`. 0 if the definition doesn't have one, or the map isn't pinned.
+    pub(crate) pinning: u32,
+}
+
+impl MapDef {
+    /// Parses the leading `type`, `key_size`, `value_size` and `max_entries` fields of a
+    /// definition, and its `pinning` field if it has one. Returns `None` if `bytes` is too short.
+    pub(crate) fn parse(bytes: &[u8], big_endian: bool) -> Option<Self> {
+        let mut fields = bytes.chunks_exact(4).map(|field| {
+            let field = field.try_into().unwrap();
+            if big_endian {
+                u32::from_be_bytes(field)
+            } else {
+                u32::from_le_bytes(field)
+            }
+        });
+        Some(Self {
+            map_type: fields.next()?,
+            key_size: fields.next()?,
+            value_size: fields.next()?,
+            max_entries: fields.next()?,
+            pinning: fields.nth(2).unwrap_or(0),
+        })
+    }
+
+    /// Whether the loader pins the map in bpffs, by name.
+    pub(crate) fn is_pinned(&self) -> bool {
+        self.pinning != 0
+    }
+}
+
+/// Returns the name the loader pins a map under. Maps in `maps/<name>` sections are named after
+/// their section, other maps after their symbol.
+pub(crate) fn pin_name<'a>(symbol: &'a str, section: &'a str) -> &'a str {
+    section
+        .strip_prefix("maps/")
+        .filter(|name| !name.is_empty())
+        .unwrap_or(symbol)
+}
+
+/// A problem found in a map definition.
+#[derive(Debug, PartialEq)]
+pub(crate) enum MapProblem {
+    /// The type is `BPF_MAP_TYPE_UNSPEC`, or isn't a known map type.
+    UnknownMapType(u32),
+    ZeroMaxEntries,
+    /// Storage maps have no `max_entries`.
+    UnexpectedMaxEntries(u32),
+    /// The map would use more than [`MAX_MAP_SIZE`] bytes.
+    HugeMaxEntries(u32),
+    /// Array maps are indexed by a `u32`.
+    InvalidArrayKeySize(u32),
+    InvalidPercpuValueSize(u32),
+    InvalidRingBufferSize(u32),
+}
+
+impl MapProblem {
+    /// Whether the kernel rejects the map. Other problems are likely mistakes, but the map can
+    /// still be loaded.
+    pub(crate) fn is_error(&self) -> bool {
+        !matches!(self, Self::HugeMaxEntries(_))
+    }
+}
+
+impl fmt::Display for MapProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownMapType(map_type) => {
+                write!(f, "type is {map_type}, which isn't a known map type")
+            }
+            Self::ZeroMaxEntries => write!(f, "max_entries is 0"),
+            Self::UnexpectedMaxEntries(max_entries) => write!(
+                f,
+                "max_entries is {max_entries}, but must be 0 for storage maps"
+            ),
+            Self::HugeMaxEntries(max_entries) => write!(
+                f,
+                "max_entries is {max_entries}, the map can use more than {MAX_MAP_SIZE} bytes"
+            ),
+            Self::InvalidArrayKeySize(key_size) => write!(
+                f,
+                "key_size is {key_size}, but array maps must have 4 bytes keys"
+            ),
+            Self::InvalidPercpuValueSize(value_size) => write!(
+                f,
+                "value_size is {value_size}, but per-CPU maps can't have values larger than \
+                 {MAX_PERCPU_VALUE_SIZE} bytes"
+            ),
+            Self::InvalidRingBufferSize(max_entries) => write!(
+                f,
+                "max_entries is {max_entries}, but ring buffer sizes must be a power of 2 and a \
+                 multiple of {PAGE_SIZE}"
+            ),
+        }
+    }
+}
+
+/// A problem found in the pinning of a map.
+#[derive(Debug, PartialEq)]
+pub(crate) enum PinProblem<'a> {
+    /// The pin name is longer than bpffs allows.
+    NameTooLong(&'a str),
+    /// Another map is pinned under the same name, so both are pinned at the same path.
+    DuplicatePath { name: &'a str, map: &'a str },
+}
+
+impl fmt::Display for PinProblem<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NameTooLong(name) => write!(
+                f,
+                "pinned as `{name}`, but bpffs names can't be longer than {MAX_PIN_NAME_LEN} \
+                 bytes"
+            ),
+            Self::DuplicatePath { name, map } => {
+                write!(f, "pinned as `{name}`, like map `{map}`")
+            }
+        }
+    }
+}
+
+/// Returns the pinning problems of `maps`, given as `(map, pin name)` pairs of the maps that are
+/// pinned, along with the map they apply to.
+pub(crate) fn check_pinning<'a>(maps: &[(&'a str, &'a str)]) -> Vec<(&'a str, PinProblem<'a>)> {
+    let mut problems = Vec::new();
+    for (index, &(map, name)) in maps.iter().enumerate() {
+        if name.len() > MAX_PIN_NAME_LEN {
+            problems.push((map, PinProblem::NameTooLong(name)));
+        }
+        if let Some(&(other, _)) = maps[..index]
+            .iter()
+            .find(|&&(_, other_name)| other_name == name)
+        {
+            problems.push((map, PinProblem::DuplicatePath { name, map: other }));
+        }
+    }
+    problems
+}
+
+/// Returns the problems found in `def`.
+pub(crate) fn check(def: &MapDef) -> Vec<MapProblem> {
+    let MapDef {
+        map_type,
+        key_size,
+        value_size,
+        max_entries,
+        pinning: _,
+    } = *def;
+    let mut problems = Vec::new();
+
+    if map_type == 0 || map_type > BPF_MAP_TYPE_MAX {
+        problems.push(MapProblem::UnknownMapType(map_type));
+    }
+    if STORAGE_MAP_TYPES.contains(&map_type) {
+        if max_entries != 0 {
+            problems.push(MapProblem::UnexpectedMaxEntries(max_entries));
+        }
+    } else if RINGBUF_MAP_TYPES.contains(&map_type) {
+        if !max_entries.is_power_of_two() || !max_entries.is_multiple_of(PAGE_SIZE) {
+            problems.push(MapProblem::InvalidRingBufferSize(max_entries));
+        }
+    } else if max_entries == 0 {
+        // The loader sets the size of perf event arrays to the number of CPUs.
+        if map_type != BPF_MAP_TYPE_PERF_EVENT_ARRAY {
+            problems.push(MapProblem::ZeroMaxEntries);
+        }
+    } else if u64::from(max_entries) * (u64::from(key_size) + u64::from(value_size)) > MAX_MAP_SIZE
+    {
+        problems.push(MapProblem::HugeMaxEntries(max_entries));
+    }
+
+    if ARRAY_MAP_TYPES.contains(&map_type) && key_size != 4 {
+        problems.push(MapProblem::InvalidArrayKeySize(key_size));
+    }
+    if PERCPU_MAP_TYPES.contains(&map_type) && value_size > MAX_PERCPU_VALUE_SIZE {
+        problems.push(MapProblem::InvalidPercpuValueSize(value_size));
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BPF_MAP_TYPE_HASH: u32 = 1;
+
+    fn def(map_type: u32, key_size: u32, value_size: u32, max_entries: u32) -> MapDef {
+        MapDef {
+            map_type,
+            key_size,
+            value_size,
+            max_entries,
+            pinning: 0,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let bytes = [1, 0, 0, 0, 4, 0, 0, 0, 8, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        assert_eq!(MapDef::parse(&bytes, false), Some(def(1, 4, 8, 256)));
+        let bytes = [0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 8, 0, 0, 1, 0];
+        assert_eq!(MapDef::parse(&bytes, true), Some(def(1, 4, 8, 256)));
+        assert_eq!(MapDef::parse(&bytes[..12], false), None);
+
+        // aya's layout, with map_flags, id and pinning.
+        let bytes = [
+            1, 0, 0, 0, 4, 0, 0, 0, 8, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0,
+        ];
+        let parsed = MapDef::parse(&bytes, false).unwrap();
+        assert_eq!(parsed.pinning, 1);
+        assert!(parsed.is_pinned());
+    }
+
+    #[test]
+    fn test_pin_name() {
+        assert_eq!(pin_name("EVENTS", "maps"), "EVENTS");
+        assert_eq!(pin_name("EVENTS", "maps/events"), "events");
+        assert_eq!(pin_name("EVENTS", "maps/"), "EVENTS");
+    }
+
+    #[test]
+    fn test_check_pinning() {
+        assert_eq!(check_pinning(&[("A", "a"), ("B", "b")]), []);
+        assert_eq!(
+            check_pinning(&[("A", "a"), ("B", "b"), ("C", "a")]),
+            [(
+                "C",
+                PinProblem::DuplicatePath {
+                    name: "a",
+                    map: "A"
+                }
+            )]
+        );
+        let long = "m".repeat(MAX_PIN_NAME_LEN + 1);
+        assert_eq!(
+            check_pinning(&[("A", &long)]),
+            [("A", PinProblem::NameTooLong(&long))]
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        assert_eq!(check(&def(BPF_MAP_TYPE_HASH, 4, 8, 1024)), []);
+        assert_eq!(
+            check(&def(BPF_MAP_TYPE_HASH, 4, 8, 0)),
+            [MapProblem::ZeroMaxEntries]
+        );
+        assert_eq!(check(&def(BPF_MAP_TYPE_PERF_EVENT_ARRAY, 4, 4, 0)), []);
+        assert_eq!(check(&def(0, 4, 8, 1)), [MapProblem::UnknownMapType(0)]);
+        assert_eq!(check(&def(100, 4, 8, 1)), [MapProblem::UnknownMapType(100)]);
+        assert_eq!(
+            check(&def(BPF_MAP_TYPE_HASH, 64, 1024, u32::MAX)),
+            [MapProblem::HugeMaxEntries(u32::MAX)]
+        );
+        assert_eq!(
+            check(&def(BPF_MAP_TYPE_ARRAY, 8, 8, 16)),
+            [MapProblem::InvalidArrayKeySize(8)]
+        );
+        assert_eq!(
+            check(&def(BPF_MAP_TYPE_PERCPU_ARRAY, 4, 64 * 1024, 1)),
+            [MapProblem::InvalidPercpuValueSize(64 * 1024)]
+        );
+        assert_eq!(check(&def(BPF_MAP_TYPE_SK_STORAGE, 4, 8, 0)), []);
+        assert_eq!(
+            check(&def(BPF_MAP_TYPE_SK_STORAGE, 4, 8, 1)),
+            [MapProblem::UnexpectedMaxEntries(1)]
+        );
+        assert_eq!(check(&def(BPF_MAP_TYPE_RINGBUF, 0, 0, 256 * 1024)), []);
+        assert_eq!(
+            check(&def(BPF_MAP_TYPE_RINGBUF, 0, 0, 1000)),
+            [MapProblem::InvalidRingBufferSize(1000)]
+        );
+    }
+}
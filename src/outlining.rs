@@ -0,0 +1,73 @@
+use object::{Object as _, ObjectSymbol as _, SymbolKind};
+
+use crate::btf::ObjectError;
+
+/// Markers LLVM puts in the names of the functions it creates by moving code out of other
+/// functions: the machine outliner, hot/cold splitting, partial inlining and the other users of
+/// the code extractor, and function specialization.
+const ARTIFICIAL_FUNCTION_MARKERS: &[&str] = &[
+    "OUTLINED_FUNCTION_",
+    ".cold",
+    ".outlined",
+    ".extracted",
+    ".specialized.",
+];
+
+/// Returns the names of the functions of `object` that LLVM created by moving code out of other
+/// functions, rather than functions of the inputs.
+pub fn artificial_functions(object: &object::File<'_>) -> Result<Vec<String>, ObjectError> {
+    let mut functions = Vec::new();
+    for symbol in object.symbols() {
+        if symbol.kind() != SymbolKind::Text {
+            continue;
+        }
+        let name = symbol.name()?;
+        if ARTIFICIAL_FUNCTION_MARKERS
+            .iter()
+            .any(|marker| name.contains(marker))
+        {
+            functions.push(name.to_owned());
+        }
+    }
+    Ok(functions)
+}
+
+#[cfg(test)]
+mod test {
+    use object::{
+        write::{self, Symbol, SymbolSection},
+        Architecture, BinaryFormat, Endianness, SectionKind, SymbolFlags, SymbolScope,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_artificial_functions() {
+        let mut object =
+            write::Object::new(BinaryFormat::Elf, Architecture::Bpf, Endianness::Little);
+        let text = object.add_section(vec![], b".text".to_vec(), SectionKind::Text);
+        let _: u64 = object.append_section_data(text, &[0; 24], 8);
+        for (index, name) in ["handle.1", "handle.cold.1", "OUTLINED_FUNCTION_0"]
+            .into_iter()
+            .enumerate()
+        {
+            let _: write::SymbolId = object.add_symbol(Symbol {
+                name: name.as_bytes().to_vec(),
+                value: index as u64 * 8,
+                size: 8,
+                kind: SymbolKind::Text,
+                scope: SymbolScope::Compilation,
+                weak: false,
+                section: SymbolSection::Section(text),
+                flags: SymbolFlags::None,
+            });
+        }
+        let object = object.write().unwrap();
+        let object = object::File::parse(object.as_slice()).unwrap();
+
+        assert_eq!(
+            artificial_functions(&object).unwrap(),
+            ["handle.cold.1", "OUTLINED_FUNCTION_0"]
+        );
+    }
+}
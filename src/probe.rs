@@ -0,0 +1,105 @@
+use std::sync::OnceLock;
+
+use tracing::{debug, warn};
+
+use crate::Cpu;
+
+/// Returns the most recent CPU version whose instructions the running kernel accepts, probed
+/// once by loading small programs using them.
+///
+/// Unlike the `probe` CPU of LLVM, which stops at v3, this also detects v4. When the kernel
+/// can't be probed, for example because the process isn't allowed to load programs, `generic` is
+/// returned.
+pub(crate) fn native_cpu() -> Cpu {
+    static CPU: OnceLock<Cpu> = OnceLock::new();
+    *CPU.get_or_init(|| {
+        let cpu = probe_cpu();
+        debug!("probed cpu: {}", cpu);
+        cpu
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn probe_cpu() -> Cpu {
+    // Instructions of the probes, as `(code, dst_reg | src_reg << 4, off, imm)`.
+    const MOV64_R0_0: Insn = (0xb7, 0, 0, 0);
+    const EXIT: Insn = (0x95, 0, 0, 0);
+    // v4: `r0 = (s8)r0`, a sign extending move.
+    const MOVSX8_R0: Insn = (0xbf, 0, 8, 0);
+    // v3: `if w0 == 0 goto +0`, a 32-bit jump.
+    const JEQ32_W0_0: Insn = (0x16, 0, 0, 0);
+    // v2: `if r0 < 1 goto +0`, an unsigned less than jump.
+    const JLT_R0_1: Insn = (0xa5, 0, 0, 1);
+
+    if !load(&[MOV64_R0_0, EXIT]) {
+        warn!("can't load BPF programs to probe the cpu, using generic");
+        return Cpu::Generic;
+    }
+    [
+        (Cpu::V4, MOVSX8_R0),
+        (Cpu::V3, JEQ32_W0_0),
+        (Cpu::V2, JLT_R0_1),
+    ]
+    .into_iter()
+    .find(|&(_, insn)| load(&[MOV64_R0_0, insn, EXIT]))
+    .map_or(Cpu::V1, |(cpu, _)| cpu)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn probe_cpu() -> Cpu {
+    warn!("can't probe the cpu on this system, using generic");
+    Cpu::Generic
+}
+
+#[cfg(target_os = "linux")]
+type Insn = (u8, u8, i16, i32);
+
+/// Whether the kernel accepts a socket filter made of `insns`.
+#[cfg(target_os = "linux")]
+fn load(insns: &[Insn]) -> bool {
+    use std::ptr;
+
+    const BPF_PROG_LOAD: libc::c_long = 5;
+    const BPF_PROG_TYPE_SOCKET_FILTER: u32 = 1;
+
+    /// The leading fields of the `BPF_PROG_LOAD` command of `union bpf_attr`. The kernel
+    /// requires the fields it knows past the given size to be zero.
+    #[repr(C)]
+    struct ProgLoadAttr {
+        prog_type: u32,
+        insn_cnt: u32,
+        insns: u64,
+        license: u64,
+    }
+
+    let insns = insns
+        .iter()
+        .flat_map(|&(code, regs, off, imm)| {
+            let mut insn = [0; 8];
+            insn[0] = code;
+            insn[1] = regs;
+            insn[2..4].copy_from_slice(&off.to_ne_bytes());
+            insn[4..].copy_from_slice(&imm.to_ne_bytes());
+            insn
+        })
+        .collect::<Vec<u8>>();
+    let attr = ProgLoadAttr {
+        prog_type: BPF_PROG_TYPE_SOCKET_FILTER,
+        insn_cnt: (insns.len() / 8) as u32,
+        insns: insns.as_ptr() as u64,
+        license: c"GPL".as_ptr() as u64,
+    };
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_bpf,
+            BPF_PROG_LOAD,
+            ptr::from_ref(&attr),
+            size_of::<ProgLoadAttr>(),
+        )
+    };
+    if fd < 0 {
+        return false;
+    }
+    let _: libc::c_int = unsafe { libc::close(fd as libc::c_int) };
+    true
+}
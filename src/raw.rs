@@ -0,0 +1,249 @@
+use std::{collections::HashMap, fmt};
+
+use object::{
+    Object as _, ObjectSection as _, ObjectSymbol as _, RelocationFlags, RelocationTarget,
+    SectionKind, SymbolKind,
+};
+use thiserror::Error;
+
+use crate::btf::ObjectError;
+
+const BPF_INSN_SIZE: u64 = 8;
+const BPF_CALL: u8 = 0x85;
+/// Source register of calls to BPF functions, as opposed to helpers.
+const BPF_PSEUDO_CALL: u8 = 1;
+/// Relocation of the 64-bit immediate of `ld_imm64` instructions, used to load the address of
+/// maps and global variables.
+const R_BPF_64_64: u32 = 1;
+
+/// Errors returned by [`raw_programs`].
+#[derive(Debug, Error)]
+pub enum RawProgramsError {
+    /// The object file couldn't be analyzed.
+    #[error(transparent)]
+    Object(#[from] ObjectError),
+
+    /// A program calls a BPF function. The raw instructions of a program can't refer to other
+    /// functions, so functions must be inlined.
+    #[error("program `{0}` calls a BPF function, which raw programs can't do")]
+    BpfCall(String),
+}
+
+impl From<object::Error> for RawProgramsError {
+    fn from(error: object::Error) -> Self {
+        Self::Object(error.into())
+    }
+}
+
+/// A reference to a map or global variable by an `ld_imm64` instruction, which the embedder must
+/// patch with the file descriptor or address of the map.
+#[derive(Debug, PartialEq)]
+pub struct MapReference {
+    /// Index of the instruction in the program.
+    pub instruction: usize,
+    /// Symbol the instruction refers to, or the section for references to data sections.
+    pub symbol: String,
+}
+
+/// The raw instructions of a program.
+#[derive(Debug, PartialEq)]
+pub struct RawProgram {
+    pub name: String,
+    pub section: String,
+    /// The instructions, in the byte order of the object.
+    pub instructions: Vec<u8>,
+    pub map_references: Vec<MapReference>,
+}
+
+impl fmt::Display for RawProgram {
+    /// Formats the program as an entry of an index of raw programs, without its instructions.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            name,
+            section,
+            instructions,
+            map_references,
+        } = self;
+        writeln!(
+            f,
+            "program {name} section={section} insns={}",
+            instructions.len() as u64 / BPF_INSN_SIZE
+        )?;
+        for MapReference {
+            instruction,
+            symbol,
+        } in map_references
+        {
+            writeln!(f, "  map {symbol} insn={instruction}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Extracts the raw instructions of each program of `object`, along with the instructions that
+/// refer to maps and global variables, for loaders that don't handle ELF objects.
+///
+/// Programs must not call BPF functions.
+pub fn raw_programs(object: &object::File<'_>) -> Result<Vec<RawProgram>, RawProgramsError> {
+    let src_shift = if object.is_little_endian() { 4 } else { 0 };
+
+    let mut programs = Vec::new();
+    for symbol in object.symbols() {
+        if symbol.kind() != SymbolKind::Text || !symbol.is_global() || symbol.size() == 0 {
+            continue;
+        }
+        let Some(section_index) = symbol.section_index() else {
+            continue;
+        };
+        let section = object.section_by_index(section_index)?;
+        let section_name = section.name()?;
+        // Global functions in .text are subprograms rather than programs.
+        if section.kind() != SectionKind::Text || section_name == ".text" {
+            continue;
+        }
+        let name = symbol.name()?;
+        let (start, end) = (symbol.address(), symbol.address() + symbol.size());
+        let Some(instructions) = section.data()?.get(start as usize..end as usize) else {
+            continue;
+        };
+
+        let relocations = section
+            .relocations()
+            .filter(|(offset, _)| (start..end).contains(offset))
+            .collect::<HashMap<_, _>>();
+        let mut map_references = Vec::new();
+        for (index, insn) in instructions
+            .chunks_exact(BPF_INSN_SIZE as usize)
+            .enumerate()
+        {
+            let offset = start + index as u64 * BPF_INSN_SIZE;
+            let relocation = relocations.get(&offset);
+            if insn[0] == BPF_CALL && (insn[1] >> src_shift) & 0xf == BPF_PSEUDO_CALL {
+                // Calls that aren't relocated are relative to the call itself, and may stay
+                // within the program.
+                let imm = if object.is_little_endian() {
+                    i32::from_le_bytes(insn[4..8].try_into().unwrap())
+                } else {
+                    i32::from_be_bytes(insn[4..8].try_into().unwrap())
+                };
+                let target = offset as i64 + (i64::from(imm) + 1) * BPF_INSN_SIZE as i64;
+                if relocation.is_some() || !(start as i64..end as i64).contains(&target) {
+                    return Err(RawProgramsError::BpfCall(name.to_owned()));
+                }
+            }
+            let Some(relocation) = relocation else {
+                continue;
+            };
+            let (
+                RelocationFlags::Elf {
+                    r_type: R_BPF_64_64,
+                },
+                RelocationTarget::Symbol(target),
+            ) = (relocation.flags(), relocation.target())
+            else {
+                continue;
+            };
+            let target = object.symbol_by_index(target)?;
+            let symbol = match (target.kind(), target.section_index()) {
+                (SymbolKind::Section, Some(index)) => object.section_by_index(index)?.name()?,
+                _ => target.name()?,
+            };
+            map_references.push(MapReference {
+                instruction: index,
+                symbol: symbol.to_owned(),
+            });
+        }
+
+        programs.push(RawProgram {
+            name: name.to_owned(),
+            section: section_name.to_owned(),
+            instructions: instructions.to_vec(),
+            map_references,
+        });
+    }
+    Ok(programs)
+}
+
+#[cfg(test)]
+mod test {
+    use object::{
+        write::{self, Relocation, Symbol, SymbolSection},
+        Architecture, BinaryFormat, Endianness, SymbolFlags, SymbolScope,
+    };
+
+    use super::*;
+
+    fn add_symbol(
+        object: &mut write::Object<'_>,
+        section: write::SectionId,
+        name: &str,
+        kind: SymbolKind,
+        value: u64,
+        size: u64,
+    ) -> write::SymbolId {
+        object.add_symbol(Symbol {
+            name: name.as_bytes().to_vec(),
+            value,
+            size,
+            kind,
+            scope: SymbolScope::Dynamic,
+            weak: false,
+            section: SymbolSection::Section(section),
+            flags: SymbolFlags::None,
+        })
+    }
+
+    #[test]
+    fn test_raw_programs() {
+        let mut object =
+            write::Object::new(BinaryFormat::Elf, Architecture::Bpf, Endianness::Little);
+        let maps = object.add_section(vec![], b"maps".to_vec(), SectionKind::Data);
+        let _: u64 = object.append_section_data(maps, &[0; 20], 4);
+        let map = add_symbol(&mut object, maps, "EVENTS", SymbolKind::Data, 0, 20);
+
+        // r1 = EVENTS ll; r0 = 0; exit
+        let code = [
+            [0x18, 0x01, 0, 0, 0, 0, 0, 0],
+            [0; 8],
+            [0xb7, 0, 0, 0, 0, 0, 0, 0],
+            [0x95, 0, 0, 0, 0, 0, 0, 0],
+        ]
+        .concat();
+        let xdp = object.add_section(vec![], b"xdp".to_vec(), SectionKind::Text);
+        let _: u64 = object.append_section_data(xdp, &code, 8);
+        let _: write::SymbolId = add_symbol(&mut object, xdp, "xdp_pass", SymbolKind::Text, 0, 32);
+        object
+            .add_relocation(
+                xdp,
+                Relocation {
+                    offset: 0,
+                    symbol: map,
+                    addend: 0,
+                    flags: RelocationFlags::Elf {
+                        r_type: R_BPF_64_64,
+                    },
+                },
+            )
+            .unwrap();
+        let object = object.write().unwrap();
+        let object = object::File::parse(object.as_slice()).unwrap();
+
+        let programs = raw_programs(&object).unwrap();
+        assert_eq!(
+            programs,
+            [RawProgram {
+                name: "xdp_pass".into(),
+                section: "xdp".into(),
+                instructions: code,
+                map_references: vec![MapReference {
+                    instruction: 0,
+                    symbol: "EVENTS".into(),
+                }],
+            }]
+        );
+        assert_eq!(
+            programs[0].to_string(),
+            "program xdp_pass section=xdp insns=4\n  map EVENTS insn=0\n"
+        );
+    }
+}
@@ -0,0 +1,778 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+};
+
+use object::{
+    Object as _, ObjectSection as _, ObjectSymbol as _, RelocationFlags, RelocationTarget,
+    SymbolKind,
+};
+use thiserror::Error;
+
+use crate::{
+    btf::{
+        merge_split_btf, Btf, MalformedBtf, ObjectError, Reader, BTF_KIND_ARRAY, BTF_KIND_ENUM,
+        BTF_KIND_ENUM64, BTF_KIND_STRUCT, BTF_KIND_UNION, BTF_MAGIC,
+    },
+    demangle::demangle,
+    diagnostic::json_string,
+};
+
+/// Names of the CO-RE relocation kinds, by `enum bpf_core_relo_kind` value, as libbpf names them.
+const CORE_RELO_KINDS: &[&str] = &[
+    "byte_off",
+    "byte_sz",
+    "field_exists",
+    "signed",
+    "lshift_u64",
+    "rshift_u64",
+    "local_type_id",
+    "target_type_id",
+    "type_exists",
+    "type_size",
+    "enumval_exists",
+    "enumval_value",
+    "type_matches",
+];
+
+/// Errors returned by [`core_relocation_report`].
+#[derive(Debug, Error)]
+pub enum RelocationReportError {
+    /// The object file couldn't be analyzed.
+    #[error(transparent)]
+    Object(#[from] ObjectError),
+
+    /// The BTF to resolve CO-RE relocations against is malformed.
+    #[error("malformed target BTF")]
+    InvalidTargetBtf,
+
+    /// The BTF of kernel modules was given without the BTF of the kernel it is split from.
+    #[error("the BTF of kernel modules needs the BTF of the kernel as its base")]
+    MissingBaseBtf,
+}
+
+impl From<object::Error> for RelocationReportError {
+    fn from(error: object::Error) -> Self {
+        Self::Object(error.into())
+    }
+}
+
+impl From<MalformedBtf> for RelocationReportError {
+    fn from(error: MalformedBtf) -> Self {
+        Self::Object(error.into())
+    }
+}
+
+/// The relocations of one kind in a section.
+#[derive(Debug, Default, PartialEq)]
+pub struct RelocationKindSummary {
+    /// Number of relocations.
+    pub count: usize,
+    /// Symbols the relocations refer to. For CO-RE relocations, the type and access string.
+    pub targets: BTreeSet<String>,
+}
+
+/// The relocations applied to a section, by kind, eg `R_BPF_64_64` or `CO-RE byte_off`.
+#[derive(Debug, PartialEq)]
+pub struct SectionRelocations {
+    pub section: String,
+    pub kinds: BTreeMap<String, RelocationKindSummary>,
+}
+
+impl fmt::Display for SectionRelocations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { section, kinds } = self;
+        writeln!(f, "{section}:")?;
+        for (kind, summary) in kinds {
+            let RelocationKindSummary { count, targets } = summary;
+            let targets = targets.iter().map(String::as_str).collect::<Vec<_>>();
+            writeln!(f, "  {kind}: {count} ({})", targets.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+fn elf_relocation_kind(r_type: u32) -> String {
+    // Relocation types, from `llvm/BinaryFormat/ELFRelocs/BPF.def`.
+    match r_type {
+        0 => "R_BPF_NONE".into(),
+        1 => "R_BPF_64_64".into(),
+        2 => "R_BPF_64_ABS64".into(),
+        3 => "R_BPF_64_ABS32".into(),
+        4 => "R_BPF_64_NODYLD32".into(),
+        10 => "R_BPF_64_32".into(),
+        r_type => format!("R_BPF_{r_type}"),
+    }
+}
+
+/// A record of the CO-RE relocations of `.BTF.ext`, laid out as `struct bpf_core_relo`.
+struct CoreReloRecord {
+    section: String,
+    insn_off: usize,
+    type_id: usize,
+    access: String,
+    kind: usize,
+}
+
+/// Name of a CO-RE relocation kind, as libbpf names them.
+fn core_relo_kind(kind: usize) -> String {
+    match CORE_RELO_KINDS.get(kind) {
+        Some(name) => (*name).into(),
+        None => kind.to_string(),
+    }
+}
+
+/// Reads the CO-RE relocations of `.BTF.ext`.
+fn core_relo_records(
+    btf: &Btf<'_>,
+    btf_ext: &[u8],
+    little_endian: bool,
+) -> Result<Vec<CoreReloRecord>, ObjectError> {
+    let reader = Reader {
+        data: btf_ext,
+        little_endian,
+        section: ".BTF.ext",
+    };
+    if reader.u16(0)? != BTF_MAGIC {
+        return Err(ObjectError::InvalidBtf(".BTF.ext"));
+    }
+    let hdr_len = reader.u32(4)?;
+    // Headers without the CO-RE relocation fields have no CO-RE relocations.
+    if hdr_len < 32 {
+        return Ok(Vec::new());
+    }
+    let (core_relo_off, core_relo_len) = (reader.u32(24)?, reader.u32(28)?);
+    if core_relo_len == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut records = Vec::new();
+    let mut offset = hdr_len + core_relo_off;
+    let end = offset + core_relo_len;
+    let record_size = reader.u32(offset)?;
+    offset += 4;
+    while offset < end {
+        let section = btf.string(reader.u32(offset)?);
+        let num_info = reader.u32(offset + 4)?;
+        offset += 8;
+        for _ in 0..num_info {
+            records.push(CoreReloRecord {
+                section: section.clone(),
+                insn_off: reader.u32(offset)?,
+                type_id: reader.u32(offset + 4)?,
+                access: btf.string(reader.u32(offset + 8)?),
+                kind: reader.u32(offset + 12)?,
+            });
+            offset += record_size;
+        }
+    }
+    Ok(records)
+}
+
+/// Adds the CO-RE relocations of `.BTF.ext` to `sections`.
+fn core_relocations(
+    btf: &Btf<'_>,
+    btf_ext: &[u8],
+    little_endian: bool,
+    sections: &mut BTreeMap<String, BTreeMap<String, RelocationKindSummary>>,
+) -> Result<(), ObjectError> {
+    for record in core_relo_records(btf, btf_ext, little_endian)? {
+        let CoreReloRecord {
+            section,
+            insn_off: _,
+            type_id,
+            access,
+            kind,
+        } = record;
+        let summary = sections
+            .entry(section)
+            .or_default()
+            .entry(format!("CO-RE {}", core_relo_kind(kind)))
+            .or_default();
+        summary.count += 1;
+        let _: bool = summary
+            .targets
+            .insert(format!("{}:{}", btf.type_name(type_id), access));
+    }
+    Ok(())
+}
+
+/// Summarizes the relocations of `object` by section and kind: ELF relocations like
+/// `R_BPF_64_64` or `R_BPF_64_NODYLD32`, and the CO-RE relocations recorded in `.BTF.ext`.
+///
+/// Loaders that predate a relocation kind can't load objects that use it, so this helps telling
+/// which loader versions an object requires.
+pub fn relocation_report(
+    object: &object::File<'_>,
+) -> Result<Vec<SectionRelocations>, ObjectError> {
+    let mut sections = BTreeMap::<String, BTreeMap<String, RelocationKindSummary>>::new();
+
+    for section in object.sections() {
+        let mut kinds = BTreeMap::<String, RelocationKindSummary>::new();
+        for (_, relocation) in section.relocations() {
+            let kind = match relocation.flags() {
+                RelocationFlags::Elf { r_type } => elf_relocation_kind(r_type),
+                flags => format!("{flags:?}"),
+            };
+            let target = match relocation.target() {
+                RelocationTarget::Symbol(index) => {
+                    let symbol = object.symbol_by_index(index)?;
+                    match (symbol.kind(), symbol.section_index()) {
+                        (SymbolKind::Section, Some(index)) => {
+                            object.section_by_index(index)?.name()?.to_owned()
+                        }
+                        _ => demangle(symbol.name()?),
+                    }
+                }
+                target => format!("{target:?}"),
+            };
+            let summary = kinds.entry(kind).or_default();
+            summary.count += 1;
+            let _: bool = summary.targets.insert(target);
+        }
+        if !kinds.is_empty() {
+            sections
+                .entry(section.name()?.to_owned())
+                .or_default()
+                .extend(kinds);
+        }
+    }
+
+    let little_endian = object.is_little_endian();
+    if let (Some(btf), Some(btf_ext)) = (
+        object.section_by_name(".BTF"),
+        object.section_by_name(".BTF.ext"),
+    ) {
+        let btf = Btf::parse(btf.data()?, little_endian)?;
+        core_relocations(&btf, btf_ext.data()?, little_endian, &mut sections)?;
+    }
+
+    Ok(sections
+        .into_iter()
+        .map(|(section, kinds)| SectionRelocations { section, kinds })
+        .collect())
+}
+
+/// A CO-RE relocation of the linked object.
+#[derive(Debug, PartialEq)]
+pub struct CoreRelocation {
+    /// Section of the relocated instruction.
+    pub section: String,
+    /// Offset in bytes of the relocated instruction in its section.
+    pub insn_off: usize,
+    /// Kind of the relocation, as libbpf names them, eg `byte_off` or `type_exists`.
+    pub kind: String,
+    /// Name of the local type the access string starts from.
+    pub type_name: String,
+    /// Access string, eg `0:1:2` for the third member of the second member of the type.
+    pub access: String,
+    /// Whether the relocation resolves against the target BTF, when one was given.
+    pub resolved: Option<bool>,
+}
+
+impl CoreRelocation {
+    /// Whether the relocation checks whether a field, type or enumerator exists, in which case
+    /// the program handles it not resolving.
+    pub fn checks_existence(&self) -> bool {
+        matches!(
+            self.kind.as_str(),
+            "field_exists" | "type_exists" | "enumval_exists" | "type_matches"
+        )
+    }
+
+    /// Formats the relocation as a JSON object.
+    pub fn to_json(&self) -> String {
+        let Self {
+            section,
+            insn_off,
+            kind,
+            type_name,
+            access,
+            resolved,
+        } = self;
+        let resolved = match resolved {
+            Some(resolved) => resolved.to_string(),
+            None => "null".into(),
+        };
+        format!(
+            "{{\"section\":{},\"insn_off\":{insn_off},\"kind\":{},\"type\":{},\"access\":{},\"resolved\":{resolved}}}",
+            json_string(section),
+            json_string(kind),
+            json_string(type_name),
+            json_string(access),
+        )
+    }
+}
+
+impl fmt::Display for CoreRelocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            section,
+            insn_off,
+            kind,
+            type_name,
+            access,
+            resolved,
+        } = self;
+        write!(f, "{section}+{insn_off:#x}: {kind} {type_name}:{access}")?;
+        match resolved {
+            Some(true) => writeln!(f, " (resolved)"),
+            Some(false) => writeln!(f, " (unresolved)"),
+            None => writeln!(f),
+        }
+    }
+}
+
+/// The name of a type or field without its `___flavor` suffix, which CO-RE ignores.
+fn essential_name(name: &str) -> &str {
+    name.rfind("___").map_or(name, |end| &name[..end])
+}
+
+/// Whether types of kinds `local` and `target` can match.
+fn kinds_compatible(local: usize, target: usize) -> bool {
+    local == target
+        || matches!(
+            (local, target),
+            (
+                BTF_KIND_ENUM | BTF_KIND_ENUM64,
+                BTF_KIND_ENUM | BTF_KIND_ENUM64
+            )
+        )
+}
+
+/// Resolves CO-RE relocations against a target BTF, matching types and fields by name like libbpf
+/// does.
+struct Resolver<'a> {
+    local: &'a Btf<'a>,
+    target: &'a Btf<'a>,
+    /// The type ids of the named types of the target BTF, by essential name.
+    target_types: HashMap<&'a str, Vec<usize>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(local: &'a Btf<'a>, target: &'a Btf<'a>, names: &'a [String]) -> Self {
+        let mut target_types = HashMap::<_, Vec<_>>::new();
+        for (index, name) in names.iter().enumerate() {
+            if !name.is_empty() {
+                target_types
+                    .entry(essential_name(name))
+                    .or_default()
+                    .push(index + 1);
+            }
+        }
+        Self {
+            local,
+            target,
+            target_types,
+        }
+    }
+
+    /// The target types with the name and a compatible kind of the local type `type_id`.
+    fn candidates(&self, type_id: usize) -> Vec<usize> {
+        let Some(local) = self.local.type_by_id(type_id) else {
+            return Vec::new();
+        };
+        // Anonymous types have nothing to be matched by.
+        if local.name_off == 0 {
+            return Vec::new();
+        }
+        let name = self.local.string(local.name_off);
+        self.target_types
+            .get(essential_name(&name))
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|&id| {
+                self.target
+                    .type_by_id(id)
+                    .is_some_and(|target| kinds_compatible(local.kind, target.kind))
+            })
+            .collect()
+    }
+
+    fn resolve(&self, record: &CoreReloRecord) -> Result<bool, MalformedBtf> {
+        let CoreReloRecord {
+            type_id,
+            access,
+            kind,
+            ..
+        } = record;
+        let spec = access
+            .split(':')
+            .map(str::parse)
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|_| MalformedBtf(".BTF.ext"))?;
+        for candidate in self.candidates(*type_id) {
+            let resolved = match *kind {
+                // byte_off, byte_sz, field_exists, signed, lshift_u64 and rshift_u64
+                0..=5 => self.resolve_field(*type_id, candidate, &spec)?,
+                // enumval_exists and enumval_value
+                10 | 11 => self.resolve_enumval(*type_id, candidate, &spec)?,
+                // The type relocations only need a type with the same name.
+                _ => true,
+            };
+            if resolved {
+                return Ok(true);
+            }
+        }
+        // The local type id always resolves.
+        Ok(*kind == 6)
+    }
+
+    /// Whether the member or array element of the local type `local_id` that the access string
+    /// `spec` designates exists in the target type `target_id`.
+    fn resolve_field(
+        &self,
+        mut local_id: usize,
+        mut target_id: usize,
+        spec: &[usize],
+    ) -> Result<bool, MalformedBtf> {
+        // The first index only offsets the pointer to the type.
+        for &index in spec.iter().skip(1) {
+            local_id = self.local.skip_modifiers(local_id);
+            target_id = self.target.skip_modifiers(target_id);
+            let Some(local) = self.local.type_by_id(local_id) else {
+                return Ok(false);
+            };
+            match local.kind {
+                BTF_KIND_STRUCT | BTF_KIND_UNION => {
+                    let members = self.local.members(local)?;
+                    let Some((name, ty)) = members.get(index) else {
+                        return Err(MalformedBtf(".BTF.ext"));
+                    };
+                    local_id = *ty;
+                    // Anonymous members are flattened into their parent in the target.
+                    if name.is_empty() {
+                        continue;
+                    }
+                    match self.find_member(target_id, essential_name(name))? {
+                        Some(ty) => target_id = ty,
+                        None => return Ok(false),
+                    }
+                }
+                BTF_KIND_ARRAY => {
+                    let Some(target) = self.target.type_by_id(target_id) else {
+                        return Ok(false);
+                    };
+                    if target.kind != BTF_KIND_ARRAY {
+                        return Ok(false);
+                    }
+                    local_id = self.local.reader.u32(local.data)?;
+                    target_id = self.target.reader.u32(target.data)?;
+                }
+                _ => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+
+    /// The type of the member named `name` of the target struct or union `type_id`, looking
+    /// into its anonymous members.
+    fn find_member(&self, type_id: usize, name: &str) -> Result<Option<usize>, MalformedBtf> {
+        let Some(ty) = self.target.type_by_id(type_id) else {
+            return Ok(None);
+        };
+        if !matches!(ty.kind, BTF_KIND_STRUCT | BTF_KIND_UNION) {
+            return Ok(None);
+        }
+        for (member, ty) in self.target.members(ty)? {
+            if member.is_empty() {
+                let ty = self.target.skip_modifiers(ty);
+                if let Some(ty) = self.find_member(ty, name)? {
+                    return Ok(Some(ty));
+                }
+            } else if essential_name(&member) == name {
+                return Ok(Some(ty));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Whether the enumerator of the local enum `local_id` that `spec` designates exists in the
+    /// target enum `target_id`.
+    fn resolve_enumval(
+        &self,
+        local_id: usize,
+        target_id: usize,
+        spec: &[usize],
+    ) -> Result<bool, MalformedBtf> {
+        let (Some(local), Some(target)) = (
+            self.local.type_by_id(local_id),
+            self.target.type_by_id(target_id),
+        ) else {
+            return Ok(false);
+        };
+        let local = self.local.members(local)?;
+        let Some((name, _)) = spec.first().and_then(|&index| local.get(index)) else {
+            return Err(MalformedBtf(".BTF.ext"));
+        };
+        let name = essential_name(name);
+        Ok(self
+            .target
+            .members(target)?
+            .iter()
+            .any(|(enumerator, _)| essential_name(enumerator) == name))
+    }
+}
+
+/// Lists the CO-RE relocations that `.BTF.ext` records for `object`.
+///
+/// When `target_btf` is given, typically the raw BTF of the kernel read from
+/// `/sys/kernel/btf/vmlinux`, each relocation is resolved against it the way libbpf does when
+/// loading the object, matching types and fields by name. This catches the types and fields that
+/// were renamed or removed when linking, rather than when loading.
+///
+/// `module_btfs` are the split BTF of kernel modules, typically read from
+/// `/sys/kernel/btf/<module>`, whose base is `target_btf`. A relocation is then resolved when it
+/// resolves against the kernel or any of the modules, like libbpf does for module types.
+pub fn core_relocation_report(
+    object: &object::File<'_>,
+    target_btf: Option<&[u8]>,
+    module_btfs: &[&[u8]],
+) -> Result<Vec<CoreRelocation>, RelocationReportError> {
+    let little_endian = object.is_little_endian();
+    let (Some(btf), Some(btf_ext)) = (
+        object.section_by_name(".BTF"),
+        object.section_by_name(".BTF.ext"),
+    ) else {
+        return Ok(Vec::new());
+    };
+    let btf = Btf::parse(btf.data()?, little_endian)?;
+    let records = core_relo_records(&btf, btf_ext.data()?, little_endian)?;
+
+    let targets = match target_btf {
+        None if !module_btfs.is_empty() => return Err(RelocationReportError::MissingBaseBtf),
+        None => Vec::new(),
+        Some(data) => {
+            // Raw BTF has the byte order of the machine it describes.
+            let little_endian = data.get(..2) == Some(BTF_MAGIC.to_le_bytes().as_slice());
+            if module_btfs.is_empty() {
+                vec![(data.to_vec(), little_endian)]
+            } else {
+                // Every module is resolved against along with the kernel, its split BTF
+                // referring to the types of the kernel.
+                module_btfs
+                    .iter()
+                    .map(|module| {
+                        merge_split_btf(data, module, little_endian)
+                            .map(|merged| (merged, little_endian))
+                            .map_err(|_| RelocationReportError::InvalidTargetBtf)
+                    })
+                    .collect::<Result<_, _>>()?
+            }
+        }
+    };
+    let targets = targets
+        .iter()
+        .map(|(data, little_endian)| {
+            Btf::parse(data, *little_endian).map_err(|_| RelocationReportError::InvalidTargetBtf)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let target_names = targets
+        .iter()
+        .map(|target| {
+            target
+                .types
+                .iter()
+                .map(|ty| target.string(ty.name_off))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let resolvers = targets
+        .iter()
+        .zip(&target_names)
+        .map(|(target, names)| Resolver::new(&btf, target, names))
+        .collect::<Vec<_>>();
+
+    records
+        .into_iter()
+        .map(|record| -> Result<_, RelocationReportError> {
+            let resolved = if resolvers.is_empty() {
+                None
+            } else {
+                let mut resolved = false;
+                for resolver in &resolvers {
+                    resolved |= resolver.resolve(&record)?;
+                }
+                Some(resolved)
+            };
+            let CoreReloRecord {
+                section,
+                insn_off,
+                type_id,
+                access,
+                kind,
+            } = record;
+            Ok(CoreRelocation {
+                section,
+                insn_off,
+                kind: core_relo_kind(kind),
+                type_name: btf.type_name(type_id),
+                access,
+                resolved,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use object::{
+        write::{self, Relocation, Symbol, SymbolSection},
+        Architecture, BinaryFormat, Endianness, SectionKind, SymbolFlags, SymbolScope,
+    };
+
+    use super::*;
+    use crate::btf::test::{raw_btf, u16s, u32s};
+
+    #[test]
+    fn test_relocation_report() {
+        let mut object =
+            write::Object::new(BinaryFormat::Elf, Architecture::Bpf, Endianness::Little);
+
+        let maps = object.add_section(vec![], b"maps".to_vec(), SectionKind::Data);
+        let _: u64 = object.append_section_data(maps, &[0; 20], 4);
+        let map = object.add_symbol(Symbol {
+            name: b"EVENTS".to_vec(),
+            value: 0,
+            size: 20,
+            kind: SymbolKind::Data,
+            scope: SymbolScope::Dynamic,
+            weak: false,
+            section: SymbolSection::Section(maps),
+            flags: SymbolFlags::None,
+        });
+
+        let text = object.add_section(vec![], b"kprobe".to_vec(), SectionKind::Text);
+        let _: u64 = object.append_section_data(text, &[0; 32], 8);
+        for offset in [0, 16] {
+            object
+                .add_relocation(
+                    text,
+                    Relocation {
+                        offset,
+                        symbol: map,
+                        addend: 0,
+                        flags: RelocationFlags::Elf { r_type: 1 },
+                    },
+                )
+                .unwrap();
+        }
+
+        // Strings: "", "task_struct", "kprobe", "0:1".
+        let strings = b"\0task_struct\0kprobe\00:1\0";
+        // A single struct type named task_struct, without members.
+        let types = u32s(&[1, 4 << 24, 0]);
+        let section = object.add_section(vec![], b".BTF".to_vec(), SectionKind::Other);
+        let _: u64 = object.append_section_data(section, &raw_btf(&types, strings), 4);
+
+        // One byte_off relocation of type 1 with access string "0:1", in kprobe.
+        let core_relos = u32s(&[16, 13, 1, 8, 1, 20, 0]);
+        let mut btf_ext = u16s(&[BTF_MAGIC]);
+        btf_ext.extend([1, 0]);
+        btf_ext.extend(u32s(&[32, 0, 0, 0, 0, 0, core_relos.len() as u32]));
+        btf_ext.extend(&core_relos);
+        let section = object.add_section(vec![], b".BTF.ext".to_vec(), SectionKind::Other);
+        let _: u64 = object.append_section_data(section, &btf_ext, 4);
+
+        let object = object.write().unwrap();
+        let object = object::File::parse(object.as_slice()).unwrap();
+        let report = relocation_report(&object).unwrap();
+        assert_eq!(
+            report,
+            [SectionRelocations {
+                section: "kprobe".into(),
+                kinds: BTreeMap::from([
+                    (
+                        "CO-RE byte_off".into(),
+                        RelocationKindSummary {
+                            count: 1,
+                            targets: BTreeSet::from(["task_struct:0:1".into()]),
+                        }
+                    ),
+                    (
+                        "R_BPF_64_64".into(),
+                        RelocationKindSummary {
+                            count: 2,
+                            targets: BTreeSet::from(["EVENTS".into()]),
+                        }
+                    ),
+                ]),
+            }]
+        );
+        assert_eq!(
+            report[0].to_string(),
+            "kprobe:\n  CO-RE byte_off: 1 (task_struct:0:1)\n  R_BPF_64_64: 2 (EVENTS)\n"
+        );
+    }
+
+    #[test]
+    fn test_core_relocation_report() {
+        let mut object =
+            write::Object::new(BinaryFormat::Elf, Architecture::Bpf, Endianness::Little);
+
+        // An int and a struct task_struct with a single int member named pid.
+        let strings = b"\0int\0task_struct\0pid\00:0\0kprobe\0";
+        let types = u32s(&[1, 1 << 24, 4, 32, 5, (4 << 24) | 1, 4, 17, 1, 0]);
+        let section = object.add_section(vec![], b".BTF".to_vec(), SectionKind::Other);
+        let _: u64 = object.append_section_data(section, &raw_btf(&types, strings), 4);
+
+        // One byte_off relocation of task_struct with access string "0:0", in kprobe.
+        let core_relos = u32s(&[16, 25, 1, 8, 2, 21, 0]);
+        let mut btf_ext = u16s(&[BTF_MAGIC]);
+        btf_ext.extend([1, 0]);
+        btf_ext.extend(u32s(&[32, 0, 0, 0, 0, 0, core_relos.len() as u32]));
+        btf_ext.extend(&core_relos);
+        let section = object.add_section(vec![], b".BTF.ext".to_vec(), SectionKind::Other);
+        let _: u64 = object.append_section_data(section, &btf_ext, 4);
+        let object = object.write().unwrap();
+        let object = object::File::parse(object.as_slice()).unwrap();
+
+        let relocation = |resolved| CoreRelocation {
+            section: "kprobe".into(),
+            insn_off: 8,
+            kind: "byte_off".into(),
+            type_name: "task_struct".into(),
+            access: "0:0".into(),
+            resolved,
+        };
+        let report = core_relocation_report(&object, None, &[]).unwrap();
+        assert_eq!(report, [relocation(None)]);
+        assert_eq!(
+            report[0].to_string(),
+            "kprobe+0x8: byte_off task_struct:0:0\n"
+        );
+
+        // The target's task_struct names its member pid as well, or tgid.
+        for (member, resolved) in [("pid", true), ("tgid", false)] {
+            let strings = format!("\0int\0task_struct\0{member}\0");
+            let target = raw_btf(&types, strings.as_bytes());
+            let report = core_relocation_report(&object, Some(&target), &[]).unwrap();
+            assert_eq!(report, [relocation(Some(resolved))]);
+        }
+        assert_eq!(
+            relocation(Some(false)).to_json(),
+            r#"{"section":"kprobe","insn_off":8,"kind":"byte_off","type":"task_struct","access":"0:0","resolved":false}"#
+        );
+
+        // The kernel has the int, and modules have a task_struct split on top of it, whose
+        // strings and member type refer to the kernel's.
+        let kernel = raw_btf(&u32s(&[1, 1 << 24, 4, 32]), b"\0int\0");
+        let module = |member: &str| {
+            let strings = format!("task_struct\0{member}\0");
+            raw_btf(&u32s(&[5, (4 << 24) | 1, 4, 17, 1, 0]), strings.as_bytes())
+        };
+        let (pid, tgid) = (module("pid"), module("tgid"));
+        for (modules, resolved) in [
+            (&[tgid.as_slice()][..], false),
+            (&[tgid.as_slice(), pid.as_slice()][..], true),
+        ] {
+            let report = core_relocation_report(&object, Some(&kernel), modules).unwrap();
+            assert_eq!(report, [relocation(Some(resolved))]);
+        }
+        assert!(matches!(
+            core_relocation_report(&object, None, &[pid.as_slice()]),
+            Err(RelocationReportError::MissingBaseBtf)
+        ));
+    }
+}
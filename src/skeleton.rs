@@ -0,0 +1,310 @@
+use std::{collections::BTreeMap, fmt::Write as _};
+
+use object::{Object as _, ObjectSection as _, ObjectSymbol as _, SectionKind, SymbolKind};
+
+use crate::btf::ObjectError;
+
+/// Sections whose global variables are exposed by the skeleton, along with the `.data.*`,
+/// `.rodata.*` and `.bss.*` custom sections.
+const DATA_SECTIONS: &[&str] = &[".data", ".rodata", ".bss", ".kconfig"];
+
+#[derive(Default)]
+struct Symbols<'data> {
+    maps: Vec<&'data str>,
+    programs: Vec<&'data str>,
+    /// Global variables with their size, by data section.
+    vars: BTreeMap<&'data str, Vec<(&'data str, u64)>>,
+}
+
+fn is_data_section(section: &str) -> bool {
+    DATA_SECTIONS.iter().any(|data_section| {
+        section
+            .strip_prefix(data_section)
+            .is_some_and(|suffix| suffix.is_empty() || suffix.starts_with('.'))
+    })
+}
+
+fn symbols<'data>(object: &object::File<'data>) -> Result<Symbols<'data>, object::Error> {
+    let mut symbols = Symbols::default();
+    for symbol in object.symbols() {
+        if !symbol.is_global() || symbol.is_undefined() {
+            continue;
+        }
+        let Some(section_index) = symbol.section_index() else {
+            continue;
+        };
+        let section = object.section_by_index(section_index)?;
+        let section_name = section.name()?;
+        let name = symbol.name()?;
+        match symbol.kind() {
+            // Global functions in .text are subprograms rather than programs.
+            SymbolKind::Text if section.kind() == SectionKind::Text && section_name != ".text" => {
+                symbols.programs.push(name)
+            }
+            SymbolKind::Data if section_name == "maps" || section_name == ".maps" => {
+                symbols.maps.push(name)
+            }
+            SymbolKind::Data if is_data_section(section_name) => symbols
+                .vars
+                .entry(section_name)
+                .or_default()
+                .push((name, symbol.size())),
+            _ => {}
+        }
+    }
+    Ok(symbols)
+}
+
+/// Turns `name` into a C identifier.
+fn sanitize(name: &str) -> String {
+    let ident = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    match ident.trim_start_matches('_').chars().next() {
+        Some(c) if !c.is_ascii_digit() => ident.trim_start_matches('_').to_owned(),
+        _ => format!("_{}", ident.trim_start_matches('_')),
+    }
+}
+
+/// Generates a libbpf subskeleton header for `object`, like `bpftool gen subskeleton` does.
+///
+/// A subskeleton gives access to the maps, programs and global variables of an object that is
+/// linked into a larger object, from the `bpf_object` of the final object. `name` is used to name
+/// the generated struct and functions, eg `<name>__open`.
+///
+/// Variables are declared as pointers to byte arrays of their size, since their types are not
+/// recovered from BTF.
+pub fn subskeleton(name: &str, object: &object::File<'_>) -> Result<String, ObjectError> {
+    let Symbols {
+        maps,
+        programs,
+        vars,
+    } = symbols(object)?;
+    let name = sanitize(name);
+    let guard = name.to_ascii_uppercase();
+    let var_count = vars.values().map(Vec::len).sum::<usize>();
+    // Each data section with variables is backed by an internal map.
+    let map_count = maps.len() + vars.len();
+
+    let mut header = String::new();
+    let h = &mut header;
+    // Writing to a String can't fail.
+    let _: Result<(), _> = (|| -> std::fmt::Result {
+        writeln!(
+            h,
+            "/* SPDX-License-Identifier: (LGPL-2.1 OR BSD-2-Clause) */"
+        )?;
+        writeln!(h)?;
+        writeln!(h, "/* THIS FILE IS AUTOGENERATED BY BPF-LINKER! */")?;
+        writeln!(h, "#ifndef __{guard}_SKEL_H__")?;
+        writeln!(h, "#define __{guard}_SKEL_H__")?;
+        writeln!(h)?;
+        writeln!(h, "#include <errno.h>")?;
+        writeln!(h, "#include <stdlib.h>")?;
+        writeln!(h, "#include <bpf/libbpf.h>")?;
+        writeln!(h)?;
+
+        writeln!(h, "struct {name} {{")?;
+        writeln!(h, "\tstruct bpf_object *obj;")?;
+        writeln!(h, "\tstruct bpf_object_subskeleton *subskel;")?;
+        if map_count > 0 {
+            writeln!(h, "\tstruct {{")?;
+            for map in &maps {
+                writeln!(h, "\t\tstruct bpf_map *{};", sanitize(map))?;
+            }
+            for section in vars.keys() {
+                writeln!(h, "\t\tstruct bpf_map *{};", sanitize(section))?;
+            }
+            writeln!(h, "\t}} maps;")?;
+        }
+        if !programs.is_empty() {
+            writeln!(h, "\tstruct {{")?;
+            for program in &programs {
+                writeln!(h, "\t\tstruct bpf_program *{};", sanitize(program))?;
+            }
+            writeln!(h, "\t}} progs;")?;
+        }
+        for (section, vars) in &vars {
+            let section = sanitize(section);
+            writeln!(h, "\tstruct {name}__{section} {{")?;
+            for (var, size) in vars {
+                writeln!(h, "\t\tunsigned char (*{})[{size}];", sanitize(var))?;
+            }
+            writeln!(h, "\t}} {section};")?;
+        }
+        writeln!(h, "}};")?;
+        writeln!(h)?;
+
+        writeln!(h, "static inline void")?;
+        writeln!(h, "{name}__destroy(struct {name} *skel)")?;
+        writeln!(h, "{{")?;
+        writeln!(h, "\tif (!skel)")?;
+        writeln!(h, "\t\treturn;")?;
+        writeln!(h, "\tif (skel->subskel)")?;
+        writeln!(h, "\t\tbpf_object__destroy_subskeleton(skel->subskel);")?;
+        writeln!(h, "\tfree(skel);")?;
+        writeln!(h, "}}")?;
+        writeln!(h)?;
+
+        writeln!(h, "static inline struct {name} *")?;
+        writeln!(h, "{name}__open(const struct bpf_object *src)")?;
+        writeln!(h, "{{")?;
+        writeln!(h, "\tstruct {name} *obj;")?;
+        writeln!(h, "\tstruct bpf_object_subskeleton *s;")?;
+        writeln!(h, "\tint err;")?;
+        writeln!(h)?;
+        writeln!(h, "\tobj = (struct {name} *)calloc(1, sizeof(*obj));")?;
+        writeln!(h, "\tif (!obj) {{")?;
+        writeln!(h, "\t\terr = -ENOMEM;")?;
+        writeln!(h, "\t\tgoto err;")?;
+        writeln!(h, "\t}}")?;
+        writeln!(
+            h,
+            "\ts = (struct bpf_object_subskeleton *)calloc(1, sizeof(*s));"
+        )?;
+        writeln!(h, "\tif (!s) {{")?;
+        writeln!(h, "\t\terr = -ENOMEM;")?;
+        writeln!(h, "\t\tgoto err;")?;
+        writeln!(h, "\t}}")?;
+        writeln!(h, "\ts->sz = sizeof(*s);")?;
+        writeln!(h, "\ts->obj = src;")?;
+        writeln!(h, "\tobj->obj = (struct bpf_object *)src;")?;
+        writeln!(h, "\tobj->subskel = s;")?;
+
+        let mut array = |kind: &str, ty: &str, count: usize| -> std::fmt::Result {
+            writeln!(h)?;
+            writeln!(h, "\t/* {kind}s */")?;
+            writeln!(h, "\ts->{kind}_cnt = {count};")?;
+            writeln!(h, "\ts->{kind}_skel_sz = sizeof(*s->{kind}s);")?;
+            writeln!(
+                h,
+                "\ts->{kind}s = (struct {ty} *)calloc({count}, sizeof(*s->{kind}s));"
+            )?;
+            writeln!(h, "\tif (!s->{kind}s) {{")?;
+            writeln!(h, "\t\terr = -ENOMEM;")?;
+            writeln!(h, "\t\tgoto err;")?;
+            writeln!(h, "\t}}")
+        };
+        if map_count > 0 {
+            array("map", "bpf_map_skeleton", map_count)?;
+            let names = maps.iter().copied().chain(vars.keys().copied()).enumerate();
+            for (index, map) in names {
+                writeln!(h)?;
+                writeln!(h, "\ts->maps[{index}].name = \"{map}\";")?;
+                writeln!(h, "\ts->maps[{index}].map = &obj->maps.{};", sanitize(map))?;
+            }
+        }
+        if !programs.is_empty() {
+            array("prog", "bpf_prog_skeleton", programs.len())?;
+            for (index, program) in programs.iter().enumerate() {
+                writeln!(h)?;
+                writeln!(h, "\ts->progs[{index}].name = \"{program}\";")?;
+                writeln!(
+                    h,
+                    "\ts->progs[{index}].prog = &obj->progs.{};",
+                    sanitize(program)
+                )?;
+            }
+        }
+        if var_count > 0 {
+            array("var", "bpf_var_skeleton", var_count)?;
+            let vars = vars
+                .iter()
+                .flat_map(|(section, vars)| vars.iter().map(move |(var, _)| (*section, *var)))
+                .enumerate();
+            for (index, (section, var)) in vars {
+                let section = sanitize(section);
+                writeln!(h)?;
+                writeln!(h, "\ts->vars[{index}].name = \"{var}\";")?;
+                writeln!(h, "\ts->vars[{index}].map = &obj->maps.{section};")?;
+                writeln!(
+                    h,
+                    "\ts->vars[{index}].addr = (void **)&obj->{section}.{};",
+                    sanitize(var)
+                )?;
+            }
+        }
+
+        writeln!(h)?;
+        writeln!(h, "\terr = bpf_object__open_subskeleton(s);")?;
+        writeln!(h, "\tif (err)")?;
+        writeln!(h, "\t\tgoto err;")?;
+        writeln!(h)?;
+        writeln!(h, "\treturn obj;")?;
+        writeln!(h, "err:")?;
+        writeln!(h, "\t{name}__destroy(obj);")?;
+        writeln!(h, "\terrno = -err;")?;
+        writeln!(h, "\treturn NULL;")?;
+        writeln!(h, "}}")?;
+        writeln!(h)?;
+        writeln!(h, "#endif /* __{guard}_SKEL_H__ */")
+    })();
+    Ok(header)
+}
+
+#[cfg(test)]
+mod test {
+    use object::{
+        write::{self, Symbol, SymbolSection},
+        Architecture, BinaryFormat, Endianness, SymbolFlags, SymbolScope,
+    };
+
+    use super::*;
+
+    fn add_symbol(
+        object: &mut write::Object<'_>,
+        section: &str,
+        kind: SectionKind,
+        name: &str,
+        symbol_kind: SymbolKind,
+        size: u64,
+    ) {
+        let section = object.add_section(vec![], section.as_bytes().to_vec(), kind);
+        let _: u64 = object.append_section_data(section, &vec![0; size as usize], 8);
+        let _: write::SymbolId = object.add_symbol(Symbol {
+            name: name.as_bytes().to_vec(),
+            value: 0,
+            size,
+            kind: symbol_kind,
+            scope: SymbolScope::Dynamic,
+            weak: false,
+            section: SymbolSection::Section(section),
+            flags: SymbolFlags::None,
+        });
+    }
+
+    #[test]
+    fn test_subskeleton() {
+        let mut object =
+            write::Object::new(BinaryFormat::Elf, Architecture::Bpf, Endianness::Little);
+        for (section, kind, name, symbol_kind, size) in [
+            ("xdp", SectionKind::Text, "xdp_pass", SymbolKind::Text, 16),
+            (".text", SectionKind::Text, "helper", SymbolKind::Text, 16),
+            ("maps", SectionKind::Data, "EVENTS", SymbolKind::Data, 28),
+            (".data", SectionKind::Data, "COUNTER", SymbolKind::Data, 8),
+        ] {
+            add_symbol(&mut object, section, kind, name, symbol_kind, size);
+        }
+        let object = object.write().unwrap();
+        let object = object::File::parse(object.as_slice()).unwrap();
+
+        let header = subskeleton("my-lib", &object).unwrap();
+        assert!(header.contains("struct my_lib {"));
+        assert!(header.contains("\t\tstruct bpf_program *xdp_pass;"));
+        assert!(!header.contains("helper"));
+        assert!(header.contains("\t\tstruct bpf_map *EVENTS;"));
+        assert!(header.contains("\t\tstruct bpf_map *data;"));
+        assert!(header.contains("\t\tunsigned char (*COUNTER)[8];"));
+        assert!(header.contains("\ts->maps[1].name = \".data\";"));
+        assert!(header.contains("\ts->vars[0].addr = (void **)&obj->data.COUNTER;"));
+        assert!(header.contains("my_lib__open(const struct bpf_object *src)"));
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("my-lib"), "my_lib");
+        assert_eq!(sanitize(".data.custom"), "data_custom");
+        assert_eq!(sanitize("1st"), "_1st");
+    }
+}
@@ -0,0 +1,421 @@
+use std::collections::{HashMap, HashSet};
+
+use object::{
+    Endianness, Object as _, ObjectSection as _, ObjectSymbol as _, RelocationTarget, SectionIndex,
+    SectionKind, SymbolIndex, SymbolKind,
+};
+
+use crate::{btf::ObjectError, demangle::demangle};
+
+/// Largest combined stack of the frames of a call chain, `MAX_BPF_STACK` in the kernel.
+pub const MAX_STACK_SIZE: u64 = 512;
+
+/// Largest number of frames in a call chain, `MAX_CALL_FRAMES` in the kernel.
+pub const MAX_CALL_FRAMES: usize = 8;
+
+/// The verifier rounds the stack of each frame up to this many bytes when adding up the frames of
+/// a call chain. Kernels with a JIT may only round up to 16 bytes, so this is an upper bound.
+const FRAME_ALIGNMENT: u64 = 32;
+
+const BPF_INSN_SIZE: usize = 8;
+/// Frame pointer register.
+const BPF_REG_FP: u8 = 10;
+/// Source register of calls to BPF functions, as opposed to helpers.
+const BPF_PSEUDO_CALL: u8 = 1;
+
+/// Instruction opcodes.
+const BPF_LD_IMM64: u8 = 0x18;
+const BPF_ADD64_IMM: u8 = 0x07;
+const BPF_MOV64_REG: u8 = 0xbf;
+const BPF_CALL: u8 = 0x85;
+
+/// Instruction classes.
+const BPF_CLASS_LDX: u8 = 0x01;
+const BPF_CLASS_ST: u8 = 0x02;
+const BPF_CLASS_STX: u8 = 0x03;
+
+/// A frame of a call chain.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackFrame {
+    /// Name of the function.
+    pub function: String,
+    /// Stack used by the function, in bytes.
+    pub size: u64,
+}
+
+/// The worst-case stack usage of a program.
+#[derive(Debug, PartialEq)]
+pub struct ProgramStackUsage {
+    /// Name of the program.
+    pub program: String,
+    /// The call chain using the most stack, starting with the program.
+    pub call_chain: Vec<StackFrame>,
+    /// Combined stack of the frames of `call_chain`, as computed by the verifier.
+    pub stack_size: u64,
+    /// Number of frames of the deepest call chain, which may not be `call_chain`.
+    pub call_frames: usize,
+}
+
+impl ProgramStackUsage {
+    /// Whether the verifier rejects the program for using too much stack or nesting too many
+    /// calls.
+    pub fn exceeds_limits(&self) -> bool {
+        self.stack_size > MAX_STACK_SIZE || self.call_frames > MAX_CALL_FRAMES
+    }
+}
+
+struct Function<'data> {
+    name: &'data str,
+    /// Stack used by the function, in bytes.
+    size: u64,
+    /// Indices of the functions called.
+    callees: Vec<usize>,
+}
+
+/// A decoded instruction.
+struct Insn {
+    code: u8,
+    dst: u8,
+    src: u8,
+    off: i16,
+    imm: i32,
+}
+
+impl Insn {
+    fn parse(bytes: &[u8], endianness: Endianness) -> Self {
+        let regs = bytes[1];
+        let (dst, src) = match endianness {
+            Endianness::Little => (regs & 0xf, regs >> 4),
+            Endianness::Big => (regs >> 4, regs & 0xf),
+        };
+        let off = [bytes[2], bytes[3]];
+        let imm = [bytes[4], bytes[5], bytes[6], bytes[7]];
+        let (off, imm) = match endianness {
+            Endianness::Little => (i16::from_le_bytes(off), i32::from_le_bytes(imm)),
+            Endianness::Big => (i16::from_be_bytes(off), i32::from_be_bytes(imm)),
+        };
+        Self {
+            code: bytes[0],
+            dst,
+            src,
+            off,
+            imm,
+        }
+    }
+}
+
+/// Returns the stack used by the instructions of a function, from the offsets of the accesses
+/// relative to the frame pointer.
+fn frame_size(code: &[u8], endianness: Endianness) -> u64 {
+    let mut size = 0;
+    // Registers holding a copy of the frame pointer.
+    let mut fp_copies = [false; 11];
+    let mut insns = code.chunks_exact(BPF_INSN_SIZE);
+    while let Some(insn) = insns.next() {
+        let Insn {
+            code,
+            dst,
+            src,
+            off,
+            imm,
+        } = Insn::parse(insn, endianness);
+        let (dst, src) = (usize::from(dst), usize::from(src));
+        let fp_offset = match code & 0x07 {
+            BPF_CLASS_LDX if src == usize::from(BPF_REG_FP) => Some(i64::from(off)),
+            BPF_CLASS_ST | BPF_CLASS_STX if dst == usize::from(BPF_REG_FP) => Some(i64::from(off)),
+            _ if code == BPF_ADD64_IMM && fp_copies.get(dst) == Some(&true) => Some(i64::from(imm)),
+            _ => None,
+        };
+        if let Some(offset) = fp_offset.filter(|&offset| offset < 0) {
+            size = size.max(offset.unsigned_abs());
+        }
+        match code {
+            BPF_MOV64_REG if dst < fp_copies.len() => {
+                fp_copies[dst] =
+                    src == usize::from(BPF_REG_FP) || fp_copies.get(src) == Some(&true);
+            }
+            BPF_LD_IMM64 => {
+                // The immediate spans two instructions.
+                let _: Option<&[u8]> = insns.next();
+            }
+            _ if code & 0x07 != BPF_CLASS_ST
+                && code & 0x07 != BPF_CLASS_STX
+                && dst < fp_copies.len() =>
+            {
+                fp_copies[dst] = false
+            }
+            _ => {}
+        }
+    }
+    size
+}
+
+fn functions<'data>(
+    object: &object::File<'data>,
+) -> Result<(Vec<Function<'data>>, Vec<usize>), object::Error> {
+    let endianness = if object.is_little_endian() {
+        Endianness::Little
+    } else {
+        Endianness::Big
+    };
+
+    let mut functions = Vec::new();
+    let mut programs = Vec::new();
+    // Functions by section and offset.
+    let mut offsets = HashMap::<(SectionIndex, u64), usize>::new();
+    for symbol in object.symbols() {
+        if symbol.kind() != SymbolKind::Text || symbol.size() == 0 {
+            continue;
+        }
+        let Some(section_index) = symbol.section_index() else {
+            continue;
+        };
+        let section = object.section_by_index(section_index)?;
+        if section.kind() != SectionKind::Text {
+            continue;
+        }
+        let start = symbol.address() as usize;
+        let Some(code) = section.data()?.get(start..start + symbol.size() as usize) else {
+            continue;
+        };
+
+        // Global functions in .text are subprograms rather than programs.
+        if symbol.is_global() && section.name()? != ".text" {
+            programs.push(functions.len());
+        }
+        let _: Option<usize> = offsets.insert((section_index, symbol.address()), functions.len());
+        functions.push(Function {
+            name: symbol.name()?,
+            size: frame_size(code, endianness),
+            callees: Vec::new(),
+        });
+    }
+
+    let symbol_offsets =
+        |index: SymbolIndex| -> Result<Option<(SectionIndex, u64)>, object::Error> {
+            let symbol = object.symbol_by_index(index)?;
+            Ok(symbol
+                .section_index()
+                .map(|section| (section, symbol.address())))
+        };
+    for section in object.sections() {
+        if section.kind() != SectionKind::Text {
+            continue;
+        }
+        let relocations = section
+            .relocations()
+            .filter_map(|(offset, relocation)| match relocation.target() {
+                RelocationTarget::Symbol(index) => Some((offset, index)),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        let data = section.data()?;
+        let mut caller = None;
+        for (index, insn) in data.chunks_exact(BPF_INSN_SIZE).enumerate() {
+            let offset = (index * BPF_INSN_SIZE) as u64;
+            if let Some(&function) = offsets.get(&(section.index(), offset)) {
+                caller = Some(function);
+            }
+            let Insn { code, src, imm, .. } = Insn::parse(insn, endianness);
+            if code != BPF_CALL || src != BPF_PSEUDO_CALL {
+                continue;
+            }
+            // Like libbpf does, the target is `imm + 1` instructions after the symbol the call
+            // is relocated against, or after the call itself.
+            let (target_section, base) = match relocations.get(&offset) {
+                Some(&symbol) => match symbol_offsets(symbol)? {
+                    Some(target) => target,
+                    None => continue,
+                },
+                None => (section.index(), offset),
+            };
+            let target = base as i64 + (i64::from(imm) + 1) * BPF_INSN_SIZE as i64;
+            let callee = u64::try_from(target)
+                .ok()
+                .and_then(|target| offsets.get(&(target_section, target)));
+            if let (Some(caller), Some(&callee)) = (caller, callee) {
+                functions[caller].callees.push(callee);
+            }
+        }
+    }
+
+    Ok((functions, programs))
+}
+
+/// Worst-case stack of the call chains starting at a function.
+#[derive(Clone, Default)]
+struct ChainUsage {
+    /// The call chain using the most stack.
+    call_chain: Vec<usize>,
+    stack_size: u64,
+    call_frames: usize,
+}
+
+fn chain_usage(
+    functions: &[Function<'_>],
+    function: usize,
+    visiting: &mut HashSet<usize>,
+    cache: &mut HashMap<usize, ChainUsage>,
+) -> ChainUsage {
+    if let Some(usage) = cache.get(&function) {
+        return usage.clone();
+    }
+
+    let mut worst = ChainUsage::default();
+    // The verifier rejects recursion, so recursive calls are simply not followed.
+    let _: bool = visiting.insert(function);
+    for &callee in &functions[function].callees {
+        if visiting.contains(&callee) {
+            continue;
+        }
+        let ChainUsage {
+            call_chain,
+            stack_size,
+            call_frames,
+        } = chain_usage(functions, callee, visiting, cache);
+        if stack_size > worst.stack_size {
+            worst.call_chain = call_chain;
+            worst.stack_size = stack_size;
+        }
+        worst.call_frames = worst.call_frames.max(call_frames);
+    }
+    let _: bool = visiting.remove(&function);
+
+    worst.call_chain.insert(0, function);
+    worst.stack_size += functions[function]
+        .size
+        .max(1)
+        .next_multiple_of(FRAME_ALIGNMENT);
+    worst.call_frames += 1;
+    let _: Option<ChainUsage> = cache.insert(function, worst.clone());
+    worst
+}
+
+/// Computes the worst-case stack usage of the programs of `object`, across BPF to BPF calls.
+///
+/// The stack of each function is inferred from its accesses relative to the frame pointer, and
+/// rounded up like the verifier does when adding up the frames of a call chain. The result is
+/// only meaningful when functions are not all inlined, since otherwise each program is a single
+/// frame.
+pub fn stack_usage(object: &object::File<'_>) -> Result<Vec<ProgramStackUsage>, ObjectError> {
+    let (functions, programs) = functions(object)?;
+
+    let mut cache = HashMap::new();
+    Ok(programs
+        .into_iter()
+        .map(|program| {
+            let ChainUsage {
+                call_chain,
+                stack_size,
+                call_frames,
+            } = chain_usage(&functions, program, &mut HashSet::new(), &mut cache);
+            ProgramStackUsage {
+                program: functions[program].name.to_owned(),
+                call_chain: call_chain
+                    .into_iter()
+                    .map(|function| StackFrame {
+                        function: demangle(functions[function].name),
+                        size: functions[function].size,
+                    })
+                    .collect(),
+                stack_size,
+                call_frames,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use object::{
+        write::{self, Symbol, SymbolSection},
+        Architecture, BinaryFormat, SymbolFlags, SymbolScope,
+    };
+
+    use super::*;
+
+    fn insn(code: u8, dst: u8, src: u8, off: i16, imm: i32) -> [u8; 8] {
+        let mut insn = [0; 8];
+        insn[0] = code;
+        insn[1] = src << 4 | dst;
+        insn[2..4].copy_from_slice(&off.to_le_bytes());
+        insn[4..8].copy_from_slice(&imm.to_le_bytes());
+        insn
+    }
+
+    #[test]
+    fn test_frame_size() {
+        let code = [
+            // *(u64 *)(r10 - 8) = r1
+            insn(0x7b, 10, 1, -8, 0),
+            // r2 = *(u32 *)(r10 - 20)
+            insn(0x61, 2, 10, -20, 0),
+            // r3 = r10; r3 += -48
+            insn(BPF_MOV64_REG, 3, 10, 0, 0),
+            insn(BPF_ADD64_IMM, 3, 0, 0, -48),
+            // r3 += -64, r3 no longer holds the frame pointer
+            insn(BPF_ADD64_IMM, 3, 0, 0, -64),
+            insn(0x95, 0, 0, 0, 0),
+        ]
+        .concat();
+        assert_eq!(frame_size(&code, Endianness::Little), 48);
+    }
+
+    #[test]
+    fn test_stack_usage() {
+        let code = [
+            // xdp_prog: *(u64 *)(r10 - 8) = r1; call sub; exit
+            insn(0x7b, 10, 1, -8, 0),
+            insn(BPF_CALL, 0, BPF_PSEUDO_CALL, 0, 1),
+            insn(0x95, 0, 0, 0, 0),
+            // sub: r1 = r10; r1 += -40; exit
+            insn(BPF_MOV64_REG, 1, 10, 0, 0),
+            insn(BPF_ADD64_IMM, 1, 0, 0, -40),
+            insn(0x95, 0, 0, 0, 0),
+        ]
+        .concat();
+
+        let mut object =
+            write::Object::new(BinaryFormat::Elf, Architecture::Bpf, Endianness::Little);
+        let section = object.add_section(vec![], b"xdp".to_vec(), SectionKind::Text);
+        let _: u64 = object.append_section_data(section, &code, 8);
+        for (name, value, scope) in [
+            ("xdp_prog", 0, SymbolScope::Dynamic),
+            ("sub", 24, SymbolScope::Compilation),
+        ] {
+            let _: write::SymbolId = object.add_symbol(Symbol {
+                name: name.as_bytes().to_vec(),
+                value,
+                size: 24,
+                kind: SymbolKind::Text,
+                scope,
+                weak: false,
+                section: SymbolSection::Section(section),
+                flags: SymbolFlags::None,
+            });
+        }
+        let object = object.write().unwrap();
+        let object = object::File::parse(object.as_slice()).unwrap();
+
+        let usage = stack_usage(&object).unwrap();
+        assert_eq!(
+            usage,
+            [ProgramStackUsage {
+                program: "xdp_prog".into(),
+                call_chain: vec![
+                    StackFrame {
+                        function: "xdp_prog".into(),
+                        size: 8,
+                    },
+                    StackFrame {
+                        function: "sub".into(),
+                        size: 40,
+                    },
+                ],
+                stack_size: 96,
+                call_frames: 2,
+            }]
+        );
+        assert!(!usage[0].exceeds_limits());
+    }
+}
@@ -0,0 +1,128 @@
+use object::{
+    elf::{FileHeader64, SHT_NOBITS, SHT_REL, SHT_RELA},
+    pod,
+    read::elf::{FileHeader as _, SectionHeader as _},
+    Endianness,
+};
+use thiserror::Error;
+
+/// Errors returned by [`strip_debug`].
+#[derive(Debug, Error)]
+pub enum StripDebugError {
+    /// The object file couldn't be parsed.
+    #[error("failed to parse object: {0}")]
+    ParseError(#[from] object::Error),
+
+    /// The object file has program headers, which the sections can't be moved under.
+    #[error("objects with program headers aren't supported")]
+    ProgramHeaders,
+}
+
+/// Returns a copy of `object` without the contents of its DWARF sections, `.debug_*`, and of
+/// their relocations. `.BTF` and `.BTF.ext` don't refer to the DWARF sections, so they are kept as
+/// they are.
+///
+/// The headers of the stripped sections are kept, with an empty size, so that the indices of the
+/// other sections, which symbols and relocations refer to, don't change.
+pub fn strip_debug(object: &[u8]) -> Result<Vec<u8>, StripDebugError> {
+    let header = FileHeader64::<Endianness>::parse(object)?;
+    let endian = header.endian()?;
+    if header.e_phnum.get(endian) != 0 {
+        return Err(StripDebugError::ProgramHeaders);
+    }
+    let sections = header.sections(endian, object)?;
+    let debug = sections
+        .iter()
+        .map(|section| {
+            let name = sections.section_name(endian, section)?;
+            Ok(name.starts_with(b".debug_"))
+        })
+        .collect::<Result<Vec<_>, object::Error>>()?;
+
+    let header_size = size_of::<FileHeader64<Endianness>>();
+    let mut stripped = object[..header_size].to_vec();
+    let mut section_headers = Vec::with_capacity(debug.len());
+    for (index, section) in sections.iter().enumerate() {
+        let mut section = *section;
+        let is_debug = debug[index]
+            || (matches!(section.sh_type(endian), SHT_REL | SHT_RELA)
+                && debug
+                    .get(section.sh_info(endian) as usize)
+                    .copied()
+                    .unwrap_or_default());
+        if is_debug {
+            section.sh_offset.set(endian, stripped.len() as u64);
+            section.sh_size.set(endian, 0);
+        } else if index != 0 && section.sh_type(endian) != SHT_NOBITS {
+            let data = section.data(endian, object)?;
+            let align = section.sh_addralign(endian).max(1) as usize;
+            stripped.resize(stripped.len().next_multiple_of(align), 0);
+            section.sh_offset.set(endian, stripped.len() as u64);
+            stripped.extend_from_slice(data);
+        }
+        section_headers.push(section);
+    }
+
+    stripped.resize(stripped.len().next_multiple_of(8), 0);
+    let mut header = *header;
+    header.e_shoff.set(endian, stripped.len() as u64);
+    stripped[..header_size].copy_from_slice(pod::bytes_of(&header));
+    for section in &section_headers {
+        stripped.extend_from_slice(pod::bytes_of(section));
+    }
+    Ok(stripped)
+}
+
+#[cfg(test)]
+mod test {
+    use object::{
+        write, Architecture, BinaryFormat, Object as _, ObjectSection as _, RelocationFlags,
+        SectionKind,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_strip_debug() {
+        let mut object =
+            write::Object::new(BinaryFormat::Elf, Architecture::Bpf, Endianness::Little);
+        let text = object.add_section(vec![], b"xdp".to_vec(), SectionKind::Text);
+        let _: u64 = object.append_section_data(text, &[0x95, 0, 0, 0, 0, 0, 0, 0], 8);
+        let debug_str = object.add_section(vec![], b".debug_str".to_vec(), SectionKind::Debug);
+        let _: u64 = object.append_section_data(debug_str, &[b'a'; 100], 1);
+        let debug_info = object.add_section(vec![], b".debug_info".to_vec(), SectionKind::Debug);
+        let _: u64 = object.append_section_data(debug_info, &[0; 100], 1);
+        let symbol = object.section_symbol(debug_str);
+        object
+            .add_relocation(
+                debug_info,
+                write::Relocation {
+                    offset: 0,
+                    symbol,
+                    addend: 0,
+                    flags: RelocationFlags::Elf {
+                        r_type: object::elf::R_BPF_64_ABS32,
+                    },
+                },
+            )
+            .unwrap();
+        let btf = object.add_section(vec![], b".BTF".to_vec(), SectionKind::Other);
+        let _: u64 = object.append_section_data(btf, &[1, 2, 3, 4], 4);
+        let object = object.write().unwrap();
+
+        let stripped = strip_debug(&object).unwrap();
+        assert!(stripped.len() < object.len());
+
+        let file = object::File::parse(stripped.as_slice()).unwrap();
+        let data = |name: &str| file.section_by_name(name).unwrap().data().unwrap();
+        assert_eq!(data("xdp"), [0x95, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(data(".BTF"), [1, 2, 3, 4]);
+        let debug = file
+            .sections()
+            .filter(|section| section.name().unwrap().contains(".debug_"))
+            .map(|section| (section.name().unwrap().to_owned(), section.size()))
+            .collect::<Vec<_>>();
+        assert_eq!(debug.len(), 3, "{debug:?}");
+        assert!(debug.iter().all(|(_, size)| *size == 0), "{debug:?}");
+    }
+}
@@ -0,0 +1,8 @@
+// no-prefer-dynamic
+// compile-flags: --crate-type rlib
+#![no_std]
+
+#[inline(never)]
+pub fn double(value: u64) -> u64 {
+    value.wrapping_mul(2)
+}
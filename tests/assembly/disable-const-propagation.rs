@@ -0,0 +1,22 @@
+// assembly-output: bpf-linker
+// compile-flags: --crate-type cdylib -C link-args=--disable-const-propagation
+#![no_std]
+
+// Loaders can patch global variables before loading the programs, so the linker provides the
+// --disable-const-propagation option to keep the code reading them instead of their initial
+// value.
+
+// aux-build: loop-panic-handler.rs
+extern crate loop_panic_handler;
+
+#[no_mangle]
+static PORT: u16 = 8080;
+
+#[no_mangle]
+#[link_section = "uprobe/port"]
+pub extern "C" fn port() -> u16 {
+    // CHECK-LABEL: port:
+    unsafe { core::ptr::addr_of!(PORT).read() }
+    // CHECK: r{{[0-9]}} = PORT ll
+    // CHECK-NOT: 8080
+}
@@ -0,0 +1,28 @@
+// assembly-output: bpf-linker
+// compile-flags: --crate-type cdylib -C link-args=--export=*exported_helper*
+#![no_std]
+
+// Export symbols containing `*` are patterns, which export all the symbols they match, including
+// mangled ones.
+
+// aux-build: loop-panic-handler.rs
+extern crate loop_panic_handler;
+
+#[inline(never)]
+pub fn exported_helper(value: u64) -> u64 {
+    value.wrapping_mul(3)
+}
+
+#[inline(never)]
+pub fn internal_helper(value: u64) -> u64 {
+    value.wrapping_mul(5)
+}
+
+#[no_mangle]
+#[link_section = "uprobe/connect"]
+pub extern "C" fn connect(value: u64) -> u64 {
+    exported_helper(value) + internal_helper(value)
+}
+
+// CHECK: .globl {{.*}}exported_helper
+// CHECK-NOT: .globl {{.*}}internal_helper
@@ -0,0 +1,37 @@
+// assembly-output: bpf-linker
+// compile-flags: --crate-type cdylib -C link-args=--gc-maps
+#![no_std]
+
+// Maps are kept global so that loaders find them, even when no program uses them. The linker
+// provides the --gc-maps option to remove them.
+
+// aux-build: loop-panic-handler.rs
+extern crate loop_panic_handler;
+
+#[link_section = "maps"]
+static mut USED: [u32; 5] = [0; 5];
+
+#[link_section = "maps"]
+static mut UNUSED: [u32; 5] = [0; 5];
+
+// The reference to UNUSED is only removed by the optimizations, which propagate `used` into the
+// function.
+#[inline(never)]
+fn pick(used: bool) -> *const u32 {
+    if used {
+        unsafe { core::ptr::addr_of!(USED).cast() }
+    } else {
+        unsafe { core::ptr::addr_of!(UNUSED).cast() }
+    }
+}
+
+#[no_mangle]
+#[link_section = "xdp"]
+pub fn xdp_events() -> *const u32 {
+    pick(true)
+}
+
+// The mangled names of the maps are prefixed by their length.
+// CHECK-NOT: UNUSED
+// CHECK: .globl {{.*}}4USED
+// CHECK-NOT: UNUSED
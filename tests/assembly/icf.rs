@@ -0,0 +1,38 @@
+// assembly-output: bpf-linker
+// revisions: icf default
+// [icf]compile-flags: --crate-type cdylib -C link-args=--icf
+// [default]compile-flags: --crate-type cdylib
+#![no_std]
+
+// Functions of different crates often compile to the same code. rustc only merges identical
+// functions within a crate, and the linker provides the --icf option to merge them across crates.
+
+// aux-build: loop-panic-handler.rs
+extern crate loop_panic_handler;
+
+// aux-build: icf-dep.rs
+extern crate icf_dep as dep;
+
+#[inline(never)]
+fn double(value: u64) -> u64 {
+    value.wrapping_mul(2)
+}
+
+#[no_mangle]
+#[link_section = "uprobe/a"]
+pub extern "C" fn a(value: u64) -> u64 {
+    // CHECK-LABEL: a:
+    // CHECK,icf: call [[DOUBLE:.*]]
+    // CHECK,default: call [[DOUBLE_A:.*]]
+    dep::double(value)
+}
+
+#[no_mangle]
+#[link_section = "uprobe/b"]
+pub extern "C" fn b(value: u64) -> u64 {
+    // CHECK-LABEL: b:
+    // CHECK,icf: call [[DOUBLE]]
+    // CHECK,default-NOT: call [[DOUBLE_A]]
+    // CHECK,default: call
+    double(value)
+}
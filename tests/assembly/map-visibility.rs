@@ -0,0 +1,20 @@
+// assembly-output: bpf-linker
+// compile-flags: --crate-type cdylib
+
+// Loaders find maps through their global symbols. Verify that maps which aren't #[no_mangle], and
+// are therefore internal to the crate, are still emitted as global symbols.
+#![no_std]
+
+// aux-build: loop-panic-handler.rs
+extern crate loop_panic_handler;
+
+#[link_section = "maps"]
+static mut EVENTS: [u32; 5] = [0; 5];
+
+#[no_mangle]
+#[link_section = "xdp"]
+pub fn xdp_events() -> *const u32 {
+    unsafe { core::ptr::addr_of!(EVENTS).cast() }
+}
+
+// CHECK: .globl {{.*}}EVENTS
@@ -0,0 +1,27 @@
+// assembly-output: bpf-linker
+// revisions: os oz
+// [os]compile-flags: --crate-type cdylib -C link-arg=-Os
+// [oz]compile-flags: --crate-type cdylib -C link-arg=-Oz
+#![no_std]
+
+// The Oz pipeline of LLVM leaves loops unrotated, with a conditional branch out of the loop at
+// its top and an unconditional one back to it at its bottom, for the verifier to explore on every
+// iteration. The linker keeps the rotated loops of -Os at -Oz, with a single conditional branch
+// back to the top of the loop.
+
+// aux-build: loop-panic-handler.rs
+extern crate loop_panic_handler;
+
+#[no_mangle]
+#[link_section = "uprobe/hash"]
+pub extern "C" fn hash(len: u64) -> u64 {
+    // CHECK-LABEL: hash:
+    let mut hash = 0u64;
+    for i in 0..len {
+        hash = hash.wrapping_mul(31).wrapping_add(i);
+    }
+    hash
+    // CHECK: if r{{[0-9]}} {{.*}} goto LBB
+    // CHECK-NOT: {{^[[:space:]]*}}goto LBB
+    // CHECK: exit
+}
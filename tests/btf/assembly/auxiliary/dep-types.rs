@@ -0,0 +1,11 @@
+// no-prefer-dynamic
+// compile-flags: --crate-type rlib -C debuginfo=2
+#![no_std]
+
+pub struct Shared {
+    pub ayy: u32,
+    pub lmao: u64,
+}
+
+#[no_mangle]
+pub static DEP_SHARED: Shared = Shared { ayy: 1, lmao: 2 };
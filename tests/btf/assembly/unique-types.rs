@@ -0,0 +1,18 @@
+// assembly-output: bpf-linker
+// compile-flags: --crate-type cdylib -C link-arg=--emit=obj -C link-arg=--btf -C debuginfo=2
+#![no_std]
+
+// Each crate brings its own debug info for the types it uses. The linker keeps a single BTF type
+// for each of them.
+
+// aux-build: loop-panic-handler.rs
+extern crate loop_panic_handler;
+
+// aux-build: dep-types.rs
+extern crate dep_types as dep;
+
+#[no_mangle]
+pub static LOCAL_SHARED: dep::Shared = dep::Shared { ayy: 3, lmao: 4 };
+
+// CHECK: <STRUCT> 'Shared' sz:16 n:2
+// CHECK-NOT: <STRUCT> 'Shared'
@@ -5,7 +5,7 @@ use std::{
     ffi::{OsStr, OsString},
     fs,
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, Output},
 };
 
 fn rustc_cmd() -> Command {
@@ -98,6 +98,42 @@ where
     }
 }
 
+/// Writes each of the C `sources` to `<name>.c` in `dir` and compiles it into LLVM bitcode, at the
+/// returned paths.
+fn build_c_bitcode(dir: &Path, sources: &[(&str, &str)]) -> Vec<PathBuf> {
+    fs::create_dir_all(dir).expect("failed to create a build directory for bitcode");
+    sources
+        .iter()
+        .map(|(name, source)| {
+            let src = dir.join(format!("{name}.c"));
+            fs::write(&src, source).expect("failed to write the source");
+            let dst = src.with_extension("bc");
+            clang_build(&src, &dst);
+            dst
+        })
+        .collect()
+}
+
+fn bpf_linker<I, S>(args: I) -> Output
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    Command::new(env!("CARGO_BIN_EXE_bpf-linker"))
+        .args(args)
+        .output()
+        .expect("failed to run bpf-linker")
+}
+
+/// Returns the assembly of `function` in `asm`, from its label to its end.
+fn function_asm<'a>(asm: &'a str, function: &str) -> &'a str {
+    let start = asm
+        .find(&format!("\n{function}:"))
+        .unwrap_or_else(|| panic!("no function `{function}` in:\n{asm}"));
+    let asm = &asm[start..];
+    &asm[..asm.find("\n.Lfunc_end").unwrap_or(asm.len())]
+}
+
 fn is_nightly() -> bool {
     let output = rustc_cmd()
         .arg("--version")
@@ -188,3 +224,162 @@ fn compile_test() {
         );
     }
 }
+
+#[test]
+fn undefined_symbols() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("undefined-symbols");
+    let inputs = build_c_bitcode(
+        &dir,
+        &[
+            (
+                "prog",
+                "int helpr(void);\n\
+                 __attribute__((section(\"xdp\"))) int prog(void *ctx) { return helpr(); }\n",
+            ),
+            ("helper", "int helper(void) { return 1; }\n"),
+        ],
+    );
+    let link = |allow_undefined: Option<&str>| {
+        let mut args = vec![
+            OsString::from("--export=prog"),
+            "--diagnostics-format=json".into(),
+            "-o".into(),
+            dir.join("prog.o").into(),
+        ];
+        args.extend(allow_undefined.map(OsString::from));
+        args.extend(inputs.iter().map(OsString::from));
+        bpf_linker(args)
+    };
+
+    // Undefined symbols are denied by default, with the defined symbol closest to their name.
+    let output = link(None);
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let message = format!(
+        "undefined symbol `helpr`, referenced by `prog`; did you mean `helper` from `{}`?",
+        inputs[1].display()
+    );
+    assert!(stderr.contains(&message), "{stderr}");
+    assert!(stderr.contains(r#""symbol":"helpr""#), "{stderr}");
+
+    let output = link(Some("--allow-undefined"));
+    assert!(output.status.success(), "{output:?}");
+    let output = link(Some("--allow-undefined=helpr"));
+    assert!(output.status.success(), "{output:?}");
+    let output = link(Some("--allow-undefined=helper"));
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(r#""symbol":"helpr""#), "{stderr}");
+}
+
+#[test]
+fn duplicate_symbols() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("duplicate-symbols");
+    // Both inputs define `value`, called by their program. The values are distinct constants to
+    // tell the definitions apart in the assembly.
+    let inputs = build_c_bitcode(
+        &dir,
+        &[
+            (
+                "a",
+                "int value(void) { return 4369; }\n\
+                 __attribute__((section(\"xdp/a\"))) int prog_a(void *ctx) { return value(); }\n",
+            ),
+            (
+                "b",
+                "int value(void) { return 8738; }\n\
+                 __attribute__((section(\"xdp/b\"))) int prog_b(void *ctx) { return value(); }\n",
+            ),
+        ],
+    );
+    let link = |policy: &str| {
+        let output = dir.join(format!("{policy}.s"));
+        let mut args = vec![
+            OsString::from("--export=prog_a"),
+            "--export=prog_b".into(),
+            "--emit=asm".into(),
+            format!("--duplicate-symbols={policy}").into(),
+            "-o".into(),
+            output.clone().into(),
+        ];
+        args.extend(inputs.iter().map(OsString::from));
+        (bpf_linker(args), output)
+    };
+    // Returns the value returned by the definition of `value` that `program` calls.
+    let called_value = |asm: &str, program: &str| {
+        let call = function_asm(asm, program)
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("call "))
+            .unwrap_or_else(|| panic!("`{program}` calls no function in:\n{asm}"));
+        let value = function_asm(asm, call);
+        if value.contains("4369") {
+            4369
+        } else if value.contains("8738") {
+            8738
+        } else {
+            panic!("`{call}` returns neither value in:\n{asm}")
+        }
+    };
+
+    let (output, _) = link("error");
+    assert!(!output.status.success(), "{output:?}");
+
+    for (policy, expected) in [
+        ("first-wins", (4369, 4369)),
+        ("last-wins", (8738, 8738)),
+        ("rename-second", (4369, 8738)),
+    ] {
+        let (output, path) = link(policy);
+        assert!(output.status.success(), "{policy}: {output:?}");
+        let asm = fs::read_to_string(path).expect("failed to read the assembly");
+        assert_eq!(
+            (called_value(&asm, "prog_a"), called_value(&asm, "prog_b")),
+            expected,
+            "{policy}"
+        );
+    }
+}
+
+#[test]
+fn jobs() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("jobs");
+    // The programs of all the inputs call the helper defined by the first one.
+    let sources = (0..4)
+        .map(|index| {
+            let helper = match index {
+                0 => "int helper(int value) { return value * 2; }\n",
+                _ => "int helper(int value);\n",
+            };
+            (
+                format!("prog_{index}"),
+                format!(
+                    "{helper}__attribute__((section(\"xdp/{index}\"))) \
+                     int prog_{index}(void *ctx) {{ return helper({index}); }}\n"
+                ),
+            )
+        })
+        .collect::<Vec<_>>();
+    let sources = sources
+        .iter()
+        .map(|(name, source)| (name.as_str(), source.as_str()))
+        .collect::<Vec<_>>();
+    let inputs = build_c_bitcode(&dir, &sources);
+    let link = |jobs: usize| {
+        let output = dir.join(format!("jobs-{jobs}.o"));
+        let mut args = vec![
+            OsString::from("--export=prog_*"),
+            format!("--jobs={jobs}").into(),
+            "-o".into(),
+            output.clone().into(),
+        ];
+        args.extend(inputs.iter().map(OsString::from));
+        let result = bpf_linker(args);
+        assert!(result.status.success(), "--jobs={jobs}: {result:?}");
+        fs::read(output).expect("failed to read the object")
+    };
+
+    // The inputs are linked by threads in chunks, which must link to the object the inputs
+    // linked one by one do.
+    assert_eq!(link(1), link(2));
+    assert_eq!(link(1), link(4));
+}